@@ -0,0 +1,53 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Feed arbitrary blobs to the EDID parser, which must never panic
+//!
+//! ```sh
+//! cargo +nightly fuzz run edid
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rres::edid;
+
+fuzz_target!(|data: &[u8]| {
+    edid::is_valid(data);
+    edid::physical_size(data);
+    for (_, payload) in edid::cta_data_blocks(data) {
+        // Payloads are slices of the input
+        assert!(payload.len() < data.len());
+    }
+    edid::has_hdr(data);
+    edid::tile(data);
+    // Must keep a valid EDID valid
+    let mut redacted = data.to_vec();
+    edid::redact(&mut redacted);
+    assert_eq!(edid::is_valid(&redacted), edid::is_valid(data));
+    edid::has_allm(data);
+    if let Some(chromaticity) = edid::chromaticity(data) {
+        // Arbitrary coordinates, including degenerate triangles
+        chromaticity.gamut_area();
+        chromaticity.srgb_coverage();
+        chromaticity.is_wide_gamut();
+    }
+    edid::colorimetry(data);
+    edid::bits_per_component(data);
+    edid::refresh_range(data);
+    edid::hdr_metadata(data);
+});
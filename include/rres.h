@@ -0,0 +1,80 @@
+#ifndef RRES_H
+#define RRES_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// A display mode
+typedef struct RresMode {
+  uint16_t width;
+  uint16_t height;
+  // Refresh rate in Hz, `0` if unknown
+  double refresh;
+} RresMode;
+
+// A connected display
+typedef struct RresDisplay {
+  // Card the display is connected to (eg. `/dev/dri/card0`)
+  char *card;
+  // Connector name (eg. `DP-1`)
+  char *connector;
+  // Current mode
+  struct RresMode mode;
+  // Whether the display is built into the device (laptop or handheld panel)
+  bool internal;
+} RresDisplay;
+
+// A list of displays, freed with [`rres_free_displays`]
+typedef struct RresDisplayList {
+  struct RresDisplay *displays;
+  uintptr_t len;
+} RresDisplayList;
+
+// Get the message of the last error of this thread, null if none
+//
+// The string stays valid until the next failing call on this thread.
+const char *rres_last_error(void);
+
+// Get the resolution of the first display
+//
+// `card` selects a card (eg. `card0`), every card is probed if null.
+//
+// # Safety
+//
+// `card` must be null or a valid string, `width` and `height` must be valid pointers.
+int rres_get_resolution(const char *card, uint16_t *width, uint16_t *height);
+
+// List every connected display
+//
+// `card` selects a card (eg. `card0`), every card is probed if null. The list must be freed
+// with [`rres_free_displays`].
+//
+// # Safety
+//
+// `card` must be null or a valid string, `list` must be a valid pointer.
+int rres_list_displays(const char *card, struct RresDisplayList *list);
+
+// Free a list of displays returned by [`rres_list_displays`]
+//
+// # Safety
+//
+// `list` must be null or a list filled by [`rres_list_displays`], not freed yet.
+void rres_free_displays(struct RresDisplayList *list);
+
+// Get the render resolution of a gamescope mode (`native` or a FSR preset: `ultra`,
+// `quality`, `balanced`, `performance`) for a display resolution, null meaning `native`
+//
+// # Safety
+//
+// `fsr_mode` must be null or a valid string, `render_width` and `render_height` must be valid
+// pointers.
+int rres_render_resolution(uint16_t width,
+                           uint16_t height,
+                           const char *fsr_mode,
+                           uint16_t *render_width,
+                           uint16_t *render_height);
+
+#endif  /* RRES_H */
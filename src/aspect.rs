@@ -0,0 +1,259 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Aspect ratios of resolutions
+//!
+//! Resolutions are often a few pixels off their nominal ratio (1366x768 is 683:384, 3440x1440
+//! is 43:18): [`nominal`] gives the name they are sold under (16:9, 21:9), [`reduce`] the
+//! exact ratio.
+
+use std::fmt;
+
+/// An aspect ratio, width:height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ratio(pub u16, pub u16);
+
+impl Ratio {
+    /// Width divided by height
+    pub fn value(&self) -> f64 {
+        f64::from(self.0) / f64::from(self.1)
+    }
+}
+
+impl TryFrom<&str> for Ratio {
+    type Error = anyhow::Error;
+
+    /// Parse a ratio, eg. `16:9`
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let Some((width, height)) = value.split_once(':') else {
+            return Err(anyhow::anyhow!("expected width:height (eg. 16:9)"));
+        };
+        let ratio = Ratio(width.trim().parse()?, height.trim().parse()?);
+        if ratio.0 == 0 || ratio.1 == 0 {
+            return Err(anyhow::anyhow!("the ratio can't be 0"));
+        }
+        Ok(ratio)
+    }
+}
+
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.0, self.1)
+    }
+}
+
+/// Nominal aspect ratios of displays
+const NOMINAL: [Ratio; 8] = [
+    Ratio(5, 4),
+    Ratio(4, 3),
+    Ratio(3, 2),
+    Ratio(16, 10),
+    Ratio(16, 9),
+    Ratio(21, 9),
+    Ratio(32, 10),
+    Ratio(32, 9),
+];
+/// Relative difference up to which a resolution has a nominal ratio
+const TOLERANCE: f64 = 0.03;
+/// Ratio from which a display is ultrawide (wider than 16:9 and 16:10)
+const ULTRAWIDE: f64 = 2.0;
+/// Resolutions every game lists, 16:9 and 16:10 from 720p to 2160p
+const STANDARD: [(u16, u16); 7] = [
+    (1280, 720),
+    (1280, 800),
+    (1920, 1080),
+    (1920, 1200),
+    (2560, 1440),
+    (2560, 1600),
+    (3840, 2160),
+];
+
+/// Greatest common divisor
+pub fn gcd(a: u16, b: u16) -> u16 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Get the exact aspect ratio of a resolution (eg. 1366x768 -> 683:384)
+pub fn reduce(res: (u16, u16)) -> Ratio {
+    let divisor = gcd(res.0, res.1).max(1);
+    Ratio(res.0 / divisor, res.1 / divisor)
+}
+
+/// Get the nominal aspect ratio of a resolution (eg. 3440x1440 -> 21:9)
+///
+/// The closest of the common ratios within 3%, or the exact ratio.
+pub fn nominal(res: (u16, u16)) -> Ratio {
+    let exact = reduce(res);
+    if res.1 == 0 {
+        return exact;
+    }
+    let value = exact.value();
+    NOMINAL
+        .into_iter()
+        .map(|ratio| (ratio, (ratio.value() - value).abs() / ratio.value()))
+        .filter(|(_, difference)| *difference <= TOLERANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(exact, |(ratio, _)| ratio)
+}
+
+/// Get the largest region of a ratio within a resolution, eg. 16:9 in 3440x1440 -> 2560x1440
+///
+/// The region is centered, with black bars on the sides (pillarbox) or above and below
+/// (letterbox).
+pub fn fit(res: (u16, u16), ratio: Ratio) -> (u16, u16) {
+    let (width, height) = (u32::from(res.0), u32::from(res.1));
+    let (ratio_width, ratio_height) = (u32::from(ratio.0), u32::from(ratio.1));
+    if ratio_width == 0 || ratio_height == 0 {
+        return res;
+    }
+    // Full height if narrow enough, full width otherwise; the results fit in u16. Extreme
+    // ratios still get a pixel.
+    if height * ratio_width / ratio_height <= width {
+        ((height * ratio_width / ratio_height).max(1) as u16, res.1)
+    } else {
+        (res.0, (width * ratio_height / ratio_width).max(1) as u16)
+    }
+}
+
+/// Scale a resolution down to fit within bounds, keeping its aspect ratio
+///
+/// eg. 3840x2160 within 1920x1080 -> 1920x1080, 3440x1440 -> 1920x803. Resolutions already
+/// within the bounds are kept.
+pub fn clamp(res: (u16, u16), bounds: (u16, u16)) -> (u16, u16) {
+    if res.0 <= bounds.0 && res.1 <= bounds.1 {
+        return res;
+    }
+    let (width, height) = (u32::from(res.0), u32::from(res.1));
+    let (max_width, max_height) = (u32::from(bounds.0), u32::from(bounds.1));
+    // Limited by the width if the resolution is wider than the bounds
+    // Tiny bounds still get a pixel
+    if width * max_height >= height * max_width {
+        (bounds.0, (height * max_width / width).max(1) as u16)
+    } else {
+        ((width * max_height / height).max(1) as u16, bounds.1)
+    }
+}
+
+/// Get the standard resolution closest to a resolution, for games with fixed resolution lists
+///
+/// eg. 3072x1280 -> 1920x1200, 2880x1800 -> 2560x1600. Only resolutions fitting within `res`
+/// are considered, so that games never render above it; `res` is kept when none fits.
+/// Resolutions of the same nominal ratio are preferred. Widths and heights count
+/// relatively: twice as large is as far as half as large.
+pub fn snap(res: (u16, u16)) -> (u16, u16) {
+    if res.0 == 0 || res.1 == 0 {
+        return res;
+    }
+    let distance = |standard: &(u16, u16)| {
+        (f64::from(standard.0) / f64::from(res.0)).ln().abs()
+            + (f64::from(standard.1) / f64::from(res.1)).ln().abs()
+    };
+    let fitting: Vec<_> = STANDARD
+        .into_iter()
+        .filter(|standard| standard.0 <= res.0 && standard.1 <= res.1)
+        .collect();
+    let ratio = nominal(res);
+    let same_ratio: Vec<_> = fitting
+        .iter()
+        .copied()
+        .filter(|&standard| nominal(standard) == ratio)
+        .collect();
+    let candidates = if same_ratio.is_empty() {
+        fitting
+    } else {
+        same_ratio
+    };
+    candidates
+        .into_iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(res)
+}
+
+/// Whether a resolution is ultrawide (21:9, 32:9...)
+pub fn is_ultrawide(res: (u16, u16)) -> bool {
+    res.1 > 0 && reduce(res).value() >= ULTRAWIDE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit() {
+        // Pillarbox
+        assert_eq!(super::fit((3440, 1440), Ratio(16, 9)), (2560, 1440));
+        assert_eq!(super::fit((5120, 1440), Ratio(16, 9)), (2560, 1440));
+        // Letterbox
+        assert_eq!(super::fit((1280, 1024), Ratio(16, 9)), (1280, 720));
+        assert_eq!(super::fit((2560, 1600), Ratio(16, 9)), (2560, 1440));
+        // Already at the ratio
+        assert_eq!(super::fit((1920, 1080), Ratio(16, 9)), (1920, 1080));
+        assert_eq!(super::fit((1366, 768), Ratio(16, 9)), (1365, 768));
+        assert_eq!(super::fit((1920, 1080), Ratio(10000, 1)), (1920, 1));
+    }
+
+    #[test]
+    fn clamp() {
+        assert_eq!(super::clamp((3840, 2160), (1920, 1080)), (1920, 1080));
+        assert_eq!(super::clamp((3440, 1440), (1920, 1080)), (1920, 803));
+        assert_eq!(super::clamp((2560, 1600), (1920, 1080)), (1728, 1080));
+        assert_eq!(super::clamp((1280, 800), (1920, 1080)), (1280, 800));
+        assert_eq!(super::clamp((3440, 1440), (1, 1)), (1, 1));
+    }
+
+    #[test]
+    fn snap() {
+        assert_eq!(super::snap((2880, 1800)), (2560, 1600));
+        assert_eq!(super::snap((1366, 768)), (1280, 720));
+        assert_eq!(super::snap((3440, 1440)), (2560, 1440));
+        assert_eq!(super::snap((1920, 1080)), (1920, 1080));
+        assert_eq!(super::snap((5120, 2880)), (3840, 2160));
+        // Never above the resolution
+        assert_eq!(super::snap((3072, 1280)), (1920, 1200));
+        assert_eq!(super::snap((3200, 1800)), (2560, 1440));
+        assert_eq!(super::snap((2400, 1080)), (1920, 1080));
+        // Nothing fits
+        assert_eq!(super::snap((1024, 600)), (1024, 600));
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(reduce((1920, 1080)), Ratio(16, 9));
+        assert_eq!(reduce((1366, 768)), Ratio(683, 384));
+        assert_eq!(reduce((1280, 800)).to_string(), "8:5");
+
+        assert_eq!(nominal((1366, 768)), Ratio(16, 9));
+        assert_eq!(nominal((1280, 800)).to_string(), "16:10");
+        assert_eq!(nominal((3440, 1440)).to_string(), "21:9");
+        assert_eq!(nominal((2560, 1080)).to_string(), "21:9");
+        assert_eq!(nominal((5120, 1440)).to_string(), "32:9");
+        assert_eq!(nominal((1280, 1024)).to_string(), "5:4");
+        assert_eq!(nominal((4096, 2160)).to_string(), "256:135");
+        assert_eq!(nominal((0, 0)), Ratio(0, 0));
+
+        assert_eq!(Ratio::try_from("16:9").unwrap(), Ratio(16, 9));
+        assert!(Ratio::try_from("16x9").is_err());
+        assert!(Ratio::try_from("16:0").is_err());
+
+        assert!(is_ultrawide((3440, 1440)));
+        assert!(!is_ultrawide((2560, 1600)));
+    }
+}
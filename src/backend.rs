@@ -0,0 +1,286 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Display detection backends
+//!
+//! Each platform lists its displays differently: DRM on Linux and FreeBSD, the display devices API on
+//! Windows, CoreGraphics on macOS, wscons on OpenBSD. [`crate::probe`] uses the backend of the
+//! current platform.
+//! Other platforms (or macOS without the `coregraphics` feature) build with a stub backend,
+//! failing with [`Unsupported`] at runtime.
+//!
+//! Without access to the GPU (WSL, containers), the compositor is asked instead (Wayland, then
+//! X11), or a configured fallback resolution is used.
+
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{config, environment, pi, Display, Mode, ProbeOptions};
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub mod drm;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub mod fixture;
+#[cfg(all(target_os = "macos", feature = "coregraphics"))]
+pub mod macos;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub mod mutter;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub mod portal;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub mod wayland;
+#[cfg(windows)]
+pub mod windows;
+#[cfg(target_os = "openbsd")]
+pub mod wsdisplay;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub mod x11;
+
+/// A way to detect the connected displays
+pub trait Backend {
+    /// Name of the backend, for logs
+    fn name(&self) -> &'static str;
+
+    /// Detect every connected display, following the given options
+    fn probe(&self, options: &ProbeOptions) -> anyhow::Result<Vec<Display>>;
+
+    /// Scale factor the compositor applies to each output, by connector name
+    ///
+    /// Only compositors know it, the other backends list none.
+    fn scales(&self) -> anyhow::Result<Vec<(String, f64)>> {
+        Ok(vec![])
+    }
+}
+
+/// Error of the backends on platforms without display detection
+///
+/// Returned through [`anyhow::Error`], it can be told apart with
+/// `error.is::<Unsupported>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported;
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "display detection isn't supported on this platform ({})",
+            env::consts::OS
+        )
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Backend of the platforms without display detection, always failing with [`Unsupported`]
+pub struct Stub;
+
+impl Backend for Stub {
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        Err(Unsupported.into())
+    }
+}
+
+/// Get the backend of the current platform
+pub fn platform() -> Box<dyn Backend> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    return Box::new(self::drm::Drm);
+    #[cfg(all(target_os = "macos", feature = "coregraphics"))]
+    return Box::new(self::macos::CoreGraphics);
+    #[cfg(windows)]
+    return Box::new(self::windows::Windows);
+    #[cfg(target_os = "openbsd")]
+    return Box::new(self::wsdisplay::Wsdisplay);
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        all(target_os = "macos", feature = "coregraphics"),
+        windows
+    )))]
+    return Box::new(Stub);
+}
+
+/// Get the backend selected by `RRES_BACKEND`, if set
+///
+/// `mock:<fixture.json>` replays a fixture recorded by `rres dump` (see [`fixture`]), for
+/// deterministic tests on machines without GPUs.
+pub fn from_env() -> anyhow::Result<Option<Box<dyn Backend>>> {
+    let Some(_path) = mock_fixture()? else {
+        return Ok(None);
+    };
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    return Ok(Some(Box::new(self::fixture::Fixture::load(&_path)?)));
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+    return Err(Unsupported.into());
+}
+
+/// Get the fixture of `RRES_BACKEND=mock:<fixture.json>`, if set
+pub fn mock_fixture() -> anyhow::Result<Option<std::path::PathBuf>> {
+    let Some(value) = env::var_os("RRES_BACKEND") else {
+        return Ok(None);
+    };
+    let value = value.to_string_lossy();
+    match value.split_once(':') {
+        Some(("mock", path)) => Ok(Some(path.into())),
+        _ => Err(anyhow::anyhow!(
+            "invalid RRES_BACKEND: {value} (expected mock:<fixture.json>)"
+        )),
+    }
+}
+
+/// Get the backend asking the compositor's display service over D-Bus (GNOME's Mutter)
+pub fn dbus() -> anyhow::Result<Box<dyn Backend>> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+    return Ok(Box::new(self::mutter::Mutter));
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+    return Err(Unsupported.into());
+}
+
+/// Get the backend asking the ScreenCast portal (`--via-portal`)
+pub fn portal() -> anyhow::Result<Box<dyn Backend>> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+    return Ok(Box::new(self::portal::ScreenCast));
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+    return Err(Unsupported.into());
+}
+
+/// Get the backends asking the compositor, in order of preference
+pub fn compositors() -> Vec<Box<dyn Backend>> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+    return vec![Box::new(self::wayland::Wayland), Box::new(self::x11::X11)];
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+    return vec![];
+}
+
+/// Get the scale factor the compositor applies to a display, by connector name
+///
+/// Mutter is asked first, then the other compositors. `None` when no compositor knows the
+/// display (eg. outside a desktop session).
+pub fn scale(connector: &str) -> Option<f64> {
+    let backends = dbus().into_iter().chain(compositors());
+    for backend in backends {
+        match backend.scales() {
+            Ok(scales) => {
+                if let Some((_, scale)) = scales.into_iter().find(|(c, _)| c == connector) {
+                    log::debug!("{connector} is scaled {scale} by {}", backend.name());
+                    return Some(scale);
+                }
+            }
+            Err(e) => log::debug!("The {} backend has no scales: {e:#}", backend.name()),
+        }
+    }
+    None
+}
+
+/// Whether [`fallback`] should replace the platform's result
+///
+/// Only when DRM is unavailable (it failed, or there is no `/dev/dri`, eg. WSLg or a
+/// container): otherwise no display is the answer. An explicit card or backend is never
+/// replaced by another one.
+pub fn should_fall_back(options: &ProbeOptions, result: &anyhow::Result<Vec<Display>>) -> bool {
+    if options.card.is_some() || env::var_os("RRES_BACKEND").is_some() {
+        return false;
+    }
+    match result {
+        Ok(displays) => displays.is_empty() && !std::path::Path::new("/dev/dri").exists(),
+        Err(_) => true,
+    }
+}
+
+/// Detect the displays when the platform's backend found none
+///
+/// The compositors are tried first, then the Raspberry Pi firmware's display, then the
+/// fallback resolution (`RRES_FALLBACK_RES`).
+/// `result` is the platform's result, returned as is when everything fails.
+pub fn fallback(
+    options: &ProbeOptions,
+    result: anyhow::Result<Vec<Display>>,
+) -> anyhow::Result<Vec<Display>> {
+    let reason = match &result {
+        Ok(_) => "no display found".to_string(),
+        Err(e) => format!("{e:#}"),
+    };
+    let environment = environment::detect();
+    match environment {
+        Some(environment) => {
+            log::warn!("Running in {environment} ({reason}), asking the compositor")
+        }
+        None => log::info!("{reason}, asking the compositor"),
+    }
+
+    for backend in compositors() {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            backend = backend.name(),
+            reason,
+            "fallback backend selected"
+        );
+        match backend.probe(options) {
+            Ok(displays) if !displays.is_empty() => {
+                log::warn!("Displays read from the {} backend", backend.name());
+                return Ok(displays);
+            }
+            Ok(_) => log::info!("The {} backend found no display", backend.name()),
+            Err(e) => log::info!("The {} backend failed: {e:#}", backend.name()),
+        }
+    }
+
+    if let Some(model) = pi::model() {
+        if let Some(display) = pi::firmware_display() {
+            log::warn!("{model}: using the display set up by the firmware");
+            return Ok(vec![display]);
+        }
+    }
+
+    let fallback_res = match options.fallback_res {
+        Some(res) => Some(res),
+        None => match env::var("RRES_FALLBACK_RES") {
+            Ok(res) => Some(config::parse_res(&res).context("failed to parse RRES_FALLBACK_RES")?),
+            Err(_) => None,
+        },
+    };
+    if let Some(res) = fallback_res {
+        log::warn!(
+            "No display detected, using the fallback resolution: {}x{}",
+            res.0,
+            res.1
+        );
+        let mode = Mode::from_size(res);
+        return Ok(vec![Display {
+            card: PathBuf::new(),
+            connector: "fallback".to_string(),
+            mode,
+            modes: vec![mode],
+            physical_size: None,
+            vrr_capable: None,
+            edid: None,
+            properties: Default::default(),
+        }]);
+    }
+
+    if let Some(environment) = environment {
+        log::warn!("No display detected: {}", environment::hint(environment));
+    }
+    result
+}
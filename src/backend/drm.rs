@@ -0,0 +1,1174 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! DRM backend (Linux and FreeBSD)
+//!
+//! Displays are read from the connectors of every card in `/dev/dri/`, or from sysfs when
+//! the cards can't be opened (Linux only).
+//!
+//! At trace level, the duration of each DRM call is logged with its card, to find which
+//! driver slows the detection down.
+
+use std::os;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Context;
+use drm::control::{connector, crtc, encoder, property, Device as ControlDevice, ResourceHandle};
+use drm::Device;
+
+use crate::backend::Backend;
+use crate::{gpu, pi, prime, Display, Mode, Orientation, ProbeOptions, Properties};
+
+// Card handle
+// Really just to get a file descriptor for `drm`
+pub(crate) struct Card(std::fs::File);
+
+impl os::fd::AsFd for Card {
+    fn as_fd(&self) -> os::fd::BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl Card {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true);
+        options.write(true);
+        Ok(Card(options.open(path)?))
+    }
+}
+
+// Implement `drm` types
+impl Device for Card {}
+impl ControlDevice for Card {}
+
+impl From<drm::control::Mode> for Mode {
+    fn from(mode: drm::control::Mode) -> Self {
+        let (hsync_start, hsync_end, htotal) = mode.hsync();
+        let (vsync_start, vsync_end, vtotal) = mode.vsync();
+        Self {
+            clock: mode.clock(),
+            hdisplay: mode.size().0,
+            hsync_start,
+            hsync_end,
+            htotal,
+            hskew: mode.hskew(),
+            vdisplay: mode.size().1,
+            vsync_start,
+            vsync_end,
+            vtotal,
+            vscan: mode.vscan(),
+            vrefresh: mode.vrefresh(),
+            flags: mode.flags().bits(),
+            mode_type: mode.mode_type().bits(),
+        }
+    }
+}
+
+impl From<Mode> for drm::control::Mode {
+    fn from(mode: Mode) -> Self {
+        let mut name = [0; 32];
+        let (width, height) = mode.size();
+        for (c, b) in name.iter_mut().zip(format!("{width}x{height}").bytes()) {
+            *c = b as _;
+        }
+        drm::control::Mode::from(drm_ffi::drm_mode_modeinfo {
+            clock: mode.clock,
+            hdisplay: mode.hdisplay,
+            hsync_start: mode.hsync_start,
+            hsync_end: mode.hsync_end,
+            htotal: mode.htotal,
+            hskew: mode.hskew,
+            vdisplay: mode.vdisplay,
+            vsync_start: mode.vsync_start,
+            vsync_end: mode.vsync_end,
+            vtotal: mode.vtotal,
+            vscan: mode.vscan,
+            vrefresh: mode.vrefresh,
+            flags: mode.flags,
+            type_: mode.mode_type,
+            name,
+        })
+    }
+}
+
+/// DRM display detection
+pub struct Drm;
+
+impl Backend for Drm {
+    fn name(&self) -> &'static str {
+        "drm"
+    }
+
+    fn probe(&self, options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        // Store found displays
+        let mut displays: Vec<Display> = vec![];
+
+        let mut cards = crate::get_cards(options.card.clone())?;
+        options.gpu_policy.sort(&mut cards);
+
+        // Render offload doesn't move the displays, keep probing the GPUs that own them
+        if let Some(offload) = prime::render_offload() {
+            log::info!("Render offload requested ({offload}), probing display-owning GPUs");
+            if let Some(render) = prime::render_card(&cards, Some(&offload)) {
+                log::info!("Rendering GPU: {}", prime::describe_card(&render));
+            }
+        }
+
+        // Virtual GPU found, if every GPU is virtual
+        let mut virtual_driver: Option<String> = None;
+        let mut all_virtual = true;
+
+        // Suspended GPUs without displays would only be woken up for nothing
+        #[cfg(target_os = "linux")]
+        cards.retain(|card| {
+            if crate::sysfs::is_runtime_suspended(card)
+                && !crate::sysfs::has_connected_connector(card)
+            {
+                log::info!(
+                    card:% = card.display();
+                    "Skipping {}: suspended, without connected displays",
+                    card.display()
+                );
+                all_virtual = false;
+                return false;
+            }
+            true
+        });
+
+        let mut record = |driver: Option<String>| {
+            if let Some(driver) = driver {
+                if gpu::is_virtual_driver(&driver) {
+                    virtual_driver.get_or_insert(driver);
+                } else {
+                    all_virtual = false;
+                }
+            }
+        };
+
+        match options.limit {
+            // One card after the other, in the order of the policy: the cards after the limit
+            // aren't opened (nor woken up)
+            Some(limit) => {
+                for (i, file) in cards.iter().enumerate() {
+                    let (driver, card_displays) =
+                        probe_card(file, Some(limit - displays.len()), options.edid)?;
+                    record(driver);
+                    displays.extend(card_displays);
+                    if displays.len() < limit {
+                        continue;
+                    }
+                    let skipped = &cards[i + 1..];
+                    if !skipped.is_empty() {
+                        log::debug!(
+                            "Found {} displays, skipping the {} other cards",
+                            displays.len(),
+                            skipped.len()
+                        );
+                    }
+                    // Their driver is still known, without opening them
+                    for card in skipped {
+                        record(Some(
+                            crate::sysfs::driver(card).unwrap_or_else(|| "unknown".to_string()),
+                        ));
+                    }
+                    break;
+                }
+            }
+            // Every card at once, a slow driver (or a GPU waking up) doesn't hold the others
+            None => {
+                let results: Vec<_> = std::thread::scope(|scope| {
+                    let threads: Vec<_> = cards
+                        .iter()
+                        .map(|file| scope.spawn(move || probe_card(file, None, options.edid)))
+                        .collect();
+                    // Joined in the order of the policy, so that the result is deterministic
+                    threads
+                        .into_iter()
+                        .map(|thread| {
+                            thread
+                                .join()
+                                .unwrap_or_else(|_| Err(anyhow::anyhow!("card probe panicked")))
+                        })
+                        .collect()
+                });
+                for result in results {
+                    let (driver, card_displays) = result?;
+                    record(driver);
+                    displays.extend(card_displays);
+                }
+            }
+        }
+
+        // Virtual connectors don't follow the window (Crostini), the compositor knows better
+        if let Some(driver) = virtual_driver.filter(|_| all_virtual && !options.prefer_drm) {
+            for backend in super::compositors() {
+                match backend.probe(options) {
+                    Ok(found) if !found.is_empty() => {
+                        log::info!(
+                            "Virtual GPU ({driver}), using the {} backend (--prefer-drm to keep DRM)",
+                            backend.name()
+                        );
+                        return Ok(found);
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::debug!("The {} backend failed: {e:#}", backend.name()),
+                }
+            }
+        }
+
+        Ok(displays)
+    }
+}
+
+/// Run a DRM call on `card`, logging how long it took at trace level
+fn timed<T>(card: &Path, call: impl FnOnce() -> String, f: impl FnOnce() -> T) -> T {
+    if !log::log_enabled!(log::Level::Trace) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    log::trace!(
+        card:% = card.display();
+        "{}: {} took {:.3} ms",
+        card.display(),
+        call(),
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+    result
+}
+
+/// Name of the card's driver, from sysfs (or sysctl) when possible to save an ioctl
+fn driver_name(gpu: &Card, file: &Path) -> anyhow::Result<String> {
+    if let Some(driver) = crate::sysfs::driver(file) {
+        return Ok(driver);
+    }
+    let driver = timed(file, || "get driver".into(), || gpu.get_driver())?;
+    Ok(driver.name().to_string_lossy().to_string())
+}
+
+/// Probe a single card, returning its driver (`None` if it couldn't be opened) and displays
+///
+/// At most `limit` displays are read from the connectors, with their EDID if `edid`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip(limit, edid), fields(driver))
+)]
+fn probe_card(
+    file: &Path,
+    limit: Option<usize>,
+    edid: bool,
+) -> anyhow::Result<(Option<String>, Vec<Display>)> {
+    let gpu = match timed(file, || "open".into(), || Card::open(file)) {
+        Ok(gpu) => gpu,
+        #[cfg(target_os = "linux")]
+        Err(e) => {
+            log::warn!(card:% = file.display(); "failed to open {}: {e}, reading sysfs", file.display());
+            return Ok((None, crate::sysfs::connected_displays(file)));
+        }
+        #[cfg(not(target_os = "linux"))]
+        Err(e) => {
+            log::warn!(card:% = file.display(); "failed to open {}: {e}", file.display());
+            return Ok((None, vec![]));
+        }
+    };
+    let driver = driver_name(&gpu, file)?;
+    log::debug!(card:% = file.display(); "Found GPU: {driver}");
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("driver", driver.as_str());
+    // Find displays
+    let displays = match card_displays(&gpu, file, limit, edid) {
+        Ok(displays) => displays,
+        Err(e) => {
+            log::error!(card:% = file.display(); "failed to read modes: {e}");
+            vec![]
+        }
+    };
+    Ok((Some(driver), displays))
+}
+
+/// A card kept open, with its connectors, for [`crate::Prober`]
+pub(crate) struct OpenCard {
+    path: PathBuf,
+    gpu: Box<dyn Kms + Send>,
+    driver: String,
+    connectors: Vec<connector::Handle>,
+}
+
+impl OpenCard {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let gpu = timed(path, || "open".into(), || Card::open(path))
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let driver = driver_name(&gpu, path)?;
+        log::debug!(card:% = path.display(); "Found GPU: {driver}");
+        Self::new(path, Box::new(gpu), driver)
+    }
+
+    /// Keep an opened card (or a scripted one, in tests), reading its connector list
+    pub fn new(path: &Path, gpu: Box<dyn Kms + Send>, driver: String) -> anyhow::Result<Self> {
+        let mut card = Self {
+            path: path.to_path_buf(),
+            gpu,
+            driver,
+            connectors: vec![],
+        };
+        card.refresh()?;
+        Ok(card)
+    }
+
+    /// Fetch the connector list again (eg. after a DP MST hub was plugged)
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        self.connectors = self.gpu.connectors()?;
+        Ok(())
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        gpu::is_virtual_driver(&self.driver)
+    }
+
+    /// Name of the card's driver (eg. `amdgpu`)
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    /// Read the connectors
+    pub fn connectors(&self) -> anyhow::Result<Vec<ConnectorInfo>> {
+        self.connectors
+            .iter()
+            .map(|&handle| self.gpu.connector(handle))
+            .collect()
+    }
+
+    /// Get the first `limit` connected displays (all of them if `None`), with their EDID if
+    /// `edid`
+    pub fn displays(&mut self, limit: Option<usize>, edid: bool) -> anyhow::Result<Vec<Display>> {
+        match connector_displays(&*self.gpu, &self.path, &self.connectors, limit, edid) {
+            Ok(displays) => Ok(displays),
+            // Connectors come and go with MST, try again with the current ones
+            Err(e) => {
+                log::debug!(
+                    "{e:#}, refreshing the connectors of {}",
+                    self.path.display()
+                );
+                self.refresh()?;
+                connector_displays(&*self.gpu, &self.path, &self.connectors, limit, edid)
+            }
+        }
+    }
+}
+
+/// Get the mode of the first connected display of the primary card, reading nothing else
+///
+/// The display is the one a full probe puts first on this card, in the device's orientation.
+pub fn primary_mode() -> Option<Mode> {
+    let mut cards = crate::get_cards(None).ok()?;
+    gpu::Policy::Default.sort(&mut cards);
+    let card = cards.first()?;
+    first_mode(&Card::open(card).ok()?, card)
+}
+
+/// Get the mode of a card's first display, rotated like [`crate::probe`] does
+fn first_mode<K: Kms + ?Sized>(gpu: &K, card: &Path) -> Option<Mode> {
+    let mut display = card_displays(gpu, card, Some(1), false).ok()?.pop()?;
+    display.rotate_to_device();
+    Some(display.mode)
+}
+
+/// A connector, as read by [`Kms::connector`]
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectorInfo {
+    /// Connector name (eg. `DP-1`)
+    pub name: String,
+    pub connected: bool,
+    /// Supported modes, preferred mode first
+    pub modes: Vec<Mode>,
+    /// Physical size in millimeters, if known
+    pub size: Option<(u32, u32)>,
+    pub current_encoder: Option<encoder::Handle>,
+}
+
+/// A connector property, as read by [`Kms::connector_properties`]
+#[derive(Debug, Clone)]
+pub(crate) struct PropertyValue {
+    pub name: String,
+    pub value: u64,
+    /// Bounds of a range property
+    pub range: Option<(u64, u64)>,
+}
+
+/// Every property of a connector
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PropertyList(pub Vec<PropertyValue>);
+
+impl PropertyList {
+    fn find(&self, name: &str) -> Option<&PropertyValue> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    /// Get the value of a property by name, `None` if the connector has no such property
+    pub fn value(&self, name: &str) -> Option<u64> {
+        self.find(name).map(|p| p.value)
+    }
+
+    /// Get the bounds of a range property by name
+    pub fn range(&self, name: &str) -> Option<(u64, u64)> {
+        self.find(name)?.range
+    }
+}
+
+/// The KMS queries of the display detection
+///
+/// Implemented for every [`ControlDevice`]. `drm`'s structures can't be built outside of the
+/// crate, the detection only sees these answers so that the tests can script them.
+pub(crate) trait Kms {
+    /// Get the connectors of the card
+    fn connectors(&self) -> anyhow::Result<Vec<connector::Handle>>;
+    fn connector(&self, handle: connector::Handle) -> anyhow::Result<ConnectorInfo>;
+    /// Get the name of a connector (eg. `DP-1`), without reading its modes
+    fn connector_name(&self, handle: connector::Handle) -> anyhow::Result<String> {
+        Ok(self.connector(handle)?.name)
+    }
+    /// Get the CRTC an encoder is bound to
+    fn encoder_crtc(&self, handle: encoder::Handle) -> anyhow::Result<Option<crtc::Handle>>;
+    /// Get the mode of a CRTC, `None` if it is off
+    fn crtc_mode(&self, handle: crtc::Handle) -> anyhow::Result<Option<Mode>>;
+    /// Get every property of a connector, with its value
+    ///
+    /// Names are only known by asking for each property: the list is read once per connector.
+    fn connector_properties(&self, handle: connector::Handle) -> anyhow::Result<PropertyList>;
+    fn property_blob(&self, blob: u64) -> anyhow::Result<Vec<u8>>;
+    /// Get a connector's EDID without ioctls (from sysfs), if possible
+    fn sysfs_edid(&self, card: &Path, connector: &str) -> Option<Vec<u8>> {
+        let _ = (card, connector);
+        None
+    }
+    /// Whether a connector's current mode uses DSC, from debugfs
+    fn debugfs_dsc(&self, card: &Path, connector: &str) -> Option<bool> {
+        let _ = (card, connector);
+        None
+    }
+    /// Get a connector's trained DisplayPort link, from debugfs
+    fn debugfs_dp_link(&self, card: &Path, connector: &str) -> Option<crate::dp::Link> {
+        let _ = (card, connector);
+        None
+    }
+}
+
+impl<G: ControlDevice> Kms for G {
+    fn connectors(&self) -> anyhow::Result<Vec<connector::Handle>> {
+        Ok(self
+            .resource_handles()
+            .context("failed to get resource handles")?
+            .connectors()
+            .to_vec())
+    }
+
+    fn connector(&self, handle: connector::Handle) -> anyhow::Result<ConnectorInfo> {
+        let connector = self
+            .get_connector(handle, false)
+            .context("failed to get connector handle")?;
+        Ok(ConnectorInfo {
+            name: connector_name(&connector),
+            connected: connector.state() == connector::State::Connected,
+            modes: connector.modes().iter().map(|&m| m.into()).collect(),
+            size: connector.size(),
+            current_encoder: connector.current_encoder(),
+        })
+    }
+
+    fn connector_name(&self, handle: connector::Handle) -> anyhow::Result<String> {
+        use os::fd::AsRawFd;
+        let info = drm_ffi::mode::get_connector(
+            self.as_fd().as_raw_fd(),
+            handle.into(),
+            None,
+            None,
+            None,
+            None,
+            false,
+        )
+        .context("failed to get connector handle")?;
+        Ok(format!(
+            "{}-{}",
+            connector::Interface::from(info.connector_type).as_str(),
+            info.connector_type_id
+        ))
+    }
+
+    fn encoder_crtc(&self, handle: encoder::Handle) -> anyhow::Result<Option<crtc::Handle>> {
+        Ok(self.get_encoder(handle)?.crtc())
+    }
+
+    fn crtc_mode(&self, handle: crtc::Handle) -> anyhow::Result<Option<Mode>> {
+        let crtc = self.get_crtc(handle).context("failed to get crtc")?;
+        Ok(crtc.mode().map(Mode::from))
+    }
+
+    fn connector_properties(&self, handle: connector::Handle) -> anyhow::Result<PropertyList> {
+        let mut properties = vec![];
+        for (property, value) in self.get_properties(handle)?.iter() {
+            let info = self.get_property(*property)?;
+            properties.push(PropertyValue {
+                name: info.name().to_string_lossy().to_string(),
+                value: *value,
+                range: match info.value_type() {
+                    property::ValueType::UnsignedRange(min, max) => Some((min, max)),
+                    _ => None,
+                },
+            });
+        }
+        Ok(PropertyList(properties))
+    }
+
+    fn property_blob(&self, blob: u64) -> anyhow::Result<Vec<u8>> {
+        Ok(self.get_property_blob(blob)?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn sysfs_edid(&self, card: &Path, connector: &str) -> Option<Vec<u8>> {
+        crate::sysfs::edid(card, connector)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn debugfs_dsc(&self, card: &Path, connector: &str) -> Option<bool> {
+        crate::debugfs::dsc(card, connector)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn debugfs_dp_link(&self, card: &Path, connector: &str) -> Option<crate::dp::Link> {
+        crate::debugfs::dp_link(card, connector)
+    }
+}
+
+/// Get all the connected display's modes from a libdrm card.
+pub fn get_card_modes<G: ControlDevice>(gpu: &G) -> anyhow::Result<Vec<Mode>> {
+    Ok(get_card_displays(gpu, Path::new(""))?
+        .into_iter()
+        .map(|d| d.mode)
+        .collect())
+}
+
+/// Get all the connected displays from a libdrm card, found at `card`
+pub fn get_card_displays<G: ControlDevice>(gpu: &G, card: &Path) -> anyhow::Result<Vec<Display>> {
+    card_displays(gpu, card, None, true)
+}
+
+/// Get the first `limit` connected displays from a card (all of them if `None`)
+pub(crate) fn card_displays<K: Kms + ?Sized>(
+    gpu: &K,
+    card: &Path,
+    limit: Option<usize>,
+    edid: bool,
+) -> anyhow::Result<Vec<Display>> {
+    let connectors = timed(card, || "get resource handles".into(), || gpu.connectors())?;
+    connector_displays(gpu, card, &connectors, limit, edid)
+}
+
+/// Get the first `limit` connected displays among `connectors`
+///
+/// The connectors are read in display order, up to the limit: the others only have their
+/// name read. The EDID blob and the properties are only read if `edid`, most callers just need
+/// the mode.
+fn connector_displays<K: Kms + ?Sized>(
+    gpu: &K,
+    card: &Path,
+    connectors: &[connector::Handle],
+    limit: Option<usize>,
+    edid: bool,
+) -> anyhow::Result<Vec<Display>> {
+    let mut displays: Vec<Display> = vec![];
+
+    // Handles follow the connectors' creation, which can change across boots (eg. MST)
+    let mut handles = connectors
+        .iter()
+        .map(|&handle| {
+            let name = timed(
+                card,
+                || format!("get connector {} name", u32::from(handle)),
+                || gpu.connector_name(handle),
+            )?;
+            Ok((handle, name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    handles.sort_by_cached_key(|(_, name)| (connector_order(name), name.clone()));
+
+    for (handle, _) in handles {
+        if limit.is_some_and(|limit| displays.len() >= limit) {
+            break;
+        }
+        let connector = timed(
+            card,
+            || format!("get connector {}", u32::from(handle)),
+            || gpu.connector(handle),
+        )?;
+        if connector.connected {
+            // Only built-in panels have an orientation, needed for the resolution. The other
+            // properties are only read if requested.
+            let internal = is_internal(&connector.name);
+            let list = if edid || internal {
+                timed(
+                    card,
+                    || format!("get {} properties", connector.name),
+                    || gpu.connector_properties(handle),
+                )
+                .unwrap_or_default()
+            } else {
+                PropertyList::default()
+            };
+            let (edid, vrr_capable, mut properties) = if edid {
+                (
+                    connector_edid(gpu, card, &connector.name, &list),
+                    list.value("vrr_capable").map(|value| value != 0),
+                    display_properties(gpu, card, &connector.name, &list),
+                )
+            } else {
+                (None, None, Properties::default())
+            };
+            if internal {
+                properties.panel_orientation = list
+                    .value("panel orientation")
+                    .and_then(Orientation::from_property);
+            }
+            displays.push(Display {
+                card: card.to_path_buf(),
+                mode: get_connector_mode(gpu, card, &connector)?,
+                physical_size: connector.size.filter(|s| s.0 > 0 && s.1 > 0),
+                connector: connector.name,
+                modes: connector.modes,
+                vrr_capable,
+                edid,
+                properties,
+            });
+        }
+    }
+    Ok(displays)
+}
+
+/// Get the properties of a connector reported in [`Display::properties`], but its panel
+/// orientation
+fn display_properties<K: Kms + ?Sized>(
+    gpu: &K,
+    card: &Path,
+    name: &str,
+    list: &PropertyList,
+) -> Properties {
+    let bpc = |value: u64| u8::try_from(value).ok();
+    Properties {
+        // Connectors always have properties, none were read if the list is empty
+        hdr_output_metadata: (!list.0.is_empty())
+            .then(|| list.value("HDR_OUTPUT_METADATA").is_some()),
+        max_bpc: list.value("max bpc").and_then(bpc),
+        max_bpc_range: list
+            .range("max bpc")
+            .and_then(|(min, max)| bpc(min).zip(bpc(max))),
+        dsc: timed(
+            card,
+            || format!("read {name} DSC state from debugfs"),
+            || gpu.debugfs_dsc(card, name),
+        ),
+        dp_link: timed(
+            card,
+            || format!("read {name} DP link from debugfs"),
+            || gpu.debugfs_dp_link(card, name),
+        ),
+        // Read with the modes, see connector_displays
+        panel_orientation: None,
+    }
+}
+
+/// Whether a connector is a built-in panel (eDP, LVDS, DSI, DPI), from its name
+fn is_internal(name: &str) -> bool {
+    !connector_order(name).0
+}
+
+/// Position of a connector in the display order, from its name (eg. `HDMI-A-1`)
+///
+/// Built-in panels (eDP, LVDS, DSI, DPI) come first, as the primary display of laptops and
+/// handhelds. Connectors are then ordered by type (`DRM_MODE_CONNECTOR_*` value), and by
+/// number among the connectors of that type. Names rres doesn't know go last.
+fn connector_order(name: &str) -> (bool, u32, u32) {
+    let Some((interface, id)) = name.rsplit_once('-') else {
+        return (true, u32::MAX, u32::MAX);
+    };
+    let interface = (0..=255)
+        .map(connector::Interface::from)
+        .find(|i| i.as_str() == interface);
+    let internal = matches!(
+        interface,
+        Some(
+            connector::Interface::EmbeddedDisplayPort
+                | connector::Interface::LVDS
+                | connector::Interface::DSI
+                | connector::Interface::DPI
+        )
+    );
+    (
+        !internal,
+        interface.map_or(u32::MAX, u32::from),
+        id.parse().unwrap_or(u32::MAX),
+    )
+}
+
+/// Get a connector's EDID, from sysfs when possible (without ioctls), or from its property
+pub(crate) fn connector_edid<K: Kms + ?Sized>(
+    gpu: &K,
+    card: &Path,
+    name: &str,
+    properties: &PropertyList,
+) -> Option<Vec<u8>> {
+    if let Some(edid) = timed(
+        card,
+        || format!("read {name} EDID from sysfs"),
+        || gpu.sysfs_edid(card, name),
+    ) {
+        return Some(edid);
+    }
+    match properties.value("EDID") {
+        Some(blob) if blob != 0 => timed(
+            card,
+            || format!("get {name} EDID blob"),
+            || gpu.property_blob(blob),
+        )
+        .ok(),
+        _ => None,
+    }
+}
+
+/// Get a property of a KMS object by name, with its current value
+pub(crate) fn get_property<G: ControlDevice, H: ResourceHandle>(
+    gpu: &G,
+    handle: H,
+    name: &str,
+) -> anyhow::Result<Option<(property::Handle, property::RawValue)>> {
+    for (property, value) in gpu.get_properties(handle)?.iter() {
+        if gpu.get_property(*property)?.name().to_str() == Ok(name) {
+            return Ok(Some((*property, *value)));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the name of a connector (eg. `DP-1`)
+pub(crate) fn connector_name(connector: &drm::control::connector::Info) -> String {
+    format!(
+        "{}-{}",
+        connector.interface().as_str(),
+        connector.interface_id()
+    )
+}
+
+/// Get current display mode from connector
+///
+/// Note: nVidia GPUs don't share the current encoder+crtc, so this function will report the
+/// native display's resolution instead of the current resolution.
+fn get_connector_mode<K: Kms + ?Sized>(
+    gpu: &K,
+    card: &Path,
+    connector: &ConnectorInfo,
+) -> anyhow::Result<Mode> {
+    if !connector.connected {
+        return Err(anyhow::anyhow!("Connector is disconnected"));
+    }
+    if let Some(encoder_handle) = connector.current_encoder {
+        // Get the encoder then crtc
+        let crtc_handle = timed(
+            card,
+            || format!("get encoder {}", u32::from(encoder_handle)),
+            || gpu.encoder_crtc(encoder_handle),
+        )?;
+        if let Some(crtc_handle) = crtc_handle {
+            // Get current mode, and store it
+            let current_mode = timed(
+                card,
+                || format!("get CRTC {}", u32::from(crtc_handle)),
+                || gpu.crtc_mode(crtc_handle),
+            )?;
+            if let Some(current_mode) = current_mode {
+                log::debug!(
+                    connector = connector.name.as_str();
+                    "Found display: {}, {}x{}",
+                    connector.name,
+                    current_mode.size().0,
+                    current_mode.size().1
+                );
+                return Ok(current_mode);
+            }
+        }
+    }
+    // The Raspberry Pi firmware (vc4-fkms) sets the mode behind DRM's back
+    if pi::model().is_some() {
+        if let Some(size) = pi::framebuffer_size() {
+            if let Some(mode) = connector.modes.iter().find(|m| m.size() == size) {
+                log::info!("Using the mode set by the Raspberry Pi firmware");
+                return Ok(*mode);
+            }
+        }
+    }
+    // nVidia GPUs don't expose the encoder (and thus neither the crtc)
+    log::warn!(
+        connector = connector.name.as_str();
+        "Could not detect current mode for display {}, reading native resolution",
+        connector.name
+    );
+    connector
+        .modes
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("{} has no modes", connector.name))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A card answering from scripted connectors, encoders and CRTCs
+    #[derive(Default)]
+    pub(crate) struct FakeCard {
+        connectors: Vec<(connector::Handle, ConnectorInfo)>,
+        /// CRTC of each encoder
+        encoders: Vec<(encoder::Handle, Option<crtc::Handle>)>,
+        /// Mode of each CRTC
+        crtcs: Vec<(crtc::Handle, Option<Mode>)>,
+        properties: Vec<(connector::Handle, &'static str, u64)>,
+        /// Bounds of the range properties
+        ranges: Vec<(connector::Handle, &'static str, (u64, u64))>,
+        blobs: Vec<(u64, Vec<u8>)>,
+        /// Number of property lists read
+        property_reads: std::cell::Cell<usize>,
+        /// Number of connectors read
+        connector_reads: std::cell::Cell<usize>,
+        /// Set when the card disappears (eg. an unplugged eGPU): every query fails
+        pub(crate) gone: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    fn handle<T: From<drm::control::RawResourceHandle>>(id: u32) -> T {
+        drm::control::from_u32(id).unwrap()
+    }
+
+    pub(crate) fn mode(size: (u16, u16), vrefresh: u32) -> Mode {
+        Mode {
+            vrefresh,
+            ..Mode::from_size(size)
+        }
+    }
+
+    fn find<K: PartialEq, V: Clone>(list: &[(K, V)], key: K) -> anyhow::Result<V> {
+        list.iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| anyhow::anyhow!("no such object"))
+    }
+
+    impl Kms for FakeCard {
+        fn connectors(&self) -> anyhow::Result<Vec<connector::Handle>> {
+            self.check()?;
+            Ok(self.connectors.iter().map(|(h, _)| *h).collect())
+        }
+
+        fn connector(&self, handle: connector::Handle) -> anyhow::Result<ConnectorInfo> {
+            self.check()?;
+            self.connector_reads.set(self.connector_reads.get() + 1);
+            find(&self.connectors, handle)
+        }
+
+        fn connector_name(&self, handle: connector::Handle) -> anyhow::Result<String> {
+            self.check()?;
+            Ok(find(&self.connectors, handle)?.name)
+        }
+
+        fn encoder_crtc(&self, handle: encoder::Handle) -> anyhow::Result<Option<crtc::Handle>> {
+            find(&self.encoders, handle)
+        }
+
+        fn crtc_mode(&self, handle: crtc::Handle) -> anyhow::Result<Option<Mode>> {
+            find(&self.crtcs, handle)
+        }
+
+        fn connector_properties(&self, handle: connector::Handle) -> anyhow::Result<PropertyList> {
+            self.property_reads.set(self.property_reads.get() + 1);
+            Ok(PropertyList(
+                self.properties
+                    .iter()
+                    .filter(|(h, _, _)| *h == handle)
+                    .map(|&(_, name, value)| PropertyValue {
+                        name: name.to_string(),
+                        value,
+                        range: self
+                            .ranges
+                            .iter()
+                            .find(|(h, n, _)| *h == handle && *n == name)
+                            .map(|(_, _, range)| *range),
+                    })
+                    .collect(),
+            ))
+        }
+
+        fn property_blob(&self, blob: u64) -> anyhow::Result<Vec<u8>> {
+            find(&self.blobs, blob)
+        }
+    }
+
+    impl FakeCard {
+        fn check(&self) -> anyhow::Result<()> {
+            if self.gone.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(std::io::Error::from_raw_os_error(libc::ENODEV).into());
+            }
+            Ok(())
+        }
+
+        /// Add a connector, driven by encoder and CRTC `id` when `current` is set
+        pub(crate) fn connect(
+            &mut self,
+            id: u32,
+            name: &str,
+            modes: &[Mode],
+            current: Option<Mode>,
+        ) {
+            self.connectors.push((
+                handle(id),
+                ConnectorInfo {
+                    name: name.to_string(),
+                    connected: true,
+                    modes: modes.to_vec(),
+                    size: Some((600, 340)),
+                    current_encoder: current.map(|_| handle(id)),
+                },
+            ));
+            if current.is_some() {
+                self.encoders.push((handle(id), Some(handle(id))));
+                self.crtcs.push((handle(id), current));
+            }
+        }
+
+        fn disconnect(&mut self, id: u32, name: &str) {
+            self.connectors.push((
+                handle(id),
+                ConnectorInfo {
+                    name: name.to_string(),
+                    connected: false,
+                    modes: vec![],
+                    size: None,
+                    current_encoder: None,
+                },
+            ));
+        }
+
+        fn displays(&self) -> Vec<Display> {
+            card_displays(self, Path::new(""), None, true).unwrap()
+        }
+    }
+
+    #[test]
+    fn current_mode_from_crtc() {
+        let mut card = FakeCard::default();
+        let modes = [mode((2560, 1440), 144), mode((1920, 1080), 60)];
+        card.connect(1, "DP-1", &modes, Some(modes[1]));
+
+        let displays = card.displays();
+        assert_eq!(displays.len(), 1);
+        assert_eq!(displays[0].connector, "DP-1");
+        assert_eq!(displays[0].mode, modes[1]);
+        assert_eq!(displays[0].modes, modes);
+        assert_eq!(displays[0].physical_size, Some((600, 340)));
+    }
+
+    #[test]
+    fn nvidia_reports_native_mode() {
+        // No current encoder, like the NVIDIA driver
+        let mut card = FakeCard::default();
+        let modes = [mode((3840, 2160), 60), mode((1920, 1080), 60)];
+        card.connect(1, "HDMI-A-1", &modes, None);
+
+        assert_eq!(card.displays()[0].mode, modes[0]);
+    }
+
+    #[test]
+    fn inactive_crtc_reports_native_mode() {
+        let mut card = FakeCard::default();
+        let modes = [mode((1920, 1200), 60)];
+        card.connect(1, "DP-1", &modes, Some(modes[0]));
+        card.crtcs[0].1 = None;
+
+        assert_eq!(card.displays()[0].mode, modes[0]);
+    }
+
+    #[test]
+    fn disconnected_connectors_are_skipped() {
+        let mut card = FakeCard::default();
+        card.disconnect(1, "DP-1");
+        let modes = [mode((1920, 1080), 60)];
+        card.connect(2, "DP-2", &modes, Some(modes[0]));
+        card.disconnect(3, "HDMI-A-1");
+
+        let displays = card.displays();
+        assert_eq!(displays.len(), 1);
+        assert_eq!(displays[0].connector, "DP-2");
+
+        let disconnected = card.connector(handle(1)).unwrap();
+        assert!(get_connector_mode(&card, Path::new(""), &disconnected).is_err());
+    }
+
+    #[test]
+    fn no_displays() {
+        let mut card = FakeCard::default();
+        card.disconnect(1, "eDP-1");
+        assert!(card.displays().is_empty());
+    }
+
+    #[test]
+    fn limit_stops_the_walk() {
+        let mut card = FakeCard::default();
+        let modes = [mode((1920, 1080), 60)];
+        card.connect(1, "HDMI-A-1", &modes, Some(modes[0]));
+        card.connect(2, "DP-2", &modes, Some(modes[0]));
+        card.disconnect(3, "DP-3");
+        card.connect(4, "DP-1", &modes, Some(modes[0]));
+
+        let displays = card_displays(&card, Path::new(""), Some(1), false).unwrap();
+        assert_eq!(displays.len(), 1);
+        assert_eq!(displays[0].connector, "DP-1");
+        // Only the first connector in display order was read
+        assert_eq!(card.connector_reads.get(), 1);
+
+        card.connector_reads.set(0);
+        let displays = card_displays(&card, Path::new(""), Some(2), false).unwrap();
+        assert_eq!(displays.len(), 2);
+        assert_eq!(card.connector_reads.get(), 2);
+    }
+
+    #[test]
+    fn displays_are_ordered_by_connector() {
+        // Handles in creation order, eg. MST connectors added after boot
+        let mut card = FakeCard::default();
+        let modes = [mode((1920, 1080), 60)];
+        for (id, name) in [
+            (1, "DP-10"),
+            (2, "HDMI-A-1"),
+            (3, "DP-2"),
+            (4, "eDP-1"),
+            (5, "Virtual-1"),
+            (6, "DP-1"),
+        ] {
+            card.connect(id, name, &modes, Some(modes[0]));
+        }
+
+        let connectors: Vec<_> = card.displays().into_iter().map(|d| d.connector).collect();
+        assert_eq!(
+            connectors,
+            ["eDP-1", "DP-1", "DP-2", "DP-10", "HDMI-A-1", "Virtual-1"]
+        );
+
+        // The limit applies to the ordered displays
+        let displays = card_displays(&card, Path::new(""), Some(2), false).unwrap();
+        assert_eq!(displays[1].connector, "DP-1");
+    }
+
+    #[test]
+    fn connector_order() {
+        assert!(super::connector_order("LVDS-1") < super::connector_order("VGA-1"));
+        assert!(super::connector_order("DSI-1") < super::connector_order("DP-1"));
+        assert!(super::connector_order("DP-1") < super::connector_order("HDMI-A-1"));
+        assert!(super::connector_order("HDMI-A-1") < super::connector_order("HDMI-A-2"));
+        assert!(super::connector_order("HDMI-A-2") < super::connector_order("HDMI-B-1"));
+        // Unknown names go last
+        assert!(super::connector_order("Virtual-1") < super::connector_order("XR-1"));
+        assert!(super::connector_order("DP-1") < super::connector_order("DP"));
+    }
+
+    #[test]
+    fn properties() {
+        let mut card = FakeCard::default();
+        let modes = [mode((1920, 1080), 60)];
+        card.connect(1, "DP-1", &modes, Some(modes[0]));
+        card.connect(2, "DP-2", &modes, Some(modes[0]));
+        card.properties.push((handle(1), "vrr_capable", 1));
+        card.properties.push((handle(1), "EDID", 7));
+        card.properties.push((handle(1), "max bpc", 10));
+        card.ranges.push((handle(1), "max bpc", (6, 16)));
+        card.blobs
+            .push((7, vec![0, 255, 255, 255, 255, 255, 255, 0]));
+
+        let displays = card.displays();
+        assert_eq!(displays[0].vrr_capable, Some(true));
+        assert_eq!(
+            displays[0].edid.as_deref(),
+            Some(&[0, 255, 255, 255, 255, 255, 255, 0][..])
+        );
+        assert_eq!(displays[0].properties.max_bpc, Some(10));
+        assert_eq!(displays[0].properties.max_bpc_range, Some((6, 16)));
+        assert_eq!(displays[1].vrr_capable, None);
+        assert_eq!(displays[1].edid, None);
+        assert_eq!(displays[1].properties.max_bpc, None);
+
+        // Not requested
+        let displays = card_displays(&card, Path::new(""), None, false).unwrap();
+        assert_eq!(displays[0].edid, None);
+    }
+
+    #[test]
+    fn fast_path_follows_connector_order() {
+        // Laptop: the panel's connector created after the external display's
+        let mut card = FakeCard::default();
+        let external = [mode((1920, 1080), 60)];
+        card.connect(1, "DP-1", &external, Some(external[0]));
+        let panel = [mode((2560, 1600), 165)];
+        card.connect(2, "eDP-1", &panel, Some(panel[0]));
+
+        assert_eq!(first_mode(&card, Path::new("")), Some(panel[0]));
+        assert_eq!(
+            first_mode(&card, Path::new("")),
+            Some(card.displays()[0].mode)
+        );
+    }
+
+    #[test]
+    fn fast_path_matches_the_probe() {
+        // Handheld: sideways panel after an external display in handle order
+        let mut card = FakeCard::default();
+        let external = [mode((1920, 1080), 60)];
+        card.connect(1, "DP-1", &external, Some(external[0]));
+        let panel = [mode((800, 1280), 60)];
+        card.connect(2, "eDP-1", &panel, Some(panel[0]));
+        card.properties.push((handle(2), "panel orientation", 3));
+
+        assert_eq!(
+            first_mode(&card, Path::new("")),
+            Some(mode((1280, 800), 60))
+        );
+        let mut displays = card_displays(&card, Path::new(""), None, false).unwrap();
+        displays[0].rotate_to_device();
+        assert_eq!(first_mode(&card, Path::new("")), Some(displays[0].mode));
+    }
+
+    #[test]
+    fn properties_are_read_once() {
+        let mut card = FakeCard::default();
+        let modes = [mode((1920, 1080), 60)];
+        card.connect(1, "eDP-1", &modes, Some(modes[0]));
+        card.connect(2, "DP-1", &modes, Some(modes[0]));
+        card.connect(3, "DP-2", &modes, Some(modes[0]));
+
+        // Only the panel's orientation is needed
+        card_displays(&card, Path::new(""), None, false).unwrap();
+        assert_eq!(card.property_reads.get(), 1);
+
+        card.property_reads.set(0);
+        card.displays();
+        assert_eq!(card.property_reads.get(), 3);
+    }
+}
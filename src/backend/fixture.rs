@@ -0,0 +1,652 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Recorded DRM state, replayed through the DRM detection
+//!
+//! A fixture is a JSON file describing the KMS objects of each card, so that a bug
+//! reporter's system can be reproduced without their hardware:
+//!
+//! ```json
+//! {"version": 1, "cards": [{
+//!     "path": "/dev/dri/card0", "driver": "amdgpu",
+//!     "connectors": [{"id": 80, "name": "eDP-1", "connected": true, "size": [344, 194],
+//!                     "encoder": 79, "modes": [[...]], "properties": {"vrr_capable": 1},
+//!                     "edid": "00ffffffffffff00..."}],
+//!     "encoders": [{"id": 79, "crtc": 60}],
+//!     "crtcs": [{"id": 60, "mode": [...]}]
+//! }]}
+//! ```
+//!
+//! Modes are arrays of their fields, in declaration order (see [`crate::Mode`]), EDIDs are
+//! hexadecimal strings. `encoder`, `crtc`, `mode`, `size` and `edid` can be `null`.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use drm::control::{connector, crtc, encoder, from_u32, Device as ControlDevice};
+use drm::Device;
+
+use crate::backend::drm::{
+    self as drm_backend, card_displays, connector_edid, ConnectorInfo, Kms, PropertyList,
+    PropertyValue,
+};
+use crate::backend::Backend;
+use crate::json::{self, Value};
+use crate::{modes, Display, Mode, ProbeOptions};
+
+/// Version of the fixture format
+pub const VERSION: u32 = 1;
+
+/// A recorded connector
+struct Connector {
+    handle: connector::Handle,
+    info: ConnectorInfo,
+    /// Numeric properties, by name
+    properties: Vec<(String, u64)>,
+    edid: Option<Vec<u8>>,
+}
+
+/// A recorded card
+pub(crate) struct Card {
+    path: PathBuf,
+    driver: String,
+    connectors: Vec<Connector>,
+    /// CRTC of each encoder
+    encoders: Vec<(encoder::Handle, Option<crtc::Handle>)>,
+    /// Mode of each CRTC
+    crtcs: Vec<(crtc::Handle, Option<Mode>)>,
+}
+
+impl Kms for Card {
+    fn connectors(&self) -> anyhow::Result<Vec<connector::Handle>> {
+        Ok(self.connectors.iter().map(|c| c.handle).collect())
+    }
+
+    fn connector(&self, handle: connector::Handle) -> anyhow::Result<ConnectorInfo> {
+        self.find_connector(handle).map(|c| c.info.clone())
+    }
+
+    fn encoder_crtc(&self, handle: encoder::Handle) -> anyhow::Result<Option<crtc::Handle>> {
+        find(&self.encoders, handle, "encoder")
+    }
+
+    fn crtc_mode(&self, handle: crtc::Handle) -> anyhow::Result<Option<Mode>> {
+        find(&self.crtcs, handle, "CRTC")
+    }
+
+    fn connector_properties(&self, handle: connector::Handle) -> anyhow::Result<PropertyList> {
+        let connector = self.find_connector(handle)?;
+        let mut properties: Vec<_> = connector
+            .properties
+            .iter()
+            .map(|(name, value)| PropertyValue {
+                name: name.clone(),
+                value: *value,
+                range: None,
+            })
+            .collect();
+        // The EDID blob has the connector's ID
+        if connector.edid.is_some() {
+            properties.push(PropertyValue {
+                name: "EDID".to_string(),
+                value: u32::from(handle).into(),
+                range: None,
+            });
+        }
+        Ok(PropertyList(properties))
+    }
+
+    fn property_blob(&self, blob: u64) -> anyhow::Result<Vec<u8>> {
+        let handle = u32::try_from(blob)
+            .ok()
+            .and_then(from_u32)
+            .context("invalid blob")?;
+        self.find_connector(handle)?
+            .edid
+            .clone()
+            .context("invalid blob")
+    }
+}
+
+/// Find an object by handle
+fn find<H: PartialEq + Copy, T: Clone>(list: &[(H, T)], handle: H, kind: &str) -> anyhow::Result<T>
+where
+    u32: From<H>,
+{
+    list.iter()
+        .find(|(h, _)| *h == handle)
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| anyhow::anyhow!("no {kind} {}", u32::from(handle)))
+}
+
+impl Card {
+    /// Write the object graph of the card, see [`Fixture::topology`]
+    fn topology(&self, text: &mut String) {
+        let _ = writeln!(text, "{} ({})", self.path.display(), self.driver);
+        let mut used_encoders = vec![];
+        let mut used_crtcs = vec![];
+        for connector in &self.connectors {
+            let info = &connector.info;
+            let state = if info.connected {
+                "connected"
+            } else {
+                "disconnected"
+            };
+            let _ = write!(
+                text,
+                "  connector {} {} ({state})",
+                u32::from(connector.handle),
+                info.name
+            );
+            let Some(encoder) = info.current_encoder else {
+                let _ = writeln!(
+                    text,
+                    " -> no encoder{}",
+                    if info.connected {
+                        ", the native mode is reported"
+                    } else {
+                        ""
+                    }
+                );
+                continue;
+            };
+            used_encoders.push(encoder);
+            let _ = write!(text, " -> encoder {}", u32::from(encoder));
+            let crtc = match find(&self.encoders, encoder, "encoder") {
+                Ok(Some(crtc)) => crtc,
+                Ok(None) => {
+                    let _ = writeln!(text, " -> no CRTC, the native mode is reported");
+                    continue;
+                }
+                Err(e) => {
+                    let _ = writeln!(text, " ({e})");
+                    continue;
+                }
+            };
+            used_crtcs.push(crtc);
+            let _ = write!(text, " -> CRTC {}", u32::from(crtc));
+            match find(&self.crtcs, crtc, "CRTC") {
+                Ok(Some(mode)) => {
+                    let _ = writeln!(text, ": {}", modes::describe(&mode));
+                }
+                Ok(None) => {
+                    let _ = writeln!(text, ": inactive, the native mode is reported");
+                }
+                Err(e) => {
+                    let _ = writeln!(text, " ({e})");
+                }
+            }
+        }
+        for (encoder, crtc) in &self.encoders {
+            if !used_encoders.contains(encoder) {
+                let _ = write!(text, "  encoder {} (unused)", u32::from(*encoder));
+                match crtc {
+                    Some(crtc) => {
+                        used_crtcs.push(*crtc);
+                        let _ = writeln!(text, " -> CRTC {}", u32::from(*crtc));
+                    }
+                    None => text.push('\n'),
+                }
+            }
+        }
+        for (crtc, mode) in &self.crtcs {
+            if !used_crtcs.contains(crtc) {
+                let _ = write!(text, "  CRTC {} (unused)", u32::from(*crtc));
+                match mode {
+                    Some(mode) => {
+                        let _ = writeln!(text, ": {}", modes::describe(mode));
+                    }
+                    None => text.push('\n'),
+                }
+            }
+        }
+    }
+
+    fn find_connector(&self, handle: connector::Handle) -> anyhow::Result<&Connector> {
+        self.connectors
+            .iter()
+            .find(|c| c.handle == handle)
+            .ok_or_else(|| anyhow::anyhow!("no connector {}", u32::from(handle)))
+    }
+
+    fn to_json(&self) -> Value {
+        let connectors = self.connectors.iter().map(|c| {
+            json::object([
+                ("id", u32::from(c.handle).into()),
+                ("name", c.info.name.as_str().into()),
+                ("connected", c.info.connected.into()),
+                ("size", c.info.size.into()),
+                ("encoder", c.info.current_encoder.map(u32::from).into()),
+                (
+                    "modes",
+                    Value::Array(c.info.modes.iter().map(modes::to_json).collect()),
+                ),
+                (
+                    "properties",
+                    Value::Object(
+                        c.properties
+                            .iter()
+                            .map(|(name, value)| (name.clone(), (*value).into()))
+                            .collect(),
+                    ),
+                ),
+                ("edid", c.edid.as_deref().map(json::to_hex).into()),
+            ])
+        });
+        let encoders = self.encoders.iter().map(|(handle, crtc)| {
+            json::object([
+                ("id", u32::from(*handle).into()),
+                ("crtc", crtc.map(u32::from).into()),
+            ])
+        });
+        let crtcs = self.crtcs.iter().map(|(handle, mode)| {
+            json::object([
+                ("id", u32::from(*handle).into()),
+                ("mode", mode.as_ref().map(modes::to_json).into()),
+            ])
+        });
+        json::object([
+            ("path", self.path.to_string_lossy().as_ref().into()),
+            ("driver", self.driver.as_str().into()),
+            ("connectors", Value::Array(connectors.collect())),
+            ("encoders", Value::Array(encoders.collect())),
+            ("crtcs", Value::Array(crtcs.collect())),
+        ])
+    }
+
+    fn from_json(value: &Value) -> anyhow::Result<Self> {
+        let connectors = value
+            .get("connectors")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .map(parse_connector)
+            .collect::<anyhow::Result<_>>()?;
+        let encoders = value
+            .get("encoders")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .map(|e| Ok((handle(e.get("id"))?, optional_handle(e.get("crtc"))?)))
+            .collect::<anyhow::Result<_>>()?;
+        let crtcs = value
+            .get("crtcs")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .map(|c| Ok((handle(c.get("id"))?, optional_mode(c.get("mode"))?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            path: PathBuf::from(string(value.get("path"), "path")?),
+            driver: string(value.get("driver"), "driver")?.to_string(),
+            connectors,
+            encoders,
+            crtcs,
+        })
+    }
+}
+
+fn string<'a>(value: Option<&'a Value>, name: &str) -> anyhow::Result<&'a str> {
+    value
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("invalid or missing {name}"))
+}
+
+fn handle<H: From<drm::control::RawResourceHandle>>(value: Option<&Value>) -> anyhow::Result<H> {
+    value
+        .and_then(Value::as_u64)
+        .and_then(|id| u32::try_from(id).ok())
+        .and_then(from_u32)
+        .context("invalid or missing id")
+}
+
+fn optional_handle<H: From<drm::control::RawResourceHandle>>(
+    value: Option<&Value>,
+) -> anyhow::Result<Option<H>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        value => handle(value).map(Some),
+    }
+}
+
+fn optional_mode(value: Option<&Value>) -> anyhow::Result<Option<Mode>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(mode) => modes::from_json(mode).map(Some).context("invalid mode"),
+    }
+}
+
+fn parse_size(value: &Value) -> Option<(u32, u32)> {
+    let [width, height] = value.items() else {
+        return None;
+    };
+    Some((
+        width.as_u64()?.try_into().ok()?,
+        height.as_u64()?.try_into().ok()?,
+    ))
+}
+
+fn parse_connector(value: &Value) -> anyhow::Result<Connector> {
+    let name = string(value.get("name"), "connector name")?;
+    let size = match value.get("size") {
+        None | Some(Value::Null) => None,
+        Some(size) => Some(parse_size(size).with_context(|| format!("invalid size of {name}"))?),
+    };
+    let modes = value
+        .get("modes")
+        .map(Value::items)
+        .unwrap_or_default()
+        .iter()
+        .map(modes::from_json)
+        .collect::<Option<_>>()
+        .with_context(|| format!("invalid modes of {name}"))?;
+    let properties = match value.get("properties") {
+        Some(Value::Object(fields)) => fields
+            .iter()
+            .map(|(k, v)| Some((k.clone(), v.as_u64()?)))
+            .collect::<Option<_>>()
+            .with_context(|| format!("invalid properties of {name}"))?,
+        _ => vec![],
+    };
+    let edid = match value.get("edid") {
+        None | Some(Value::Null) => None,
+        Some(edid) => Some(
+            edid.as_str()
+                .and_then(json::from_hex)
+                .with_context(|| format!("invalid EDID of {name}"))?,
+        ),
+    };
+    Ok(Connector {
+        handle: handle(value.get("id")).with_context(|| format!("invalid id of {name}"))?,
+        info: ConnectorInfo {
+            name: name.to_string(),
+            connected: value
+                .get("connected")
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+            modes,
+            size,
+            current_encoder: optional_handle(value.get("encoder"))
+                .with_context(|| format!("invalid encoder of {name}"))?,
+        },
+        properties,
+        edid,
+    })
+}
+
+/// Record every KMS object of a card
+fn capture_card(path: &Path) -> anyhow::Result<Card> {
+    let gpu = drm_backend::Card::open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let driver = gpu.get_driver()?.name().to_string_lossy().to_string();
+    let resources = gpu
+        .resource_handles()
+        .context("failed to get resource handles")?;
+
+    let mut connectors = vec![];
+    for handle in resources.connectors() {
+        let info = gpu.connector(*handle)?;
+        let list = gpu.connector_properties(*handle)?;
+        let properties = list
+            .0
+            .iter()
+            .filter(|p| p.name != "EDID")
+            .map(|p| (p.name.clone(), p.value))
+            .collect();
+        let edid = connector_edid(&gpu, path, &info.name, &list);
+        connectors.push(Connector {
+            handle: *handle,
+            info,
+            properties,
+            edid,
+        });
+    }
+    let encoders = resources
+        .encoders()
+        .iter()
+        .map(|handle| Ok((*handle, gpu.encoder_crtc(*handle)?)))
+        .collect::<anyhow::Result<_>>()?;
+    let crtcs = resources
+        .crtcs()
+        .iter()
+        .map(|handle| Ok((*handle, gpu.crtc_mode(*handle)?)))
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(Card {
+        path: path.to_path_buf(),
+        driver,
+        connectors,
+        encoders,
+        crtcs,
+    })
+}
+
+/// Displays detected from a fixture instead of the GPUs
+pub struct Fixture {
+    cards: Vec<Card>,
+}
+
+impl Fixture {
+    /// Parse a fixture
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let value = json::parse(text).context("invalid fixture")?;
+        match value.get("version").and_then(Value::as_u64) {
+            Some(version) if version == u64::from(VERSION) => {}
+            version => {
+                return Err(anyhow::anyhow!(
+                    "unsupported fixture version: {version:?}, expected {VERSION}"
+                ))
+            }
+        }
+        let cards = value
+            .get("cards")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .map(Card::from_json)
+            .collect::<anyhow::Result<_>>()
+            .context("invalid fixture")?;
+        Ok(Self { cards })
+    }
+
+    /// Read a fixture file
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    /// Record the state of the cards selected by the options (`rres dump`)
+    pub fn capture(options: &ProbeOptions) -> anyhow::Result<Self> {
+        let mut paths = crate::get_cards(options.card.clone())?;
+        options.gpu_policy.sort(&mut paths);
+        let mut cards = vec![];
+        for path in paths {
+            match capture_card(&path) {
+                Ok(card) => cards.push(card),
+                Err(e) => log::warn!("failed to record {}: {e:#}", path.display()),
+            }
+        }
+        Ok(Self { cards })
+    }
+
+    /// Mask the serial numbers of the EDIDs (see [`crate::edid::redact`]), to share it publicly
+    pub fn redact(&mut self) {
+        for card in &mut self.cards {
+            for connector in &mut card.connectors {
+                if let Some(edid) = &mut connector.edid {
+                    crate::edid::redact(edid);
+                }
+            }
+        }
+    }
+
+    /// Describe which connector uses which encoder and CRTC, and the unused objects
+    pub fn topology(&self) -> String {
+        let mut text = String::new();
+        for card in &self.cards {
+            card.topology(&mut text);
+        }
+        text
+    }
+
+    /// Serialize the fixture
+    pub fn to_json(&self) -> Value {
+        json::object([
+            ("version", VERSION.into()),
+            (
+                "cards",
+                Value::Array(self.cards.iter().map(Card::to_json).collect()),
+            ),
+        ])
+    }
+}
+
+impl Backend for Fixture {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn probe(&self, options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let mut displays = vec![];
+        for card in &self.cards {
+            if let Some(selected) = &options.card {
+                if card.path.file_name() != Some(selected.as_ref()) {
+                    continue;
+                }
+            }
+            log::debug!(card:% = card.path.display(); "Replaying {} ({})", card.path.display(), card.driver);
+            let limit = options.limit.map(|l| l.saturating_sub(displays.len()));
+            if limit == Some(0) {
+                break;
+            }
+            displays.extend(card_displays(card, &card.path, limit, options.edid)?);
+        }
+        Ok(displays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NVIDIA_DESKTOP: &str = include_str!("../../tests/fixtures/nvidia-desktop.json");
+    const AMD_LAPTOP: &str = include_str!("../../tests/fixtures/amd-laptop.json");
+
+    fn probe(fixture: &str, options: &ProbeOptions) -> Vec<Display> {
+        Fixture::parse(fixture).unwrap().probe(options).unwrap()
+    }
+
+    #[test]
+    fn nvidia_desktop() {
+        let displays = probe(NVIDIA_DESKTOP, &ProbeOptions::default());
+        assert_eq!(displays.len(), 2);
+        assert_eq!(displays[0].connector, "DP-2");
+        // No encoder: the native mode
+        assert_eq!(displays[0].mode.size(), (2560, 1440));
+        // Read with the other properties
+        assert_eq!(displays[0].vrr_capable, None);
+        assert_eq!(displays[1].connector, "HDMI-A-1");
+        assert_eq!(displays[1].mode.size(), (1920, 1080));
+
+        let options = ProbeOptions {
+            edid: true,
+            ..Default::default()
+        };
+        assert_eq!(probe(NVIDIA_DESKTOP, &options)[0].vrr_capable, Some(true));
+    }
+
+    #[test]
+    fn amd_laptop() {
+        let options = ProbeOptions {
+            edid: true,
+            ..Default::default()
+        };
+        let displays = probe(AMD_LAPTOP, &options);
+        assert_eq!(displays.len(), 2);
+        let panel = &displays[0];
+        assert_eq!(panel.connector, "eDP-1");
+        assert!(panel.is_internal());
+        // Running below the native mode
+        assert_eq!(panel.mode.size(), (1920, 1080));
+        assert_eq!(panel.modes[0].size(), (2560, 1600));
+        assert_eq!(panel.physical_size, Some((344, 215)));
+        assert!(panel.edid.as_deref().is_some_and(crate::edid::is_valid));
+        assert_eq!(displays[1].connector, "HDMI-A-1");
+        assert_eq!(displays[1].card, Path::new("/dev/dri/card1"));
+
+        let options = ProbeOptions {
+            card: Some("card1".to_string()),
+            ..Default::default()
+        };
+        let displays = probe(AMD_LAPTOP, &options);
+        assert_eq!(displays.len(), 1);
+        assert_eq!(displays[0].edid, None);
+    }
+
+    #[test]
+    fn round_trip() {
+        for fixture in [NVIDIA_DESKTOP, AMD_LAPTOP] {
+            let json = Fixture::parse(fixture).unwrap().to_json();
+            let again = Fixture::parse(&json.to_string()).unwrap().to_json();
+            assert_eq!(json, again);
+        }
+    }
+
+    #[test]
+    fn topology() {
+        let fixture = Fixture::parse(NVIDIA_DESKTOP).unwrap();
+        assert_eq!(
+            fixture.topology(),
+            "/dev/dri/card0 (nvidia-drm)
+  connector 65 DP-1 (disconnected) -> no encoder
+  connector 67 DP-2 (connected) -> no encoder, the native mode is reported
+  connector 69 HDMI-A-1 (connected) -> no encoder, the native mode is reported
+"
+        );
+
+        let fixture = Fixture::parse(
+            r#"{"version": 1, "cards": [{"path": "/dev/dri/card0", "driver": "i915",
+                "connectors": [{"id": 1, "name": "DP-1", "connected": true, "encoder": 2,
+                                "modes": []}],
+                "encoders": [{"id": 2, "crtc": 3}, {"id": 4, "crtc": null}],
+                "crtcs": [{"id": 3, "mode": null}, {"id": 5, "mode": null}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            fixture.topology(),
+            "/dev/dri/card0 (i915)
+  connector 1 DP-1 (connected) -> encoder 2 -> CRTC 3: inactive, the native mode is reported
+  encoder 4 (unused)
+  CRTC 5 (unused)
+"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Fixture::parse("{}").is_err());
+        assert!(Fixture::parse(r#"{"version": 2, "cards": []}"#).is_err());
+        assert!(Fixture::parse(
+            r#"{"version": 1, "cards": [{"path": "/dev/dri/card0", "driver": "i915",
+                "connectors": [{"id": 0, "name": "DP-1"}]}]}"#
+        )
+        .is_err());
+    }
+}
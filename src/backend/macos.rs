@@ -0,0 +1,182 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! macOS backend (CoreGraphics)
+//!
+//! Displays are the active CoreGraphics displays, main display first. Resolutions are in
+//! pixels, not points: a Retina display running "looks like 1440x900" reports 2880x1800.
+
+use std::ffi::c_void;
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::backend::Backend;
+use crate::{modes, Display, Mode, ProbeOptions};
+
+type DisplayId = u32;
+type DisplayModeRef = *const c_void;
+type ArrayRef = *const c_void;
+
+/// `CGSize`
+#[repr(C)]
+struct Size {
+    width: f64,
+    height: f64,
+}
+
+/// `kDisplayModeInterlacedFlag`
+const IO_INTERLACED: u32 = 0x0000_0002;
+/// `kDisplayModeNativeFlag`
+const IO_NATIVE: u32 = 0x0200_0000;
+/// Maximum number of displays read
+const MAX_DISPLAYS: usize = 32;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGGetActiveDisplayList(max: u32, displays: *mut DisplayId, count: *mut u32) -> i32;
+    fn CGMainDisplayID() -> DisplayId;
+    fn CGDisplayIsBuiltin(display: DisplayId) -> u32;
+    fn CGDisplayScreenSize(display: DisplayId) -> Size;
+    fn CGDisplayCopyDisplayMode(display: DisplayId) -> DisplayModeRef;
+    fn CGDisplayCopyAllDisplayModes(display: DisplayId, options: *const c_void) -> ArrayRef;
+    fn CGDisplayModeGetPixelWidth(mode: DisplayModeRef) -> usize;
+    fn CGDisplayModeGetPixelHeight(mode: DisplayModeRef) -> usize;
+    fn CGDisplayModeGetRefreshRate(mode: DisplayModeRef) -> f64;
+    fn CGDisplayModeGetIOFlags(mode: DisplayModeRef) -> u32;
+    fn CGDisplayModeRelease(mode: DisplayModeRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFArrayGetCount(array: ArrayRef) -> isize;
+    fn CFArrayGetValueAtIndex(array: ArrayRef, index: isize) -> *const c_void;
+    fn CFRelease(object: *const c_void);
+}
+
+/// Convert a CoreGraphics display mode
+///
+/// # Safety
+///
+/// `mode` must be a valid `CGDisplayModeRef`.
+unsafe fn convert_mode(mode: DisplayModeRef) -> Option<Mode> {
+    let mut converted = Mode::from_size((
+        CGDisplayModeGetPixelWidth(mode).try_into().ok()?,
+        CGDisplayModeGetPixelHeight(mode).try_into().ok()?,
+    ));
+    // Built-in panels report 0
+    converted.vrefresh = CGDisplayModeGetRefreshRate(mode).round() as u32;
+    let io_flags = CGDisplayModeGetIOFlags(mode);
+    if io_flags & IO_INTERLACED != 0 {
+        converted.flags |= modes::FLAG_INTERLACE;
+    }
+    if io_flags & IO_NATIVE != 0 {
+        converted.mode_type |= modes::TYPE_PREFERRED;
+    }
+    Some(converted)
+}
+
+/// Get every mode of a display, native mode first
+fn display_modes(display: DisplayId) -> Vec<Mode> {
+    let mut supported = vec![];
+    // SAFETY: the array is owned by us and released once read, its values are display modes
+    unsafe {
+        let array = CGDisplayCopyAllDisplayModes(display, ptr::null());
+        if array.is_null() {
+            return supported;
+        }
+        for index in 0..CFArrayGetCount(array) {
+            if let Some(mode) = convert_mode(CFArrayGetValueAtIndex(array, index)) {
+                if !supported.contains(&mode) {
+                    supported.push(mode);
+                }
+            }
+        }
+        CFRelease(array);
+    }
+    supported.sort_by_key(|m| !modes::is_preferred(m));
+    supported
+}
+
+/// macOS display detection
+pub struct CoreGraphics;
+
+impl Backend for CoreGraphics {
+    fn name(&self) -> &'static str {
+        "coregraphics"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let mut ids = [0; MAX_DISPLAYS];
+        let mut count = 0;
+        // SAFETY: ids has room for MAX_DISPLAYS displays
+        let error =
+            unsafe { CGGetActiveDisplayList(MAX_DISPLAYS as u32, ids.as_mut_ptr(), &mut count) };
+        if error != 0 {
+            return Err(anyhow::anyhow!(
+                "failed to list the displays (error {error})"
+            ));
+        }
+        let mut ids = ids[..count as usize].to_vec();
+        // SAFETY: plain query
+        let main = unsafe { CGMainDisplayID() };
+        ids.sort_by_key(|id| *id != main);
+
+        let mut displays = vec![];
+        for id in ids {
+            // SAFETY: the mode is released once converted
+            let mode = unsafe {
+                let current = CGDisplayCopyDisplayMode(id);
+                if current.is_null() {
+                    log::warn!("failed to read the current mode of display {id}");
+                    continue;
+                }
+                let mode = convert_mode(current);
+                CGDisplayModeRelease(current);
+                mode
+            };
+            let Some(mode) = mode else {
+                continue;
+            };
+
+            // SAFETY: plain queries
+            let (builtin, size) = unsafe { (CGDisplayIsBuiltin(id) != 0, CGDisplayScreenSize(id)) };
+            let connector = if builtin {
+                format!("Built-in-{id}")
+            } else {
+                format!("Display-{id}")
+            };
+            log::debug!(
+                "Found display: {connector}, {}x{}",
+                mode.size().0,
+                mode.size().1
+            );
+            displays.push(Display {
+                card: PathBuf::from("coregraphics"),
+                connector,
+                mode,
+                modes: display_modes(id),
+                physical_size: Some((size.width.round() as u32, size.height.round() as u32))
+                    .filter(|s| s.0 > 0 && s.1 > 0),
+                vrr_capable: None,
+                edid: None,
+                properties: Default::default(),
+            });
+        }
+
+        Ok(displays)
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Mutter backend (GNOME's `org.gnome.Mutter.DisplayConfig` D-Bus service)
+//!
+//! Unlike Wayland's `wl_output`, Mutter lists every mode of the monitors. Nothing is read
+//! from `/dev/dri`, which suits unprivileged contexts.
+
+use std::path::PathBuf;
+
+use crate::backend::Backend;
+use crate::dbus::{Connection, Value};
+use crate::{modes, Display, Mode, ProbeOptions};
+
+const SERVICE: &str = "org.gnome.Mutter.DisplayConfig";
+const PATH: &str = "/org/gnome/Mutter/DisplayConfig";
+/// `layout-mode` where logical monitors are sized in physical pixels (X11 sessions)
+const LAYOUT_PHYSICAL: i64 = 2;
+
+/// Convert a Mutter mode, `(id, width, height, refresh, scale, scales, properties)`
+fn convert_mode(mode: &Value) -> Option<(Mode, bool)> {
+    let [_, width, height, refresh, _, _, properties] = mode.items() else {
+        return None;
+    };
+    let mut converted = Mode::from_size((
+        width.as_i64()?.try_into().ok()?,
+        height.as_i64()?.try_into().ok()?,
+    ));
+    converted.vrefresh = refresh.as_f64()?.round() as u32;
+    if properties.get("is-preferred").and_then(Value::as_bool) == Some(true) {
+        converted.mode_type |= modes::TYPE_PREFERRED;
+    }
+    if properties.get("is-interlaced").and_then(Value::as_bool) == Some(true) {
+        converted.flags |= modes::FLAG_INTERLACE;
+    }
+    let current = properties.get("is-current").and_then(Value::as_bool) == Some(true);
+    Some((converted, current))
+}
+
+/// GNOME display detection
+pub struct Mutter;
+
+impl Backend for Mutter {
+    fn name(&self) -> &'static str {
+        "mutter"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let mut connection = Connection::session()?;
+        let state = connection.call(SERVICE, PATH, SERVICE, "GetCurrentState")?;
+        // (serial, monitors, logical monitors, properties)
+        let [_, monitors, logical_monitors, _] = state.as_slice() else {
+            return Err(anyhow::anyhow!("unexpected reply from {SERVICE}"));
+        };
+
+        // Connector of the primary monitor
+        let primary = logical_monitors
+            .items()
+            .iter()
+            .find(|l| l.items().get(4).and_then(Value::as_bool) == Some(true))
+            .and_then(|l| l.items().get(5)?.items().first()?.items().first()?.as_str());
+
+        let mut displays = vec![];
+        // (spec (connector, vendor, product, serial), modes, properties)
+        for monitor in monitors.items() {
+            let [spec, monitor_modes, _] = monitor.items() else {
+                continue;
+            };
+            let Some(connector) = spec.items().first().and_then(Value::as_str) else {
+                continue;
+            };
+
+            let mut current = None;
+            let mut supported = vec![];
+            for (mode, is_current) in monitor_modes.items().iter().filter_map(convert_mode) {
+                if is_current {
+                    current = Some(mode);
+                }
+                if !supported.contains(&mode) {
+                    supported.push(mode);
+                }
+            }
+            // Disabled monitors have no current mode
+            let Some(mode) = current else {
+                log::debug!("Skipping disabled monitor {connector}");
+                continue;
+            };
+            supported.sort_by_key(|m| !modes::is_preferred(m));
+
+            log::debug!(
+                "Found display: {connector}, {}x{}",
+                mode.size().0,
+                mode.size().1
+            );
+            displays.push(Display {
+                card: PathBuf::from(SERVICE),
+                connector: connector.to_string(),
+                mode,
+                modes: supported,
+                physical_size: None,
+                vrr_capable: None,
+                edid: None,
+                properties: Default::default(),
+            });
+        }
+        displays.sort_by_key(|d| Some(d.connector.as_str()) != primary);
+
+        Ok(displays)
+    }
+
+    fn scales(&self) -> anyhow::Result<Vec<(String, f64)>> {
+        let mut connection = Connection::session()?;
+        let state = connection.call(SERVICE, PATH, SERVICE, "GetCurrentState")?;
+        let [_, _, logical_monitors, properties] = state.as_slice() else {
+            return Err(anyhow::anyhow!("unexpected reply from {SERVICE}"));
+        };
+        let physical =
+            properties.get("layout-mode").and_then(Value::as_i64) == Some(LAYOUT_PHYSICAL);
+
+        let mut scales = vec![];
+        // (x, y, scale, transform, primary, monitors (connector, vendor, product, serial),
+        // properties)
+        for logical in logical_monitors.items() {
+            let Some(scale) = logical.items().get(2).and_then(Value::as_f64) else {
+                continue;
+            };
+            // Applications still get physical pixels
+            let scale = if physical { 1.0 } else { scale };
+            let Some(monitors) = logical.items().get(5) else {
+                continue;
+            };
+            for spec in monitors.items() {
+                if let Some(connector) = spec.items().first().and_then(Value::as_str) {
+                    scales.push((connector.to_string(), scale));
+                }
+            }
+        }
+
+        Ok(scales)
+    }
+}
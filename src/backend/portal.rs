@@ -0,0 +1,189 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! ScreenCast portal backend (`org.freedesktop.portal.ScreenCast`)
+//!
+//! Starting a monitor screencast makes the desktop ask the user which monitors to share, and
+//! the portal reports the logical size and position of each. No stream is opened: the
+//! session is closed right after. Works in Flatpak and without any access to `/dev/dri`,
+//! but the sizes are logical (scaled) and there's no refresh rate.
+
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use crate::backend::Backend;
+use crate::dbus::{Arg, Connection, Value};
+use crate::{Display, Mode, ProbeOptions};
+
+const SERVICE: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREEN_CAST: &str = "org.freedesktop.portal.ScreenCast";
+/// `types` of SelectSources
+const SOURCE_MONITOR: u32 = 1;
+/// Time to answer the portal requests, except Start
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Time for the user to pick the monitors
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A connection to the portal
+struct Portal {
+    connection: Connection,
+    /// Tokens created so far
+    tokens: u32,
+}
+
+impl Portal {
+    /// Get a new handle token
+    fn token(&mut self) -> String {
+        self.tokens += 1;
+        format!("rres{}_{}", process::id(), self.tokens)
+    }
+
+    /// Call a method returning a request, and wait for its results
+    ///
+    /// `options` are passed after `args`, with the handle token.
+    fn request(
+        &mut self,
+        method: &str,
+        args: &[Arg],
+        options: &[(&str, Arg)],
+        timeout: Duration,
+    ) -> anyhow::Result<Value> {
+        let token = self.token();
+        // The request's path is known in advance, to subscribe before calling
+        let sender = self
+            .connection
+            .unique_name()
+            .trim_start_matches(':')
+            .replace('.', "_");
+        let request = format!("{PATH}/request/{sender}/{token}");
+        self.connection.call_with(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "AddMatch",
+            &[Arg::Str(&format!(
+                "type='signal',interface='org.freedesktop.portal.Request',member='Response',path='{request}'"
+            ))],
+        )?;
+
+        let mut all_options = vec![("handle_token", Arg::Str(&token))];
+        all_options.extend_from_slice(options);
+        let mut all_args = args.to_vec();
+        all_args.push(Arg::Options(&all_options));
+        self.connection
+            .call_with(SERVICE, PATH, SCREEN_CAST, method, &all_args)?;
+
+        let response = self.connection.wait_signal(&request, "Response", timeout)?;
+        match response.as_slice() {
+            [code, results] if code.as_i64() == Some(0) => Ok(results.clone()),
+            [code, _] if code.as_i64() == Some(1) => Err(anyhow::anyhow!("{method} was cancelled")),
+            _ => Err(anyhow::anyhow!("{method} failed")),
+        }
+    }
+}
+
+/// Convert a stream, `(node id, properties)`
+fn convert_stream(stream: &Value) -> Option<Display> {
+    let [node, properties] = stream.items() else {
+        return None;
+    };
+    let [width, height] = properties.get("size")?.items() else {
+        return None;
+    };
+    let mode = Mode::from_size((
+        width.as_i64()?.try_into().ok()?,
+        height.as_i64()?.try_into().ok()?,
+    ));
+    let connector = format!("portal-{}", node.as_i64()?);
+    log::debug!(
+        "Found display: {connector}, {}x{} (logical)",
+        mode.size().0,
+        mode.size().1
+    );
+    Some(Display {
+        card: PathBuf::from(SCREEN_CAST),
+        connector,
+        mode,
+        modes: vec![mode],
+        physical_size: None,
+        vrr_capable: None,
+        edid: None,
+        properties: Default::default(),
+    })
+}
+
+/// Display detection through the ScreenCast portal
+pub struct ScreenCast;
+
+impl Backend for ScreenCast {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let mut portal = Portal {
+            connection: Connection::session()?,
+            tokens: 0,
+        };
+
+        let session_token = portal.token();
+        let session = portal.request(
+            "CreateSession",
+            &[],
+            &[("session_handle_token", Arg::Str(&session_token))],
+            TIMEOUT,
+        )?;
+        let session = session
+            .get("session_handle")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("the portal returned no session"))?
+            .to_string();
+
+        let result = (|| {
+            portal.request(
+                "SelectSources",
+                &[Arg::ObjectPath(&session)],
+                &[
+                    ("types", Arg::U32(SOURCE_MONITOR)),
+                    ("multiple", Arg::Bool(true)),
+                ],
+                TIMEOUT,
+            )?;
+            log::info!("Waiting for the monitors to be selected");
+            portal.request(
+                "Start",
+                &[Arg::ObjectPath(&session), Arg::Str("")],
+                &[],
+                SELECTION_TIMEOUT,
+            )
+        })();
+        // Nothing is streamed
+        if let Err(e) =
+            portal
+                .connection
+                .call(SERVICE, &session, "org.freedesktop.portal.Session", "Close")
+        {
+            log::debug!("Failed to close the portal session: {e:#}");
+        }
+
+        let results = result?;
+        let streams = results.get("streams").map(Value::items).unwrap_or_default();
+        Ok(streams.iter().filter_map(convert_stream).collect())
+    }
+}
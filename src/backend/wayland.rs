@@ -0,0 +1,420 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Wayland backend
+//!
+//! Displays are the compositor's `wl_output`s, read with a minimal client speaking the wire
+//! protocol directly. Compositors usually only advertise the current mode.
+//!
+//! Scales are read from xdg-output's logical sizes when available, as `wl_output.scale` is
+//! rounded up to an integer.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::backend::Backend;
+use crate::{modes, Display, Mode, ProbeOptions};
+
+/// `wl_display` object ID
+const DISPLAY_ID: u32 = 1;
+/// ID of the `wl_registry` we create
+const REGISTRY_ID: u32 = 2;
+/// `wl_output` version bound, the first one with output names
+const OUTPUT_VERSION: u32 = 4;
+/// `zxdg_output_manager_v1` version bound, logical sizes are in the first one
+const XDG_OUTPUT_VERSION: u32 = 1;
+/// `wl_output.mode` flag of the current mode
+const MODE_CURRENT: u32 = 0x1;
+/// `wl_output.mode` flag of the preferred mode
+const MODE_PREFERRED: u32 = 0x2;
+/// Time to wait for the compositor's answers
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A `wl_output` being read
+#[derive(Default)]
+struct Output {
+    name: Option<String>,
+    physical_size: Option<(u32, u32)>,
+    current: Option<Mode>,
+    modes: Vec<Mode>,
+    /// Integer scale (`wl_output.scale`)
+    scale: Option<i32>,
+    /// Size in the compositor's space (`zxdg_output_v1.logical_size`)
+    logical_size: Option<(i32, i32)>,
+}
+
+impl Output {
+    /// Scale factor, fractional when the compositor gives the logical size
+    fn scale(&self) -> Option<f64> {
+        let mode = self.current.or(self.modes.first().copied())?;
+        self.logical_size
+            .and_then(|logical| fractional_scale(mode.size(), logical))
+            .or(self.scale.filter(|&s| s > 0).map(f64::from))
+    }
+}
+
+/// Scale factor from the mode's size and the logical size
+///
+/// The largest sides are compared, as rotated outputs have their logical size rotated.
+fn fractional_scale(size: (u16, u16), logical: (i32, i32)) -> Option<f64> {
+    let logical = logical.0.max(logical.1);
+    if logical <= 0 {
+        return None;
+    }
+    // Sizes are rounded by the compositor (eg. 1706 for 2560 at 1.5), while scales are in
+    // 120ths (wp_fractional_scale_v1)
+    let scale = f64::from(size.0.max(size.1)) / f64::from(logical);
+    Some((scale * 120.0).round() / 120.0)
+}
+
+/// A minimal Wayland connection
+struct Connection {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    next_id: u32,
+}
+
+/// Append a `u32` argument
+fn push_u32(message: &mut Vec<u8>, value: u32) {
+    message.extend_from_slice(&value.to_ne_bytes());
+}
+
+/// Append a string argument (length with the NUL terminator, padded to 32 bits)
+fn push_string(message: &mut Vec<u8>, value: &str) {
+    push_u32(message, value.len() as u32 + 1);
+    message.extend_from_slice(value.as_bytes());
+    message.push(0);
+    message.resize(message.len().div_ceil(4) * 4, 0);
+}
+
+/// Arguments of an event
+struct Arguments<'a>(&'a [u8]);
+
+impl Arguments<'_> {
+    fn u32(&mut self) -> Option<u32> {
+        let (value, rest) = self.0.split_first_chunk::<4>()?;
+        self.0 = rest;
+        Some(u32::from_ne_bytes(*value))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|v| v as i32)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        let padded = length.div_ceil(4) * 4;
+        let bytes = self.0.get(..padded)?;
+        self.0 = &self.0[padded..];
+        let value = bytes.get(..length.saturating_sub(1))?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+}
+
+impl Connection {
+    /// Connect to the compositor (`WAYLAND_DISPLAY`)
+    fn open() -> anyhow::Result<(Self, String)> {
+        let name = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+        let mut path = PathBuf::from(&name);
+        if path.is_relative() {
+            let runtime_dir = env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR not set")?;
+            path = PathBuf::from(runtime_dir).join(&name);
+        }
+        let stream = UnixStream::connect(&path)
+            .with_context(|| format!("failed to connect to {}", path.display()))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok((
+            Self {
+                stream,
+                buffer: vec![],
+                next_id: REGISTRY_ID + 1,
+            },
+            name,
+        ))
+    }
+
+    /// Allocate a new object ID
+    fn new_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Send a request
+    fn send(&mut self, object: u32, opcode: u16, arguments: &[u8]) -> anyhow::Result<()> {
+        let mut message = vec![];
+        push_u32(&mut message, object);
+        push_u32(
+            &mut message,
+            ((8 + arguments.len() as u32) << 16) | opcode as u32,
+        );
+        message.extend_from_slice(arguments);
+        self.stream.write_all(&message)?;
+        Ok(())
+    }
+
+    /// Send `wl_display.sync`, returning the callback ID
+    fn sync(&mut self) -> anyhow::Result<u32> {
+        let callback = self.new_id();
+        self.send(DISPLAY_ID, 0, &callback.to_ne_bytes())?;
+        Ok(callback)
+    }
+
+    /// Bind a global (`wl_registry.bind`)
+    fn bind(&mut self, name: u32, interface: &str, version: u32, id: u32) -> anyhow::Result<()> {
+        let mut arguments = vec![];
+        push_u32(&mut arguments, name);
+        push_string(&mut arguments, interface);
+        push_u32(&mut arguments, version);
+        push_u32(&mut arguments, id);
+        self.send(REGISTRY_ID, 0, &arguments)
+    }
+
+    /// Read the next event, as (object, opcode, arguments)
+    fn event(&mut self) -> anyhow::Result<(u32, u16, Vec<u8>)> {
+        loop {
+            if self.buffer.len() >= 8 {
+                let header = u32::from_ne_bytes(self.buffer[4..8].try_into()?);
+                let size = (header >> 16) as usize;
+                if size < 8 {
+                    return Err(anyhow::anyhow!("invalid message from the compositor"));
+                }
+                if self.buffer.len() >= size {
+                    let object = u32::from_ne_bytes(self.buffer[..4].try_into()?);
+                    let arguments = self.buffer[8..size].to_vec();
+                    self.buffer.drain(..size);
+                    return Ok((object, (header & 0xffff) as u16, arguments));
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let read = self
+                .stream
+                .read(&mut chunk)
+                .context("failed to read from the compositor")?;
+            if read == 0 {
+                return Err(anyhow::anyhow!("the compositor closed the connection"));
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Handle events until the callback is done, passing the others to `handle`
+    fn roundtrip(
+        &mut self,
+        callback: u32,
+        mut handle: impl FnMut(u32, u16, Arguments),
+    ) -> anyhow::Result<()> {
+        loop {
+            let (object, opcode, arguments) = self.event()?;
+            match (object, opcode) {
+                (o, 0) if o == callback => return Ok(()),
+                (DISPLAY_ID, 0) => {
+                    let mut arguments = Arguments(&arguments);
+                    let (_, code, message) = (arguments.u32(), arguments.u32(), arguments.string());
+                    return Err(anyhow::anyhow!(
+                        "compositor error {}: {}",
+                        code.unwrap_or_default(),
+                        message.unwrap_or_default()
+                    ));
+                }
+                // wl_display.delete_id
+                (DISPLAY_ID, _) => {}
+                _ => handle(object, opcode, Arguments(&arguments)),
+            }
+        }
+    }
+}
+
+/// Bind every output and read their state, with the socket name
+fn read_outputs() -> anyhow::Result<(String, Vec<Output>)> {
+    let (mut connection, socket) = Connection::open()?;
+
+    // List the globals
+    connection.send(DISPLAY_ID, 1, &REGISTRY_ID.to_ne_bytes())?;
+    let callback = connection.sync()?;
+    let mut globals = vec![];
+    let mut xdg_output_manager = None;
+    connection.roundtrip(callback, |object, opcode, mut arguments| {
+        if (object, opcode) != (REGISTRY_ID, 0) {
+            return;
+        }
+        if let (Some(name), Some(interface), Some(version)) =
+            (arguments.u32(), arguments.string(), arguments.u32())
+        {
+            match interface.as_str() {
+                "wl_output" => globals.push((name, version)),
+                "zxdg_output_manager_v1" => xdg_output_manager = Some(name),
+                _ => {}
+            }
+        }
+    })?;
+
+    // Bind every output, they send their state right away
+    let mut outputs = BTreeMap::new();
+    for (name, version) in globals {
+        let id = connection.new_id();
+        connection.bind(name, "wl_output", version.min(OUTPUT_VERSION), id)?;
+        outputs.insert(id, Output::default());
+    }
+    // Their logical size, for fractional scales
+    let mut xdg_outputs = BTreeMap::new();
+    if let Some(name) = xdg_output_manager {
+        let manager = connection.new_id();
+        connection.bind(name, "zxdg_output_manager_v1", XDG_OUTPUT_VERSION, manager)?;
+        for &output in outputs.keys() {
+            let id = connection.new_id();
+            let mut arguments = vec![];
+            push_u32(&mut arguments, id);
+            push_u32(&mut arguments, output);
+            // get_xdg_output
+            connection.send(manager, 1, &arguments)?;
+            xdg_outputs.insert(id, output);
+        }
+    }
+    let callback = connection.sync()?;
+    connection.roundtrip(callback, |object, opcode, mut arguments| {
+        // zxdg_output_v1.logical_size
+        if let Some(output) = xdg_outputs.get(&object) {
+            if let (1, Some(width), Some(height)) = (opcode, arguments.i32(), arguments.i32()) {
+                if let Some(output) = outputs.get_mut(output) {
+                    output.logical_size = Some((width, height));
+                }
+            }
+            return;
+        }
+        let Some(output) = outputs.get_mut(&object) else {
+            return;
+        };
+        match opcode {
+            // geometry
+            0 => {
+                let (_, _, width, height) = (
+                    arguments.i32(),
+                    arguments.i32(),
+                    arguments.i32(),
+                    arguments.i32(),
+                );
+                if let (Some(width), Some(height)) = (width, height) {
+                    output.physical_size =
+                        Some((width as u32, height as u32)).filter(|_| width > 0 && height > 0);
+                }
+            }
+            // mode
+            1 => {
+                let (Some(flags), Some(width), Some(height), Some(refresh)) = (
+                    arguments.u32(),
+                    arguments.i32(),
+                    arguments.i32(),
+                    arguments.i32(),
+                ) else {
+                    return;
+                };
+                let (Ok(width), Ok(height)) = (width.try_into(), height.try_into()) else {
+                    return;
+                };
+                let mut mode = Mode::from_size((width, height));
+                // In mHz
+                mode.vrefresh = (refresh.max(0) as u32 + 500) / 1000;
+                if flags & MODE_PREFERRED != 0 {
+                    mode.mode_type |= modes::TYPE_PREFERRED;
+                }
+                if flags & MODE_CURRENT != 0 {
+                    output.current = Some(mode);
+                }
+                if !output.modes.contains(&mode) {
+                    output.modes.push(mode);
+                }
+            }
+            // scale
+            3 => output.scale = arguments.i32(),
+            // name
+            4 => output.name = arguments.string(),
+            _ => {}
+        }
+    })?;
+
+    Ok((socket, outputs.into_values().collect()))
+}
+
+/// Wayland display detection
+pub struct Wayland;
+
+impl Backend for Wayland {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let (socket, outputs) = read_outputs()?;
+
+        let mut displays = vec![];
+        for (index, output) in outputs.into_iter().enumerate() {
+            let Some(mode) = output.current.or(output.modes.first().copied()) else {
+                continue;
+            };
+            let mut supported = output.modes;
+            supported.sort_by_key(|m| !modes::is_preferred(m));
+            let connector = output.name.unwrap_or_else(|| format!("wayland-{index}"));
+            log::debug!(
+                "Found display: {connector}, {}x{}",
+                mode.size().0,
+                mode.size().1
+            );
+            displays.push(Display {
+                card: PathBuf::from(&socket),
+                connector,
+                mode,
+                modes: supported,
+                physical_size: output.physical_size,
+                vrr_capable: None,
+                edid: None,
+                properties: Default::default(),
+            });
+        }
+
+        Ok(displays)
+    }
+
+    fn scales(&self) -> anyhow::Result<Vec<(String, f64)>> {
+        let (_, outputs) = read_outputs()?;
+        Ok(outputs
+            .iter()
+            .filter_map(|o| Some((o.name.clone()?, o.scale()?)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale() {
+        assert_eq!(fractional_scale((3840, 2160), (2560, 1440)), Some(1.5));
+        assert_eq!(fractional_scale((2560, 1600), (1706, 1066)), Some(1.5));
+        assert_eq!(fractional_scale((2560, 1600), (2048, 1280)), Some(1.25));
+        // Rotated
+        assert_eq!(fractional_scale((1920, 1080), (540, 960)), Some(2.0));
+        assert_eq!(fractional_scale((1920, 1080), (0, 0)), None);
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Windows backend
+//!
+//! Displays are the desktop's display devices (`\\.\DISPLAY1`...), with their modes from
+//! `EnumDisplaySettingsExW`. Windows doesn't flag the native mode, the largest one is
+//! reported as preferred instead.
+
+use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::backend::Backend;
+use crate::{modes, Display, Mode, ProbeOptions};
+
+/// `DISPLAY_DEVICEW`
+#[repr(C)]
+struct DisplayDevice {
+    cb: u32,
+    device_name: [u16; 32],
+    device_string: [u16; 128],
+    state_flags: u32,
+    device_id: [u16; 128],
+    device_key: [u16; 128],
+}
+
+/// `DEVMODEW`, display fields only
+#[repr(C)]
+struct DevMode {
+    device_name: [u16; 32],
+    spec_version: u16,
+    driver_version: u16,
+    size: u16,
+    driver_extra: u16,
+    fields: u32,
+    position: [i32; 2],
+    display_orientation: u32,
+    display_fixed_output: u32,
+    color: i16,
+    duplex: i16,
+    y_resolution: i16,
+    tt_option: i16,
+    collate: i16,
+    form_name: [u16; 32],
+    log_pixels: u16,
+    bits_per_pel: u32,
+    pels_width: u32,
+    pels_height: u32,
+    display_flags: u32,
+    display_frequency: u32,
+    icm_method: u32,
+    icm_intent: u32,
+    media_type: u32,
+    dither_type: u32,
+    reserved1: u32,
+    reserved2: u32,
+    panning_width: u32,
+    panning_height: u32,
+}
+
+const _: () = assert!(mem::size_of::<DisplayDevice>() == 840);
+const _: () = assert!(mem::size_of::<DevMode>() == 220);
+
+/// `DISPLAY_DEVICE_ATTACHED_TO_DESKTOP`
+const ATTACHED_TO_DESKTOP: u32 = 0x1;
+/// `DM_INTERLACED`
+const DM_INTERLACED: u32 = 0x2;
+/// `ENUM_CURRENT_SETTINGS`
+const ENUM_CURRENT_SETTINGS: u32 = u32::MAX;
+
+#[link(name = "user32")]
+extern "system" {
+    fn EnumDisplayDevicesW(
+        device: *const u16,
+        device_num: u32,
+        display_device: *mut DisplayDevice,
+        flags: u32,
+    ) -> i32;
+    fn EnumDisplaySettingsExW(
+        device_name: *const u16,
+        mode_num: u32,
+        dev_mode: *mut DevMode,
+        flags: u32,
+    ) -> i32;
+}
+
+/// Convert a NUL terminated UTF-16 buffer
+fn from_wide(buffer: &[u16]) -> String {
+    let end = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..end])
+}
+
+/// Get a display device's mode, by index or `ENUM_CURRENT_SETTINGS`
+fn display_mode(device_name: &[u16], index: u32) -> Option<Mode> {
+    // SAFETY: DEVMODEW is plain data, dmSize tells Windows which version we use
+    let mut dev_mode: DevMode = unsafe { mem::zeroed() };
+    dev_mode.size = mem::size_of::<DevMode>() as u16;
+    // SAFETY: device_name is NUL terminated and dev_mode is a valid DEVMODEW
+    if unsafe { EnumDisplaySettingsExW(device_name.as_ptr(), index, &mut dev_mode, 0) } == 0 {
+        return None;
+    }
+
+    let mut mode = Mode::from_size((
+        dev_mode.pels_width.try_into().ok()?,
+        dev_mode.pels_height.try_into().ok()?,
+    ));
+    // 0 and 1 mean the hardware default refresh rate
+    if dev_mode.display_frequency > 1 {
+        mode.vrefresh = dev_mode.display_frequency;
+    }
+    if dev_mode.display_flags & DM_INTERLACED != 0 {
+        mode.flags |= modes::FLAG_INTERLACE;
+    }
+    Some(mode)
+}
+
+/// Windows display detection
+pub struct Windows;
+
+impl Backend for Windows {
+    fn name(&self) -> &'static str {
+        "windows"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let mut displays = vec![];
+
+        for index in 0.. {
+            // SAFETY: DISPLAY_DEVICEW is plain data, cb tells Windows which version we use
+            let mut device: DisplayDevice = unsafe { mem::zeroed() };
+            device.cb = mem::size_of::<DisplayDevice>() as u32;
+            // SAFETY: device is a valid DISPLAY_DEVICEW
+            if unsafe { EnumDisplayDevicesW(ptr::null(), index, &mut device, 0) } == 0 {
+                break;
+            }
+            if device.state_flags & ATTACHED_TO_DESKTOP == 0 {
+                continue;
+            }
+
+            let name = from_wide(&device.device_name);
+            let Some(mode) = display_mode(&device.device_name, ENUM_CURRENT_SETTINGS) else {
+                log::warn!("failed to read the current mode of {name}");
+                continue;
+            };
+
+            let mut supported: Vec<Mode> = vec![];
+            for mode_index in 0.. {
+                let Some(mode) = display_mode(&device.device_name, mode_index) else {
+                    break;
+                };
+                // Modes are listed once per color depth
+                if !supported.contains(&mode) {
+                    supported.push(mode);
+                }
+            }
+            // Largest mode first, highest refresh rate first
+            supported.sort_by_key(|m| {
+                std::cmp::Reverse((m.hdisplay as u32 * m.vdisplay as u32, m.vrefresh))
+            });
+            if let Some(native) = supported.first_mut() {
+                native.mode_type |= modes::TYPE_PREFERRED;
+            }
+
+            // Same mode, with the preferred flag
+            let mode = supported
+                .iter()
+                .find(|m| {
+                    Mode {
+                        mode_type: 0,
+                        ..**m
+                    } == mode
+                })
+                .copied()
+                .unwrap_or(mode);
+
+            log::debug!(
+                "Found display: {name} ({}), {}x{}",
+                from_wide(&device.device_string),
+                mode.size().0,
+                mode.size().1
+            );
+            displays.push(Display {
+                card: PathBuf::from(&name),
+                connector: name.trim_start_matches(r"\\.\").to_string(),
+                mode,
+                modes: supported,
+                physical_size: None,
+                vrr_capable: None,
+                edid: None,
+                properties: Default::default(),
+            });
+        }
+
+        Ok(displays)
+    }
+}
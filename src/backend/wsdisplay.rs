@@ -0,0 +1,89 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! OpenBSD backend (wscons)
+//!
+//! OpenBSD's DRM drivers don't expose modesetting to userspace, the framebuffer of the
+//! console (`/dev/ttyC0`) is read instead. It is the size of the display the console runs on,
+//! usually its native resolution. `/dev/ttyC0` belongs to the user logged in on the console
+//! (see fbtab(5)).
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::backend::Backend;
+use crate::{Display, Mode, ProbeOptions};
+
+/// Console display device
+const CONSOLE: &str = "/dev/ttyC0";
+/// `WSDISPLAYIO_GINFO`, `_IOR('W', 65, struct wsdisplay_fbinfo)`
+const WSDISPLAYIO_GINFO: libc::c_ulong = 0x4010_5741;
+
+/// `struct wsdisplay_fbinfo`
+#[repr(C)]
+#[derive(Default)]
+struct FbInfo {
+    height: libc::c_uint,
+    width: libc::c_uint,
+    depth: libc::c_uint,
+    cmsize: libc::c_uint,
+}
+
+/// OpenBSD display detection
+pub struct Wsdisplay;
+
+impl Backend for Wsdisplay {
+    fn name(&self) -> &'static str {
+        "wsdisplay"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let console = File::open(CONSOLE).with_context(|| format!("failed to open {CONSOLE}"))?;
+        let mut info = FbInfo::default();
+        // SAFETY: info is a valid wsdisplay_fbinfo, the file stays open during the call
+        if unsafe { libc::ioctl(console.as_raw_fd(), WSDISPLAYIO_GINFO, &mut info) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("failed to read the framebuffer of {CONSOLE}"));
+        }
+
+        let mode = Mode::from_size((info.width.try_into()?, info.height.try_into()?));
+        let connector = Path::new(CONSOLE)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        log::debug!(
+            "Found display: {connector}, {}x{} ({} bpp)",
+            info.width,
+            info.height,
+            info.depth
+        );
+
+        Ok(vec![Display {
+            card: PathBuf::from(CONSOLE),
+            connector,
+            mode,
+            modes: vec![mode],
+            physical_size: None,
+            vrr_capable: None,
+            edid: None,
+            properties: Default::default(),
+        }])
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! X11 backend
+//!
+//! Only the connection setup is done: displays are the X screens, sized like their root
+//! window. With several monitors, the root window spans all of them.
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::backend::Backend;
+use crate::{Display, Mode, ProbeOptions};
+
+/// Authorization protocol supported
+const AUTH_NAME: &str = "MIT-MAGIC-COOKIE-1";
+/// Time to wait for the server's answer
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Round up to a multiple of 4, X11 pads everything to 32 bits
+fn pad(length: usize) -> usize {
+    length.div_ceil(4) * 4
+}
+
+/// Read a big-endian `u16` of an Xauthority entry
+fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let (value, rest) = data.split_first_chunk::<2>()?;
+    *data = rest;
+    Some(u16::from_be_bytes(*value))
+}
+
+/// Read a length prefixed field of an Xauthority entry
+fn read_field<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = read_u16(data)? as usize;
+    let field = data.get(..length)?;
+    *data = &data[length..];
+    Some(field)
+}
+
+/// Find the MIT-MAGIC-COOKIE-1 of a display number in the Xauthority file
+fn cookie(number: &str) -> Option<Vec<u8>> {
+    let path = match env::var_os("XAUTHORITY") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env::var_os("HOME")?).join(".Xauthority"),
+    };
+    let content = fs::read(path).ok()?;
+    let mut data = content.as_slice();
+    while !data.is_empty() {
+        let _family = read_u16(&mut data)?;
+        let _address = read_field(&mut data)?;
+        let entry_number = read_field(&mut data)?;
+        let name = read_field(&mut data)?;
+        let cookie = read_field(&mut data)?;
+        // An empty number matches every display
+        if (entry_number.is_empty() || entry_number == number.as_bytes())
+            && name == AUTH_NAME.as_bytes()
+        {
+            return Some(cookie.to_vec());
+        }
+    }
+    None
+}
+
+/// A connection to the X server, local or TCP
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    /// Connect to a display (`:0`, `host:0`...)
+    fn open(display: &str) -> anyhow::Result<(Self, String)> {
+        let (host, rest) = display
+            .rsplit_once(':')
+            .with_context(|| format!("invalid DISPLAY: {display}"))?;
+        // Drop the screen (`:0.1`)
+        let number = rest.split('.').next().unwrap_or(rest).to_string();
+        let port: u16 = number
+            .parse()
+            .with_context(|| format!("invalid DISPLAY: {display}"))?;
+
+        let stream = if host.is_empty() || host == "unix" {
+            let path = format!("/tmp/.X11-unix/X{number}");
+            let stream = UnixStream::connect(&path)
+                .with_context(|| format!("failed to connect to {path}"))?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            Self::Unix(stream)
+        } else {
+            let address = (host, 6000 + port);
+            let stream = TcpStream::connect(address)
+                .with_context(|| format!("failed to connect to {host}:{}", 6000 + port))?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            Self::Tcp(stream)
+        };
+        Ok((stream, number))
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Unix(s) => s.write_all(data),
+            Self::Tcp(s) => s.write_all(data),
+        }
+    }
+
+    fn read_exact(&mut self, data: &mut [u8]) -> std::io::Result<()> {
+        match self {
+            Self::Unix(s) => s.read_exact(data),
+            Self::Tcp(s) => s.read_exact(data),
+        }
+    }
+}
+
+/// Read a little-endian `u16` of the setup reply
+fn reply_u16(reply: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        reply.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// X11 display detection
+pub struct X11;
+
+impl Backend for X11 {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn probe(&self, _options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+        let display = env::var("DISPLAY").context("DISPLAY not set")?;
+        let (mut stream, number) = Stream::open(&display)?;
+        let cookie = cookie(&number).unwrap_or_default();
+
+        // Connection setup, little-endian, protocol 11.0
+        let mut request = vec![b'l', 0, 11, 0, 0, 0];
+        let name_length = if cookie.is_empty() {
+            0
+        } else {
+            AUTH_NAME.len()
+        };
+        request.extend_from_slice(&(name_length as u16).to_le_bytes());
+        request.extend_from_slice(&(cookie.len() as u16).to_le_bytes());
+        request.extend_from_slice(&[0, 0]);
+        if !cookie.is_empty() {
+            request.extend_from_slice(AUTH_NAME.as_bytes());
+            request.resize(pad(request.len()), 0);
+            request.extend_from_slice(&cookie);
+            request.resize(pad(request.len()), 0);
+        }
+        stream.write_all(&request)?;
+
+        let mut header = [0; 8];
+        stream
+            .read_exact(&mut header)
+            .context("failed to read from the X server")?;
+        let length = u16::from_le_bytes([header[6], header[7]]) as usize * 4;
+        let mut reply = vec![0; length];
+        stream
+            .read_exact(&mut reply)
+            .context("failed to read from the X server")?;
+        if header[0] != 1 {
+            let reason = match header[0] {
+                0 => &reply[..(header[1] as usize).min(reply.len())],
+                _ => reply.split(|b| *b == 0).next().unwrap_or_default(),
+            };
+            return Err(anyhow::anyhow!(
+                "X server refused the connection: {}",
+                String::from_utf8_lossy(reason).trim()
+            ));
+        }
+
+        // Skip the fixed part, the vendor and the pixmap formats to reach the screens
+        let vendor_length = reply_u16(&reply, 16).context("invalid X setup reply")? as usize;
+        let screens = *reply.get(20).context("invalid X setup reply")?;
+        let formats = *reply.get(21).context("invalid X setup reply")? as usize;
+        let mut offset = 32 + pad(vendor_length) + formats * 8;
+
+        let mut displays = vec![];
+        for screen in 0..screens {
+            let (Some(width), Some(height), Some(width_mm), Some(height_mm), Some(depths)) = (
+                reply_u16(&reply, offset + 20),
+                reply_u16(&reply, offset + 22),
+                reply_u16(&reply, offset + 24),
+                reply_u16(&reply, offset + 26),
+                reply.get(offset + 39),
+            ) else {
+                return Err(anyhow::anyhow!("invalid X setup reply"));
+            };
+
+            let mode = Mode::from_size((width, height));
+            let connector = format!("X11-{screen}");
+            log::debug!("Found display: {connector}, {width}x{height}");
+            displays.push(Display {
+                card: PathBuf::from(&display),
+                connector,
+                mode,
+                modes: vec![mode],
+                physical_size: Some((width_mm as u32, height_mm as u32))
+                    .filter(|s| s.0 > 0 && s.1 > 0),
+                vrr_capable: None,
+                edid: None,
+                properties: Default::default(),
+            });
+
+            // Skip the allowed depths and their visuals
+            offset += 40;
+            for _ in 0..*depths {
+                let visuals = reply_u16(&reply, offset + 2).context("invalid X setup reply")?;
+                offset += 8 + visuals as usize * 24;
+            }
+        }
+
+        Ok(displays)
+    }
+}
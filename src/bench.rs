@@ -0,0 +1,120 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Probe benchmark (`rres --bench`)
+//!
+//! Every backend, then every card through the platform's backend, is probed several times
+//! without the cache nor the fallbacks. The first run includes waking up suspended GPUs.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::backend::{self, Backend};
+use crate::ProbeOptions;
+
+/// Timings of a backend or a card
+pub struct Timings {
+    /// Backend name, or card and backend name (eg. `card0 (drm)`)
+    pub name: String,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Runs that failed, they are timed too
+    pub failures: usize,
+    /// Displays found by the last successful run
+    pub displays: usize,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:<16} min {:>8.2} ms, avg {:>8.2} ms, max {:>8.2} ms, {} display(s)",
+            self.name,
+            ms(self.min),
+            ms(self.avg),
+            ms(self.max),
+            self.displays
+        )?;
+        if self.failures > 0 {
+            write!(f, ", {} failure(s)", self.failures)?;
+        }
+        Ok(())
+    }
+}
+
+/// Probe `runs` times with a backend
+fn time(name: String, backend: &dyn Backend, options: &ProbeOptions, runs: usize) -> Timings {
+    let mut durations = vec![];
+    let mut failures = 0;
+    let mut displays = 0;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let result = backend.probe(options);
+        durations.push(start.elapsed());
+        match result {
+            Ok(found) => displays = found.len(),
+            Err(e) => {
+                log::debug!("{name} failed: {e:#}");
+                failures += 1;
+            }
+        }
+    }
+    Timings {
+        name,
+        min: durations.iter().min().copied().unwrap_or_default(),
+        avg: durations.iter().sum::<Duration>() / durations.len() as u32,
+        max: durations.iter().max().copied().unwrap_or_default(),
+        failures,
+        displays,
+    }
+}
+
+/// Time `runs` probes of every backend, then of every card
+pub fn run(options: &ProbeOptions, runs: usize) -> Vec<Timings> {
+    let options = ProbeOptions {
+        cache: false,
+        ..options.clone()
+    };
+    let mut backends = vec![backend::platform()];
+    backends.extend(backend::dbus());
+    backends.extend(backend::compositors());
+
+    let mut timings: Vec<Timings> = backends
+        .iter()
+        .map(|b| time(b.name().to_string(), b.as_ref(), &options, runs))
+        .collect();
+
+    let platform = backend::platform();
+    for card in crate::get_cards(options.card.clone()).unwrap_or_default() {
+        let Some(name) = card.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let card_options = ProbeOptions {
+            card: Some(name.clone()),
+            ..options.clone()
+        };
+        timings.push(time(
+            format!("{name} ({})", platform.name()),
+            platform.as_ref(),
+            &card_options,
+            runs,
+        ));
+    }
+    timings
+}
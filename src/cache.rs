@@ -0,0 +1,301 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Probe result cache (`$XDG_RUNTIME_DIR/rres.json`)
+//!
+//! Launch scripts often run rres several times in a row. The displays found by the backend
+//! are reused for [`TTL`], unless the probe options differ or a connector changed (hotplug,
+//! read from sysfs). Fallbacks (compositors, `RRES_FALLBACK_RES`) aren't cached.
+//!
+//! A probe limited to the first displays ([`ProbeOptions::limit`]) is reused by probes needing
+//! as many displays or less, a full probe by every probe.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::json::{self, Value};
+use crate::{dp, modes, sysfs, Display, Orientation, ProbeOptions, Properties};
+
+/// How long the cached displays are used
+pub const TTL: Duration = Duration::from_secs(5);
+
+/// Path of the cache file, `None` without `XDG_RUNTIME_DIR`
+fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_RUNTIME_DIR")?;
+    Some(PathBuf::from(dir).join("rres.json"))
+}
+
+/// Identify the options changing the backend's result
+fn key(options: &ProbeOptions) -> String {
+    format!(
+        "{:?} {:?} {} {} {} {}",
+        options.card,
+        options.gpu_policy,
+        options.prefer_drm,
+        options.via_dbus,
+        options.via_portal,
+        options.edid
+    )
+}
+
+/// Whether displays found with a limit cover the limit of a probe (`None` for every display)
+fn covers(cached: Option<usize>, requested: Option<usize>) -> bool {
+    match (cached, requested) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(cached), Some(requested)) => cached >= requested,
+    }
+}
+
+/// Milliseconds since the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn display_json(display: &Display) -> Value {
+    json::object([
+        ("card", display.card.to_string_lossy().as_ref().into()),
+        ("connector", display.connector.as_str().into()),
+        ("mode", modes::to_json(&display.mode)),
+        (
+            "modes",
+            Value::Array(display.modes.iter().map(modes::to_json).collect()),
+        ),
+        ("physical_size", display.physical_size.into()),
+        ("vrr_capable", display.vrr_capable.into()),
+        ("edid", display.edid.as_deref().map(json::to_hex).into()),
+        (
+            "hdr_output_metadata",
+            display.properties.hdr_output_metadata.into(),
+        ),
+        ("max_bpc", display.properties.max_bpc.into()),
+        ("max_bpc_range", display.properties.max_bpc_range.into()),
+        ("dsc", display.properties.dsc.into()),
+        (
+            "dp_link",
+            display
+                .properties
+                .dp_link
+                .map(|link| (link.lanes, link.rate))
+                .into(),
+        ),
+        (
+            "panel_orientation",
+            display
+                .properties
+                .panel_orientation
+                .map(|o| o.as_str())
+                .into(),
+        ),
+    ])
+}
+
+fn parse_display(value: &Value) -> Option<Display> {
+    let physical_size = match value.get("physical_size")? {
+        Value::Array(size) => match &size[..] {
+            [width, height] => Some((
+                width.as_u64()?.try_into().ok()?,
+                height.as_u64()?.try_into().ok()?,
+            )),
+            _ => return None,
+        },
+        _ => None,
+    };
+    let edid = match value.get("edid")?.as_str() {
+        Some(hex) => Some(json::from_hex(hex)?),
+        None => None,
+    };
+    let max_bpc_range = match value.get("max_bpc_range")? {
+        Value::Array(range) => match &range[..] {
+            [min, max] => Some((
+                min.as_u64()?.try_into().ok()?,
+                max.as_u64()?.try_into().ok()?,
+            )),
+            _ => return None,
+        },
+        _ => None,
+    };
+    let dp_link = match value.get("dp_link")? {
+        Value::Array(link) => match &link[..] {
+            [lanes, rate] => Some(dp::Link {
+                lanes: lanes.as_u64()?.try_into().ok()?,
+                rate: rate.as_u64()?.try_into().ok()?,
+            }),
+            _ => return None,
+        },
+        _ => None,
+    };
+    let Value::Array(modes) = value.get("modes")? else {
+        return None;
+    };
+    Some(Display {
+        card: PathBuf::from(value.get("card")?.as_str()?),
+        connector: value.get("connector")?.as_str()?.to_string(),
+        mode: modes::from_json(value.get("mode")?)?,
+        modes: modes.iter().map(modes::from_json).collect::<Option<_>>()?,
+        physical_size,
+        vrr_capable: value.get("vrr_capable")?.as_bool(),
+        edid,
+        properties: Properties {
+            hdr_output_metadata: value.get("hdr_output_metadata")?.as_bool(),
+            max_bpc: match value.get("max_bpc")?.as_u64() {
+                Some(bpc) => Some(bpc.try_into().ok()?),
+                None => None,
+            },
+            max_bpc_range,
+            dsc: value.get("dsc")?.as_bool(),
+            dp_link,
+            panel_orientation: match value.get("panel_orientation")?.as_str() {
+                Some(name) => Some(Orientation::from_name(name)?),
+                None => None,
+            },
+        },
+    })
+}
+
+/// Get the cached displays, if still valid for the options
+pub(crate) fn load(options: &ProbeOptions) -> Option<Vec<Display>> {
+    read(&path()?, options)
+}
+
+/// Cache the displays found with the options
+pub(crate) fn store(options: &ProbeOptions, displays: &[Display]) {
+    if let Some(path) = path() {
+        write(&path, options, displays);
+    }
+}
+
+/// Read the displays cached at `path`, if still valid for the options
+fn read(path: &Path, options: &ProbeOptions) -> Option<Vec<Display>> {
+    let cache = json::parse(&fs::read_to_string(path).ok()?).ok()?;
+    let age = now().checked_sub(cache.get("time")?.as_u64()?)?;
+    if age > TTL.as_millis() as u64 {
+        return None;
+    }
+    if cache.get("key")?.as_str()? != key(options) {
+        return None;
+    }
+    let limit = match cache.get("limit")?.as_u64() {
+        Some(limit) => Some(limit.try_into().ok()?),
+        None => None,
+    };
+    if !covers(limit, options.limit) {
+        log::debug!("The cached probe found too few displays");
+        return None;
+    }
+    if cache.get("connectors")?.as_str()? != sysfs::connector_states() {
+        log::debug!("Connectors changed, ignoring the cache");
+        return None;
+    }
+    let Value::Array(displays) = cache.get("displays")? else {
+        return None;
+    };
+    displays.iter().map(parse_display).collect()
+}
+
+/// Cache the displays found with the options at `path`
+fn write(path: &Path, options: &ProbeOptions, displays: &[Display]) {
+    let cache = json::object([
+        ("time", now().into()),
+        ("key", key(options).into()),
+        ("limit", options.limit.into()),
+        ("connectors", sysfs::connector_states().into()),
+        (
+            "displays",
+            Value::Array(displays.iter().map(display_json).collect()),
+        ),
+    ]);
+    // Written aside then renamed, so that concurrent runs never read a partial file
+    let temporary = path.with_extension(format!("json.{}", std::process::id()));
+    if let Err(e) =
+        fs::write(&temporary, cache.to_string()).and_then(|_| fs::rename(&temporary, path))
+    {
+        log::debug!("Failed to write {}: {e}", path.display());
+        let _ = fs::remove_file(&temporary);
+    }
+}
+
+/// Remove the cache, eg. after changing a mode
+pub fn invalidate() {
+    if let Some(path) = path() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        assert!(covers(None, None));
+        assert!(covers(None, Some(1)));
+        assert!(covers(Some(2), Some(1)));
+        assert!(covers(Some(1), Some(1)));
+        assert!(!covers(Some(1), Some(2)));
+        assert!(!covers(Some(1), None));
+    }
+
+    #[test]
+    fn limited_probe() {
+        let path = env::temp_dir().join(format!("rres-cache-{}.json", std::process::id()));
+        let mode = crate::Mode::from_size((1920, 1080));
+        let display = Display {
+            card: PathBuf::from("/dev/dri/card0"),
+            connector: "DP-1".to_string(),
+            mode,
+            modes: vec![mode],
+            physical_size: None,
+            vrr_capable: None,
+            edid: None,
+            properties: Default::default(),
+        };
+        let options = ProbeOptions {
+            cache: true,
+            ..Default::default()
+        };
+        let limited = |limit| ProbeOptions {
+            limit,
+            ..options.clone()
+        };
+
+        // Stored as get_display_opts probes the first display
+        write(&path, &limited(Some(1)), std::slice::from_ref(&display));
+        assert!(read(&path, &limited(Some(1))).is_some_and(|d| d[0].connector == "DP-1"));
+        assert!(read(&path, &limited(Some(2))).is_none());
+        assert!(read(&path, &options).is_none());
+        // Other options
+        let edid = ProbeOptions {
+            edid: true,
+            ..limited(Some(1))
+        };
+        assert!(read(&path, &edid).is_none());
+
+        // A full probe serves every limit
+        write(&path, &options, &[display]);
+        assert!(read(&path, &limited(Some(3))).is_some_and(|d| d[0].connector == "DP-1"));
+        assert!(read(&path, &options).is_some_and(|d| d[0].connector == "DP-1"));
+
+        fs::remove_file(&path).unwrap();
+        assert!(read(&path, &options).is_none());
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Wrapped command building
+
+use std::env;
+use std::path;
+use std::process;
+
+use crate::edid::HdrMetadata;
+
+/// Detected values available to wrapped commands
+#[derive(Debug, Clone, Copy)]
+pub struct Values {
+    /// Display resolution
+    pub res: (u16, u16),
+    /// Display refresh rate, 0 if unknown
+    pub refresh: u32,
+    /// Render resolution (FSR), same as `res` without upscaling
+    pub render_res: (u16, u16),
+}
+
+impl Values {
+    /// Get the tokens and their values, without `{REFRESH}` when the refresh rate is unknown
+    fn tokens(&self) -> Vec<(&'static str, String)> {
+        let mut tokens = vec![
+            ("{W}", self.res.0.to_string()),
+            ("{H}", self.res.1.to_string()),
+            ("{FSR_W}", self.render_res.0.to_string()),
+            ("{FSR_H}", self.render_res.1.to_string()),
+        ];
+        if self.refresh > 0 {
+            tokens.push(("{REFRESH}", self.refresh.to_string()));
+        }
+        tokens
+    }
+}
+
+impl Values {
+    /// Get the environment variables exposing the values
+    ///
+    /// `RRES_W`, `RRES_H` and `RRES_REFRESH` (when known), plus `RRES_FSR_W` and `RRES_FSR_H`
+    /// when upscaling.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let mut env = vec![
+            ("RRES_W".to_string(), self.res.0.to_string()),
+            ("RRES_H".to_string(), self.res.1.to_string()),
+        ];
+        if self.refresh > 0 {
+            env.push(("RRES_REFRESH".to_string(), self.refresh.to_string()));
+        }
+        if self.render_res != self.res {
+            env.push(("RRES_FSR_W".to_string(), self.render_res.0.to_string()));
+            env.push(("RRES_FSR_H".to_string(), self.render_res.1.to_string()));
+        }
+        env
+    }
+}
+
+/// Build a dotenv file (`KEY=value` lines, eg. for systemd's `EnvironmentFile=`)
+///
+/// Besides [`Values::env`], each display gets `RRES_<index>_W`, `RRES_<index>_H`,
+/// `RRES_<index>_REFRESH` and `RRES_<index>_CONNECTOR`, and `RRES_DISPLAYS` is their count.
+pub fn env_file(values: &Values, displays: &[crate::Display]) -> String {
+    let mut env = values.env();
+    env.push(("RRES_DISPLAYS".to_string(), displays.len().to_string()));
+    for (i, display) in displays.iter().enumerate() {
+        let (width, height) = display.mode.size();
+        env.push((format!("RRES_{i}_W"), width.to_string()));
+        env.push((format!("RRES_{i}_H"), height.to_string()));
+        env.push((
+            format!("RRES_{i}_REFRESH"),
+            display.mode.vrefresh.to_string(),
+        ));
+        env.push((format!("RRES_{i}_CONNECTOR"), display.connector.clone()));
+    }
+    env.iter()
+        .map(|(key, value)| format!("{key}={}\n", quote(value)))
+        .collect()
+}
+
+/// Replace the tokens (`{W}`, `{H}`, `{REFRESH}`, `{FSR_W}`, `{FSR_H}`) in command arguments
+///
+/// `{REFRESH}` is left as is when the refresh rate is unknown.
+pub fn substitute(args: &[String], values: &Values) -> Vec<String> {
+    let tokens = values.tokens();
+    args.iter()
+        .map(|arg| {
+            tokens
+                .iter()
+                .fold(arg.clone(), |arg, (token, value)| arg.replace(token, value))
+        })
+        .collect()
+}
+
+/// Quote an argument for POSIX shells, if needed
+pub fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Join a command into a single shell-quoted string
+pub fn join(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Launchers with a wrapper command field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launcher {
+    /// Heroic Games Launcher: the game command is appended to the wrapper
+    Heroic,
+    /// Bottles: the game command replaces `%command%`
+    Bottles,
+}
+
+impl TryFrom<&str> for Launcher {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_ref() {
+            "heroic" => Ok(Self::Heroic),
+            "bottles" => Ok(Self::Bottles),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Launcher {
+    /// Build the wrapper string to paste into the launcher
+    pub fn wrapper(&self, wrapper: &[String]) -> String {
+        let mut command = wrapper.to_vec();
+        command.push("--".to_string());
+        match self {
+            Self::Heroic => join(&command),
+            Self::Bottles => format!("{} %command%", join(&command)),
+        }
+    }
+}
+
+/// Find a program in `PATH`
+pub fn which(program: &str) -> Option<path::PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|file| file.is_file())
+}
+
+/// Prefix a command with `gamemoderun`
+pub fn gamemode(command: &[String]) -> anyhow::Result<Vec<String>> {
+    let Some(gamemoderun) = which("gamemoderun") else {
+        return Err(anyhow::anyhow!(
+            "gamemoderun not found in PATH (is gamemode installed?)"
+        ));
+    };
+    let mut wrapped = vec![gamemoderun.to_string_lossy().to_string()];
+    wrapped.extend_from_slice(command);
+    Ok(wrapped)
+}
+
+/// First gamescope version with `--mangoapp`
+const MANGOAPP_VERSION: (u32, u32, u32) = (3, 12, 0);
+
+/// Get the version of a gamescope binary (`gamescope version 3.14.2 ...`)
+pub fn gamescope_version(gamescope: &str) -> Option<(u32, u32, u32)> {
+    let output = process::Command::new(gamescope)
+        .arg("--version")
+        .output()
+        .ok()?;
+    // Older versions print the version on stderr
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let version = text
+        .split_whitespace()
+        .skip_while(|w| *w != "version")
+        .nth(1)?;
+    let mut parts = version
+        .trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|p| p.parse().unwrap_or(0));
+    Some((
+        parts.next()?,
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    ))
+}
+
+/// Enable MangoHud through gamescope's `--mangoapp`, if the gamescope version supports it
+///
+/// Returns `false` when `mangohud` has to wrap the game inside gamescope instead: MangoHud
+/// can't draw over gamescope itself.
+pub fn gamescope_mangoapp(gamescope: &mut Vec<String>) -> bool {
+    match gamescope_version(&gamescope[0]) {
+        Some(version) if version >= MANGOAPP_VERSION => {
+            gamescope.push("--mangoapp".to_string());
+            true
+        }
+        version => {
+            log::debug!("gamescope version {version:?} doesn't support --mangoapp");
+            false
+        }
+    }
+}
+
+/// First gamescope version with HDR output
+const HDR_VERSION: (u32, u32, u32) = (3, 12, 0);
+/// gamescope's default luminance of SDR content in HDR, in cd/m²
+const SDR_CONTENT_NITS: f32 = 400.0;
+
+/// gamescope arguments enabling HDR output, tuned to the display's luminance
+///
+/// The peak luminance is the target of the inverse tone mapping of SDR content, and SDR
+/// content is kept below the max frame-average luminance, so that full-screen SDR windows
+/// aren't dimmed by the display. gamescope has no option for the min luminance.
+pub fn gamescope_hdr_args(metadata: &HdrMetadata) -> Vec<String> {
+    let mut args = vec!["--hdr-enabled".to_string()];
+    if let Some(max) = metadata.max_luminance {
+        args.extend(["--hdr-itm-target-nits".to_string(), format!("{max:.0}")]);
+    }
+    if let Some(fall) = metadata
+        .max_frame_avg_luminance
+        .filter(|fall| *fall < SDR_CONTENT_NITS)
+    {
+        args.extend(["--hdr-sdr-content-nits".to_string(), format!("{fall:.0}")]);
+    }
+    args
+}
+
+/// Enable HDR output in a gamescope command, if the gamescope version supports it
+pub fn gamescope_hdr(gamescope: &mut Vec<String>, metadata: &HdrMetadata) -> bool {
+    match gamescope_version(&gamescope[0]) {
+        Some(version) if version >= HDR_VERSION => {
+            gamescope.extend(gamescope_hdr_args(metadata));
+            true
+        }
+        version => {
+            log::warn!("gamescope version {version:?} doesn't support HDR, leaving it off");
+            false
+        }
+    }
+}
+
+/// Insert `mangohud` in a command at the given position
+pub fn mangohud(command: &[String], position: usize) -> Vec<String> {
+    let mut wrapped = command.to_vec();
+    wrapped.insert(position, "mangohud".to_string());
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn substitute() {
+        let values = Values {
+            res: (2560, 1440),
+            refresh: 144,
+            render_res: (1706, 960),
+        };
+        assert_eq!(
+            super::substitute(
+                &strings(&[
+                    "game",
+                    "-w",
+                    "{W}",
+                    "-h",
+                    "{H}",
+                    "--res={FSR_W}x{FSR_H}@{REFRESH}"
+                ]),
+                &values
+            ),
+            strings(&["game", "-w", "2560", "-h", "1440", "--res=1706x960@144"])
+        );
+        // Repeated tokens, unknown and malformed placeholders
+        assert_eq!(
+            super::substitute(
+                &strings(&["{W}{W}", "{X}", "{w}", "{W", "W}", "{{H}}", ""]),
+                &values
+            ),
+            strings(&["25602560", "{X}", "{w}", "{W", "W}", "{1440}", ""])
+        );
+        // Unknown refresh rate
+        let values = Values {
+            refresh: 0,
+            ..values
+        };
+        assert_eq!(
+            super::substitute(&strings(&["{W}@{REFRESH}"]), &values),
+            strings(&["2560@{REFRESH}"])
+        );
+        assert!(!values.env().iter().any(|(key, _)| key == "RRES_REFRESH"));
+    }
+
+    #[test]
+    fn quote() {
+        assert_eq!(super::quote("gamescope"), "gamescope");
+        assert_eq!(super::quote("--width=1920"), "--width=1920");
+        assert_eq!(
+            super::quote("/usr/bin/game,x+1@50%"),
+            "/usr/bin/game,x+1@50%"
+        );
+        assert_eq!(super::quote(""), "''");
+        assert_eq!(super::quote("My Game"), "'My Game'");
+        assert_eq!(super::quote("it's"), "'it'\\''s'");
+        assert_eq!(super::quote("\"quoted\""), "'\"quoted\"'");
+        assert_eq!(super::quote("$HOME"), "'$HOME'");
+        assert_eq!(super::quote("a;b"), "'a;b'");
+        assert_eq!(super::quote("*"), "'*'");
+        assert_eq!(super::quote("é"), "'é'");
+    }
+
+    #[test]
+    fn join() {
+        assert_eq!(
+            super::join(&strings(&[
+                "gamescope",
+                "-W",
+                "1920",
+                "--",
+                "My Game.exe",
+                ""
+            ])),
+            "gamescope -W 1920 -- 'My Game.exe' ''"
+        );
+        assert_eq!(super::join(&[]), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn join_round_trip() {
+        let args = strings(&[
+            "a b",
+            "it's",
+            "\"",
+            "",
+            "$(false)",
+            "\\",
+            "tab\there",
+            "new\nline",
+        ]);
+        let output = process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf '%s\\0' {}", super::join(&args)))
+            .output()
+            .unwrap();
+        let parsed: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .map(str::to_string)
+            .collect();
+        assert_eq!(parsed, args);
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Shell completion scripts (`rres completions <shell>`)
+//!
+//! The commands and options are read from the help text, so new options get completed
+//! without changes here. Cards and connectors are listed when completing, by running
+//! `rres completions cards` and `rres completions connectors`.
+
+use std::fmt::Write as _;
+
+/// FSR modes of `-g`
+const FSR_MODES: [&str; 5] = ["native", "ultra", "quality", "balanced", "performance"];
+/// Values of `--gpu-policy`
+const GPU_POLICIES: [&str; 3] = ["default", "prefer-dgpu", "prefer-igpu"];
+/// Launchers of `rres wrapper`
+const LAUNCHERS: [&str; 2] = ["heroic", "bottles"];
+
+/// Supported shells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl TryFrom<&str> for Shell {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err(anyhow::anyhow!(
+                "unsupported shell: {value} (supported: bash, zsh, fish)"
+            )),
+        }
+    }
+}
+
+/// Shell names, for completing `rres completions`
+const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+/// What the value of an option completes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    /// Free text (resolution, mode...)
+    Any,
+    Cards,
+    Connectors,
+    FsrModes,
+    GpuPolicies,
+    Files,
+    Directories,
+}
+
+/// A command line option
+#[derive(Debug)]
+struct Option_ {
+    short: Option<char>,
+    long: String,
+    value: Option<Value>,
+    description: String,
+}
+
+/// A subcommand
+#[derive(Debug)]
+struct Command {
+    name: String,
+    description: String,
+}
+
+/// Get the value kind of an option, from its name and placeholder
+fn value_kind(long: &str, placeholder: &str) -> Value {
+    match (long, placeholder) {
+        ("card", _) => Value::Cards,
+        ("output", _) => Value::Connectors,
+        ("gamescope", _) => Value::FsrModes,
+        ("gpu-policy", _) => Value::GpuPolicies,
+        (_, "<file>") => Value::Files,
+        (_, "<dir>") => Value::Directories,
+        _ => Value::Any,
+    }
+}
+
+/// Split a help line into its syntax and description, separated by two spaces
+fn split_line(line: &str) -> (&str, Option<&str>) {
+    let line = line.trim();
+    match line.split_once("  ") {
+        Some((syntax, description)) => (syntax, Some(description.trim())),
+        None => (line, None),
+    }
+}
+
+/// Get the first sentence of a description, continued on the more indented lines
+fn description(first: Option<&str>, continuation: &[&str]) -> String {
+    let text = first
+        .into_iter()
+        .chain(
+            continuation
+                .iter()
+                .take_while(|l| l.len() - l.trim_start().len() > 6)
+                .map(|l| l.trim()),
+        )
+        .collect::<Vec<&str>>()
+        .join(" ");
+    // Abbreviations don't end the sentence
+    let end = text.match_indices(". ").map(|(i, _)| i).find(|&i| {
+        !text[..i].ends_with(" eg") && !text[..i].ends_with("(eg") && !text[..i].ends_with("e.g")
+    });
+    match end {
+        Some(end) => text[..end].to_string(),
+        None => text.trim_end_matches('.').to_string(),
+    }
+}
+
+/// Read the commands and options of the help text
+fn parse(usage: &str) -> (Vec<Command>, Vec<Option_>) {
+    let mut commands = vec![];
+    let mut options = vec![];
+    let mut section = "";
+    let lines: Vec<&str> = usage.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if !line.starts_with(' ') && line.ends_with(':') {
+            section = line;
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let continuation = &lines[i + 1..];
+        match section {
+            "Commands:" if indent == 2 => {
+                let (syntax, first) = split_line(line);
+                let Some(name) = syntax.split_whitespace().next() else {
+                    continue;
+                };
+                commands.push(Command {
+                    name: name.to_string(),
+                    description: description(first, continuation),
+                });
+            }
+            "Options:" if (1..=6).contains(&indent) && line.trim_start().starts_with('-') => {
+                let (syntax, first) = split_line(line);
+                let mut option = Option_ {
+                    short: None,
+                    long: String::new(),
+                    value: None,
+                    description: description(first, continuation),
+                };
+                for token in syntax.split_whitespace() {
+                    let token = token.trim_end_matches(',');
+                    if let Some(long) = token.strip_prefix("--") {
+                        option.long = long.to_string();
+                    } else if let Some(short) = token.strip_prefix('-') {
+                        option.short = short.chars().next();
+                    } else if token.starts_with('<') {
+                        option.value = Some(value_kind(&option.long, token));
+                    } else {
+                        break;
+                    }
+                }
+                if !option.long.is_empty() {
+                    options.push(option);
+                }
+            }
+            _ => {}
+        }
+    }
+    (commands, options)
+}
+
+/// Generate the completion script of a shell, for the given help text
+pub fn generate(shell: Shell, usage: &str) -> String {
+    let (commands, options) = parse(usage);
+    match shell {
+        Shell::Bash => bash(&commands, &options),
+        Shell::Zsh => zsh(&commands, &options),
+        Shell::Fish => fish(&commands, &options),
+    }
+}
+
+/// Option names, eg. `-c|--card`
+fn names(option: &Option_, separator: &str) -> String {
+    match option.short {
+        Some(short) => format!("-{short}{separator}--{}", option.long),
+        None => format!("--{}", option.long),
+    }
+}
+
+fn bash(commands: &[Command], options: &[Option_]) -> String {
+    let words = |values: &[&str]| {
+        format!(
+            "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            values.join(" ")
+        )
+    };
+    let mut script = String::new();
+    let _ = writeln!(script, "_rres() {{");
+    let _ = writeln!(script, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(script, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(script, "    local i");
+    let _ = writeln!(script, "    # Wrapped commands");
+    let _ = writeln!(script, "    for ((i = 1; i < COMP_CWORD; i++)); do");
+    let _ = writeln!(script, "        [[ ${{COMP_WORDS[i]}} == -- ]] && return");
+    let _ = writeln!(script, "    done");
+    let _ = writeln!(script, "    case \"$prev\" in");
+    for option in options {
+        let action = match option.value {
+            None => continue,
+            Some(Value::Any) => ":".to_string(),
+            Some(Value::Cards) => "COMPREPLY=($(compgen -W \"$(rres completions cards 2>/dev/null)\" -- \"$cur\"))".to_string(),
+            Some(Value::Connectors) => "COMPREPLY=($(compgen -W \"$(rres completions connectors 2>/dev/null)\" -- \"$cur\"))".to_string(),
+            Some(Value::FsrModes) => words(&FSR_MODES),
+            Some(Value::GpuPolicies) => words(&GPU_POLICIES),
+            Some(Value::Files) => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+            Some(Value::Directories) => "COMPREPLY=($(compgen -d -- \"$cur\"))".to_string(),
+        };
+        let _ = writeln!(script, "        {})", names(option, "|"));
+        let _ = writeln!(script, "            {action}");
+        let _ = writeln!(script, "            return");
+        let _ = writeln!(script, "            ;;");
+    }
+    let _ = writeln!(script, "    esac");
+    let _ = writeln!(script, "    if [[ $cur == -* ]]; then");
+    let all: Vec<String> = options.iter().map(|o| names(o, " ")).collect();
+    let _ = writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        all.join(" ")
+    );
+    let _ = writeln!(script, "    elif [[ $COMP_CWORD == 1 ]]; then");
+    let names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+    let _ = writeln!(script, "        {}", words(&names));
+    let _ = writeln!(
+        script,
+        "    elif [[ $COMP_CWORD == 2 && ${{COMP_WORDS[1]}} == completions ]]; then"
+    );
+    let _ = writeln!(script, "        {}", words(&SHELLS));
+    let _ = writeln!(
+        script,
+        "    elif [[ $COMP_CWORD == 2 && ${{COMP_WORDS[1]}} == wrapper ]]; then"
+    );
+    let _ = writeln!(script, "        {}", words(&LAUNCHERS));
+    let _ = writeln!(
+        script,
+        "    elif [[ ${{COMP_WORDS[1]}} == @(wine-reg|dxvk-conf) ]]; then"
+    );
+    let _ = writeln!(script, "        COMPREPLY=($(compgen -f -- \"$cur\"))");
+    let _ = writeln!(script, "    fi");
+    let _ = writeln!(script, "}}");
+    let _ = writeln!(script, "complete -o default -F _rres rres");
+    script
+}
+
+/// Escape a description for zsh's `_arguments` and `_describe`
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh(commands: &[Command], options: &[Option_]) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "#compdef rres");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "_rres_commands() {{");
+    let _ = writeln!(script, "    local -a commands=(");
+    for command in commands {
+        let _ = writeln!(
+            script,
+            "        '{}:{}'",
+            command.name,
+            zsh_escape(&command.description)
+        );
+    }
+    let _ = writeln!(script, "    )");
+    let _ = writeln!(script, "    _describe command commands");
+    let _ = writeln!(script, "}}");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "_rres_arguments() {{");
+    let _ = writeln!(script, "    case $line[1] in");
+    let _ = writeln!(
+        script,
+        "        completions) _values shell {} ;;",
+        SHELLS.join(" ")
+    );
+    let _ = writeln!(
+        script,
+        "        wrapper) _values launcher {} ;;",
+        LAUNCHERS.join(" ")
+    );
+    let _ = writeln!(script, "        *) _files ;;");
+    let _ = writeln!(script, "    esac");
+    let _ = writeln!(script, "}}");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "_rres() {{");
+    let _ = writeln!(script, "    _arguments -s -S \\");
+    for option in options {
+        let description = zsh_escape(&option.description);
+        // Options that can be repeated don't exclude themselves
+        let repeat = matches!(option.long.as_str(), "verbose" | "quiet" | "unset");
+        let names = match (option.short, repeat) {
+            (Some(short), false) => format!(
+                "'(-{short} --{long})'{{-{short},--{long}}}'",
+                long = option.long
+            ),
+            (Some(short), true) => format!("'*'{{-{short},--{}}}'", option.long),
+            (None, false) => format!("'--{}", option.long),
+            (None, true) => format!("'*--{}", option.long),
+        };
+        let value = match option.value {
+            None => String::new(),
+            Some(Value::Any) => format!(":{}: ", option.long),
+            Some(Value::Cards) => {
+                ":card:{compadd -- ${(f)\"$(rres completions cards 2>/dev/null)\"}}".to_string()
+            }
+            Some(Value::Connectors) => {
+                ":output:{compadd -- ${(f)\"$(rres completions connectors 2>/dev/null)\"}}"
+                    .to_string()
+            }
+            Some(Value::FsrModes) => format!(":mode:({})", FSR_MODES.join(" ")),
+            Some(Value::GpuPolicies) => format!(":policy:({})", GPU_POLICIES.join(" ")),
+            Some(Value::Files) => ":file:_files".to_string(),
+            Some(Value::Directories) => ":directory:_files -/".to_string(),
+        };
+        let _ = writeln!(script, "        {names}[{description}]{value}' \\");
+    }
+    let _ = writeln!(script, "        '1: :_rres_commands' \\");
+    let _ = writeln!(script, "        '*: :_rres_arguments'");
+    let _ = writeln!(script, "}}");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "_rres \"$@\"");
+    script
+}
+
+/// Quote a string for fish
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish(commands: &[Command], options: &[Option_]) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "complete -c rres -f");
+    for command in commands {
+        let _ = writeln!(
+            script,
+            "complete -c rres -n __fish_use_subcommand -a {} -d {}",
+            command.name,
+            fish_quote(&command.description)
+        );
+    }
+    let _ = writeln!(
+        script,
+        "complete -c rres -n '__fish_seen_subcommand_from completions' -a '{}'",
+        SHELLS.join(" ")
+    );
+    let _ = writeln!(
+        script,
+        "complete -c rres -n '__fish_seen_subcommand_from wrapper' -a '{}'",
+        LAUNCHERS.join(" ")
+    );
+    let _ = writeln!(
+        script,
+        "complete -c rres -n '__fish_seen_subcommand_from wine-reg dxvk-conf' -F"
+    );
+    for option in options {
+        let mut line = String::from("complete -c rres");
+        if let Some(short) = option.short {
+            let _ = write!(line, " -s {short}");
+        }
+        let _ = write!(line, " -l {}", option.long);
+        match option.value {
+            None => {}
+            Some(Value::Any) => line.push_str(" -x"),
+            Some(Value::Cards) => line.push_str(" -x -a '(rres completions cards 2>/dev/null)'"),
+            Some(Value::Connectors) => {
+                line.push_str(" -x -a '(rres completions connectors 2>/dev/null)'")
+            }
+            Some(Value::FsrModes) => {
+                let _ = write!(line, " -x -a '{}'", FSR_MODES.join(" "));
+            }
+            Some(Value::GpuPolicies) => {
+                let _ = write!(line, " -x -a '{}'", GPU_POLICIES.join(" "));
+            }
+            Some(Value::Files) => line.push_str(" -r -F"),
+            Some(Value::Directories) => line.push_str(" -x -a '(__fish_complete_directories)'"),
+        }
+        let _ = writeln!(script, "{line} -d {}", fish_quote(&option.description));
+    }
+    script
+}
@@ -0,0 +1,215 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Configuration file
+//!
+//! The configuration is read from `RRES_CONFIG`, or `$XDG_CONFIG_HOME/rres/config`
+//! (`~/.config/rres/config`). Example:
+//!
+//! ```text
+//! # Old game only supporting 1080p
+//! [steam.12345]
+//! res = 1920x1080
+//!
+//! # Always run on the second display
+//! [steam.620]
+//! display = 1
+//! ```
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+
+use anyhow::Context;
+
+/// Overrides for a Steam game
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AppOverride {
+    /// Forced resolution
+    pub res: Option<(u16, u16)>,
+    /// Display index
+    pub display: Option<usize>,
+}
+
+/// rres configuration
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// Overrides by Steam AppID
+    pub apps: HashMap<u32, AppOverride>,
+}
+
+/// Get the configuration file path
+pub fn path() -> Option<path::PathBuf> {
+    if let Some(config) = env::var_os("RRES_CONFIG") {
+        return Some(path::PathBuf::from(config));
+    }
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => path::PathBuf::from(dir),
+        None => path::PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("rres/config"))
+}
+
+/// Parse a resolution (eg. `1920x1080`)
+pub fn parse_res(value: &str) -> anyhow::Result<(u16, u16)> {
+    let Some((x, y)) = value.split_once('x') else {
+        return Err(anyhow::anyhow!("invalid resolution: {value}"));
+    };
+    Ok((x.parse()?, y.parse()?))
+}
+
+impl Config {
+    /// Load the configuration file, an empty configuration if it doesn't exist
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                Self::parse(&contents).with_context(|| format!("invalid {}", path.display()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Parse the configuration
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        let mut app: Option<u32> = None;
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let context = || format!("line {}", number + 1);
+
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let Some(id) = section.strip_prefix("steam.") else {
+                    return Err(anyhow::anyhow!("unknown section: {section}"))
+                        .with_context(context);
+                };
+                let id = id.parse().with_context(context)?;
+                config.apps.entry(id).or_default();
+                app = Some(id);
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(anyhow::anyhow!("expected key = value")).with_context(context);
+            };
+            let Some(id) = app else {
+                return Err(anyhow::anyhow!("key outside of a section")).with_context(context);
+            };
+            let entry = config.apps.entry(id).or_default();
+            let value = value.trim();
+            match key.trim() {
+                "res" => entry.res = Some(parse_res(value).with_context(context)?),
+                "display" => entry.display = Some(value.parse().with_context(context)?),
+                key => {
+                    return Err(anyhow::anyhow!("unknown key: {key}")).with_context(context);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Get the Steam AppID of the game being launched
+///
+/// Read from `SteamAppId`, or from the `AppId=` argument of Steam's reaper in `command`.
+pub fn steam_app_id(command: &[String]) -> Option<u32> {
+    if let Some(id) = env::var("SteamAppId").ok().and_then(|id| id.parse().ok()) {
+        return Some(id);
+    }
+    command
+        .iter()
+        .find_map(|arg| arg.strip_prefix("AppId=")?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let config = Config::parse(
+            "[steam.12345]\n\
+            res = 1920x1080\n\
+            \n\
+            [steam.620]\n\
+            display = 1\n\
+            [steam.12345]\n\
+            display = 2\n",
+        )
+        .unwrap();
+        assert_eq!(config.apps.len(), 2);
+        // Repeated sections are merged
+        assert_eq!(
+            config.apps[&12345],
+            AppOverride {
+                res: Some((1920, 1080)),
+                display: Some(2),
+            }
+        );
+        assert_eq!(config.apps[&620].display, Some(1));
+        assert_eq!(config.apps[&620].res, None);
+        // Empty sections are kept
+        assert!(Config::parse("[steam.1]").unwrap().apps.contains_key(&1));
+    }
+
+    #[test]
+    fn comments_and_whitespace() {
+        let config = Config::parse(
+            "# Comment\n\
+            \t[steam.620]  \n\
+            \x20 # Indented comment\n\
+            \x20 res=  2560x1440 \n\
+            \r\n",
+        )
+        .unwrap();
+        assert_eq!(config.apps[&620].res, Some((2560, 1440)));
+        assert!(Config::parse("").unwrap().apps.is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let error = |contents: &str| format!("{:#}", Config::parse(contents).unwrap_err());
+        assert_eq!(error("res = 1920x1080"), "line 1: key outside of a section");
+        assert_eq!(error("[steam.1]\n\nres"), "line 3: expected key = value");
+        assert_eq!(error("[lutris.1]"), "line 1: unknown section: lutris.1");
+        assert!(error("[steam.abc]").starts_with("line 1: "));
+        assert_eq!(error("[steam.1]\nfps = 60"), "line 2: unknown key: fps");
+        assert!(error("[steam.1]\nres = 1920").contains("invalid resolution"));
+        assert!(error("[steam.1]\ndisplay = -1").starts_with("line 2: "));
+        // Unclosed section
+        assert_eq!(error("[steam.1"), "line 1: expected key = value");
+    }
+
+    #[test]
+    fn resolutions() {
+        assert_eq!(parse_res("1920x1080").unwrap(), (1920, 1080));
+        assert!(parse_res("1920").is_err());
+        assert!(parse_res("1920x").is_err());
+        assert!(parse_res("x1080").is_err());
+        assert!(parse_res("99999x1080").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! VESA CVT reduced blanking (CVT-RB) timings
+//!
+//! Same results as `cvt -r` (xorg) and the kernel's `video=<connector>:<W>x<H>R@<refresh>`.
+
+use crate::modes::{self, Mode};
+
+/// Horizontal granularity, in pixels
+const CELL_GRANULARITY: u16 = 8;
+/// Minimum vertical blanking time, in microseconds
+const MIN_V_BLANK: f64 = 460.0;
+/// Vertical front porch, in lines
+const V_FRONT_PORCH: u16 = 3;
+/// Minimum vertical back porch, in lines
+const MIN_V_BACK_PORCH: u16 = 6;
+/// Horizontal blanking, in pixels
+const H_BLANK: u16 = 160;
+/// Horizontal sync width, in pixels
+const H_SYNC: u16 = 32;
+/// Pixel clock step, in kHz
+const CLOCK_STEP: u32 = 250;
+
+/// Vertical sync width, which encodes the aspect ratio
+fn vsync_width(width: u16, height: u16) -> u16 {
+    let (width, height) = (width as u32, height as u32);
+    if height % 3 == 0 && height * 4 / 3 == width {
+        4
+    } else if height % 9 == 0 && height * 16 / 9 == width {
+        5
+    } else if height % 10 == 0 && height * 16 / 10 == width {
+        6
+    } else if (height % 4 == 0 && height * 5 / 4 == width)
+        || (height % 9 == 0 && height * 15 / 9 == width)
+    {
+        7
+    } else {
+        10
+    }
+}
+
+/// Compute a CVT-RB mode for a resolution and refresh rate (in Hz)
+///
+/// The width is rounded down to a multiple of 8.
+pub fn reduced_blanking(size: (u16, u16), refresh: f64) -> anyhow::Result<Mode> {
+    let width = size.0 - size.0 % CELL_GRANULARITY;
+    let height = size.1;
+    if width == 0 || height == 0 || refresh <= 0.0 {
+        return Err(anyhow::anyhow!(
+            "invalid mode: {}x{}@{refresh}",
+            size.0,
+            size.1
+        ));
+    }
+
+    let vsync = vsync_width(width, height);
+
+    // Line period, in microseconds
+    let h_period = (1_000_000.0 / refresh - MIN_V_BLANK) / height as f64;
+    if h_period <= 0.0 {
+        return Err(anyhow::anyhow!("refresh rate too high: {refresh}"));
+    }
+    let v_blank =
+        ((MIN_V_BLANK / h_period) as u16 + 1).max(V_FRONT_PORCH + vsync + MIN_V_BACK_PORCH);
+
+    let (Some(htotal), Some(vtotal)) = (width.checked_add(H_BLANK), height.checked_add(v_blank))
+    else {
+        return Err(anyhow::anyhow!("resolution too high: {}x{height}", size.0));
+    };
+    let mut clock = (htotal as f64 * 1000.0 / h_period) as u32;
+    clock -= clock % CLOCK_STEP;
+
+    let hsync_end = width + H_BLANK / 2;
+    let vsync_start = height + V_FRONT_PORCH;
+
+    Ok(Mode {
+        clock,
+        hdisplay: width,
+        hsync_start: hsync_end - H_SYNC,
+        hsync_end,
+        htotal,
+        vdisplay: height,
+        vsync_start,
+        vsync_end: vsync_start + vsync,
+        vtotal,
+        vrefresh: (clock as f64 * 1000.0 / (htotal as f64 * vtotal as f64)).round() as u32,
+        flags: modes::FLAG_PHSYNC | modes::FLAG_NVSYNC,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check a mode against a `cvt -r` modeline
+    fn assert_modeline(mode: Mode, clock: u32, h: [u16; 4], v: [u16; 4]) {
+        assert_eq!(mode.clock, clock);
+        assert_eq!(
+            [mode.hdisplay, mode.hsync_start, mode.hsync_end, mode.htotal],
+            h
+        );
+        assert_eq!(
+            [mode.vdisplay, mode.vsync_start, mode.vsync_end, mode.vtotal],
+            v
+        );
+        assert_eq!(mode.flags, modes::FLAG_PHSYNC | modes::FLAG_NVSYNC);
+        assert_eq!(mode.vrefresh, 60);
+    }
+
+    #[test]
+    fn vectors() {
+        // Modeline "1920x1080R"  138.50  1920 1968 2000 2080  1080 1083 1088 1111 +hsync -vsync
+        assert_modeline(
+            reduced_blanking((1920, 1080), 60.0).unwrap(),
+            138_500,
+            [1920, 1968, 2000, 2080],
+            [1080, 1083, 1088, 1111],
+        );
+        // Modeline "2560x1440R"  241.50  2560 2608 2640 2720  1440 1443 1448 1481 +hsync -vsync
+        assert_modeline(
+            reduced_blanking((2560, 1440), 60.0).unwrap(),
+            241_500,
+            [2560, 2608, 2640, 2720],
+            [1440, 1443, 1448, 1481],
+        );
+        // Modeline "3840x2160R"  533.00  3840 3888 3920 4000  2160 2163 2168 2222 +hsync -vsync
+        assert_modeline(
+            reduced_blanking((3840, 2160), 60.0).unwrap(),
+            533_000,
+            [3840, 3888, 3920, 4000],
+            [2160, 2163, 2168, 2222],
+        );
+        // 16:10: Modeline "1280x800R"  71.00  1280 1328 1360 1440  800 803 809 823 +hsync -vsync
+        assert_modeline(
+            reduced_blanking((1280, 800), 60.0).unwrap(),
+            71_000,
+            [1280, 1328, 1360, 1440],
+            [800, 803, 809, 823],
+        );
+        // 4:3: Modeline "1024x768R"  56.00  1024 1072 1104 1184  768 771 775 790 +hsync -vsync
+        assert_modeline(
+            reduced_blanking((1024, 768), 60.0).unwrap(),
+            56_000,
+            [1024, 1072, 1104, 1184],
+            [768, 771, 775, 790],
+        );
+    }
+
+    #[test]
+    fn vsync() {
+        assert_eq!(vsync_width(1024, 768), 4);
+        assert_eq!(vsync_width(1920, 1080), 5);
+        assert_eq!(vsync_width(1920, 1200), 6);
+        assert_eq!(vsync_width(1280, 1024), 7);
+        assert_eq!(vsync_width(1440, 864), 7);
+        assert_eq!(vsync_width(3440, 1440), 10);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(reduced_blanking((0, 1080), 60.0).is_err());
+        // Rounded down to 0
+        assert!(reduced_blanking((7, 1080), 60.0).is_err());
+        assert!(reduced_blanking((1920, 0), 60.0).is_err());
+        assert!(reduced_blanking((1920, 1080), 0.0).is_err());
+        assert!(reduced_blanking((1920, 1080), 3000.0).is_err());
+        assert!(reduced_blanking((u16::MAX, 1080), 60.0).is_err());
+
+        let mode = reduced_blanking((1366, 768), 60.0).unwrap();
+        assert_eq!(mode.size(), (1360, 768));
+    }
+}
@@ -0,0 +1,782 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Minimal D-Bus client
+//!
+//! Only what rres needs: connecting to the session bus, calling methods with simple
+//! arguments, decoding their replies and waiting for signals (portal responses).
+
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+/// Time to wait for the bus' answers
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// Maximum nesting of containers
+const MAX_DEPTH: usize = 64;
+
+/// A decoded D-Bus value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    /// Every integer type
+    Int(i64),
+    Double(f64),
+    /// Strings, object paths and signatures
+    Str(String),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            Self::Variant(v) => v.as_bool(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(i) => Some(*i),
+            Self::Variant(v) => v.as_i64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Double(d) => Some(*d),
+            Self::Variant(v) => v.as_f64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            Self::Variant(v) => v.as_str(),
+            _ => None,
+        }
+    }
+
+    /// Items of an array or fields of a struct
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Self::Array(items) | Self::Struct(items) => items,
+            Self::Variant(v) => v.items(),
+            _ => &[],
+        }
+    }
+
+    /// Get an entry of a string-keyed dictionary (`a{sv}`)
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Dict(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            Self::Variant(v) => v.get(key),
+            _ => None,
+        }
+    }
+}
+
+/// A method argument
+#[derive(Debug, Clone, Copy)]
+pub enum Arg<'a> {
+    Str(&'a str),
+    ObjectPath(&'a str),
+    U32(u32),
+    Bool(bool),
+    /// `a{sv}` options, like the portals' `options` arguments
+    Options(&'a [(&'a str, Arg<'a>)]),
+}
+
+impl Arg<'_> {
+    fn signature(&self) -> &'static str {
+        match self {
+            Self::Str(_) => "s",
+            Self::ObjectPath(_) => "o",
+            Self::U32(_) => "u",
+            Self::Bool(_) => "b",
+            Self::Options(_) => "a{sv}",
+        }
+    }
+}
+
+/// Split the first complete type off a signature
+fn split_type(signature: &str) -> anyhow::Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let end = match bytes.first() {
+        None => return Err(anyhow::anyhow!("empty signature")),
+        Some(b'a') => 1 + split_type(&signature[1..])?.0.len(),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+            bytes
+                .iter()
+                .position(|b| {
+                    if *b == *open {
+                        depth += 1;
+                    } else if *b == close {
+                        depth -= 1;
+                    }
+                    depth == 0
+                })
+                .context("unbalanced signature")?
+                + 1
+        }
+        Some(_) => 1,
+    };
+    Ok(signature.split_at(end))
+}
+
+/// Alignment of a type
+fn alignment(code: u8) -> usize {
+    match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+/// Message body reader
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, alignment: usize) {
+        self.position = self.position.div_ceil(alignment) * alignment;
+    }
+
+    fn take(&mut self, count: usize) -> anyhow::Result<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .context("truncated D-Bus message")?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        self.align(N);
+        let mut bytes: [u8; N] = self.take(N)?.try_into()?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.fixed()?))
+    }
+
+    fn string(&mut self, length: usize) -> anyhow::Result<String> {
+        let value = String::from_utf8_lossy(self.take(length)?).into_owned();
+        // NUL terminator
+        self.take(1)?;
+        Ok(value)
+    }
+
+    /// Read a value of a single complete type
+    fn value(&mut self, signature: &str, depth: usize) -> anyhow::Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow::anyhow!("D-Bus value too deeply nested"));
+        }
+        let code = *signature.as_bytes().first().context("empty signature")?;
+        Ok(match code {
+            b'y' => Value::Int(self.take(1)?[0] as i64),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int(i16::from_le_bytes(self.fixed()?) as i64),
+            b'q' => Value::Int(u16::from_le_bytes(self.fixed()?) as i64),
+            b'i' => Value::Int(i32::from_le_bytes(self.fixed()?) as i64),
+            b'u' | b'h' => Value::Int(self.u32()? as i64),
+            b'x' => Value::Int(i64::from_le_bytes(self.fixed()?)),
+            b't' => Value::Int(u64::from_le_bytes(self.fixed()?) as i64),
+            b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+            b's' | b'o' => {
+                let length = self.u32()? as usize;
+                Value::Str(self.string(length)?)
+            }
+            b'g' => {
+                let length = self.take(1)?[0] as usize;
+                Value::Str(self.string(length)?)
+            }
+            b'v' => {
+                let length = self.take(1)?[0] as usize;
+                let inner = self.string(length)?;
+                Value::Variant(Box::new(self.value(&inner, depth + 1)?))
+            }
+            b'a' => {
+                let length = self.u32()? as usize;
+                let element = &signature[1..];
+                self.align(alignment(*element.as_bytes().first().unwrap_or(&b'y')));
+                let end = self.position + length;
+                let mut items = vec![];
+                while self.position < end {
+                    items.push(self.value(element, depth + 1)?);
+                }
+                if element.starts_with('{') {
+                    Value::Dict(
+                        items
+                            .into_iter()
+                            .filter_map(|entry| match entry {
+                                Value::Struct(mut pair) if pair.len() == 2 => {
+                                    let value = pair.pop()?;
+                                    Some((pair.pop()?, value))
+                                }
+                                _ => None,
+                            })
+                            .collect(),
+                    )
+                } else {
+                    Value::Array(items)
+                }
+            }
+            b'(' | b'{' => {
+                self.align(8);
+                let mut fields = vec![];
+                let mut rest = &signature[1..signature.len() - 1];
+                while !rest.is_empty() {
+                    let (field, remaining) = split_type(rest)?;
+                    fields.push(self.value(field, depth + 1)?);
+                    rest = remaining;
+                }
+                Value::Struct(fields)
+            }
+            _ => return Err(anyhow::anyhow!("unsupported D-Bus type: {}", code as char)),
+        })
+    }
+}
+
+/// Message writer (little-endian)
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        self.data
+            .resize(self.data.len().div_ceil(alignment) * alignment, 0);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.data.push(value.len() as u8);
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn arg(&mut self, arg: &Arg) {
+        match arg {
+            Arg::Str(value) | Arg::ObjectPath(value) => self.string(value),
+            Arg::U32(value) => self.u32(*value),
+            Arg::Bool(value) => self.u32(*value as u32),
+            Arg::Options(options) => {
+                self.u32(0);
+                let length_position = self.data.len() - 4;
+                // Dict entries are 8-aligned, even when the array is empty
+                self.align(8);
+                let start = self.data.len();
+                for (key, value) in options.iter() {
+                    self.align(8);
+                    self.string(key);
+                    self.signature(value.signature());
+                    self.arg(value);
+                }
+                let length = (self.data.len() - start) as u32;
+                self.data[length_position..length_position + 4]
+                    .copy_from_slice(&length.to_le_bytes());
+            }
+        }
+    }
+
+    /// Write a header field holding a string-like value
+    fn field(&mut self, code: u8, signature: &str, value: &str) {
+        self.align(8);
+        self.data.push(code);
+        self.signature(signature);
+        match signature {
+            "g" => self.signature(value),
+            _ => self.string(value),
+        }
+    }
+}
+
+/// A connection to the session bus
+pub struct Connection {
+    stream: BufReader<UnixStream>,
+    serial: u32,
+    /// Unique name on the bus (`:1.42`)
+    unique_name: String,
+    /// Signals received while waiting for replies
+    signals: Vec<(Header, Vec<u8>)>,
+}
+
+/// Connect to a bus address (`unix:path=...` or `unix:abstract=...`)
+fn connect(address: &str) -> anyhow::Result<UnixStream> {
+    for address in address.split(';') {
+        let Some(parameters) = address.strip_prefix("unix:") else {
+            continue;
+        };
+        for parameter in parameters.split(',') {
+            if let Some(path) = parameter.strip_prefix("path=") {
+                return Ok(UnixStream::connect(path)?);
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(name) = parameter.strip_prefix("abstract=") {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                return Ok(UnixStream::connect_addr(&address)?);
+            }
+        }
+    }
+    Err(anyhow::anyhow!("unsupported D-Bus address: {address}"))
+}
+
+impl Connection {
+    /// Connect and authenticate to the session bus
+    pub fn session() -> anyhow::Result<Self> {
+        let address = match env::var("DBUS_SESSION_BUS_ADDRESS") {
+            Ok(address) => address,
+            Err(_) => format!(
+                "unix:path={}/bus",
+                env::var("XDG_RUNTIME_DIR").context("no session bus")?
+            ),
+        };
+        let stream = connect(&address).context("failed to connect to the session bus")?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut stream = BufReader::new(stream);
+
+        // SAFETY: getuid can't fail
+        let uid = unsafe { libc::getuid() }.to_string();
+        let uid: String = uid.bytes().map(|b| format!("{b:02x}")).collect();
+        stream
+            .get_mut()
+            .write_all(format!("\0AUTH EXTERNAL {uid}\r\n").as_bytes())?;
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(anyhow::anyhow!(
+                "D-Bus authentication failed: {}",
+                line.trim()
+            ));
+        }
+        stream.get_mut().write_all(b"BEGIN\r\n")?;
+
+        let mut connection = Self {
+            stream,
+            serial: 0,
+            unique_name: String::new(),
+            signals: vec![],
+        };
+        let reply = connection.call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        )?;
+        connection.unique_name = reply
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        Ok(connection)
+    }
+
+    /// Unique name of the connection on the bus (`:1.42`)
+    pub fn unique_name(&self) -> &str {
+        &self.unique_name
+    }
+
+    /// Call a method without arguments, returning the values of the reply
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+    ) -> anyhow::Result<Vec<Value>> {
+        self.call_with(destination, path, interface, member, &[])
+    }
+
+    /// Call a method, returning the values of the reply
+    pub fn call_with(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Arg],
+    ) -> anyhow::Result<Vec<Value>> {
+        self.serial += 1;
+        let message = method_call(self.serial, destination, path, interface, member, args);
+        self.stream.get_mut().write_all(&message)?;
+
+        loop {
+            let (kind, header, body) = self.read_message()?;
+            if kind == SIGNAL {
+                self.signals.push((header, body));
+                continue;
+            }
+            if header.reply_serial != Some(self.serial) {
+                continue;
+            }
+            match kind {
+                METHOD_RETURN => return header.decode(&body),
+                ERROR => {
+                    let message = header
+                        .decode(&body)
+                        .ok()
+                        .and_then(|v| v.first().and_then(|m| m.as_str().map(str::to_string)))
+                        .unwrap_or_default();
+                    return Err(anyhow::anyhow!(
+                        "{}: {message}",
+                        header.error_name.unwrap_or_default()
+                    ));
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Wait for a signal emitted by an object, returning its values
+    pub fn wait_signal(
+        &mut self,
+        path: &str,
+        member: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<Vec<Value>> {
+        let matches = |header: &Header| {
+            header.path.as_deref() == Some(path) && header.member.as_deref() == Some(member)
+        };
+        if let Some(index) = self.signals.iter().position(|(h, _)| matches(h)) {
+            let (header, body) = self.signals.remove(index);
+            return header.decode(&body);
+        }
+
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(anyhow::anyhow!("timed out waiting for {member}"));
+            }
+            self.stream.get_ref().set_read_timeout(Some(remaining))?;
+            match self.read_message() {
+                Ok((SIGNAL, header, body)) if matches(&header) => break header.decode(&body),
+                Ok(_) => continue,
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.get_ref().set_read_timeout(Some(TIMEOUT))?;
+        result
+    }
+
+    /// Read a message, as (type, header fields, body)
+    fn read_message(&mut self) -> anyhow::Result<(u8, Header, Vec<u8>)> {
+        let mut fixed = [0; 16];
+        self.stream
+            .read_exact(&mut fixed)
+            .context("failed to read from the session bus")?;
+        let big_endian = fixed[0] == b'B';
+        let read_u32 = |bytes: &[u8]| {
+            let bytes: [u8; 4] = bytes.try_into().unwrap_or_default();
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let body_length = read_u32(&fixed[4..8]) as usize;
+        let fields_length = read_u32(&fixed[12..16]) as usize;
+
+        let mut rest = vec![0; fields_length.div_ceil(8) * 8 + body_length];
+        self.stream.read_exact(&mut rest)?;
+        let mut data = fixed.to_vec();
+        data.extend_from_slice(&rest);
+        parse_message(&data)
+    }
+}
+
+/// Build a method call message
+fn method_call(
+    serial: u32,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    member: &str,
+    args: &[Arg],
+) -> Vec<u8> {
+    let mut body = Writer::default();
+    for arg in args {
+        body.arg(arg);
+    }
+    let signature: String = args.iter().map(Arg::signature).collect();
+
+    let mut fields = Writer::default();
+    fields.field(FIELD_PATH, "o", path);
+    fields.field(FIELD_INTERFACE, "s", interface);
+    fields.field(FIELD_MEMBER, "s", member);
+    fields.field(FIELD_DESTINATION, "s", destination);
+    if !signature.is_empty() {
+        fields.field(FIELD_SIGNATURE, "g", &signature);
+    }
+
+    let mut message = Writer::default();
+    message.data.extend_from_slice(&[b'l', METHOD_CALL, 0, 1]);
+    message.u32(body.data.len() as u32);
+    message.u32(serial);
+    message.u32(fields.data.len() as u32);
+    message.align(8);
+    message.data.extend_from_slice(&fields.data);
+    message.align(8);
+    message.data.extend_from_slice(&body.data);
+    message.data
+}
+
+/// Parse a complete message, as (type, header fields, body)
+fn parse_message(data: &[u8]) -> anyhow::Result<(u8, Header, Vec<u8>)> {
+    let big_endian = data.first() == Some(&b'B');
+    let mut reader = Reader {
+        data,
+        position: 12,
+        big_endian,
+    };
+    let mut header = Header {
+        big_endian,
+        ..Default::default()
+    };
+    for field in reader.value("a(yv)", 0)?.items() {
+        let [code, value] = field.items() else {
+            continue;
+        };
+        match code.as_i64().map(|c| c as u8) {
+            Some(FIELD_PATH) => header.path = value.as_str().map(str::to_string),
+            Some(FIELD_MEMBER) => header.member = value.as_str().map(str::to_string),
+            Some(FIELD_REPLY_SERIAL) => header.reply_serial = value.as_i64().map(|s| s as u32),
+            Some(FIELD_SIGNATURE) => header.signature = value.as_str().map(str::to_string),
+            Some(FIELD_ERROR_NAME) => header.error_name = value.as_str().map(str::to_string),
+            _ => {}
+        }
+    }
+    // The body starts 8-aligned after the fields
+    reader.align(8);
+    let body = data
+        .get(reader.position..)
+        .context("truncated D-Bus message")?;
+    Ok((data[1], header, body.to_vec()))
+}
+
+/// Header fields of a received message
+#[derive(Default)]
+struct Header {
+    big_endian: bool,
+    path: Option<String>,
+    member: Option<String>,
+    reply_serial: Option<u32>,
+    signature: Option<String>,
+    error_name: Option<String>,
+}
+
+impl Header {
+    /// Decode the body following the signature
+    fn decode(&self, body: &[u8]) -> anyhow::Result<Vec<Value>> {
+        let mut reader = Reader {
+            data: body,
+            position: 0,
+            big_endian: self.big_endian,
+        };
+        let mut values = vec![];
+        let mut rest = self.signature.as_deref().unwrap_or_default();
+        while !rest.is_empty() {
+            let (value, remaining) = split_type(rest)?;
+            values.push(reader.value(value, 0)?);
+            rest = remaining;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(value: &str) -> Value {
+        Value::Str(value.to_string())
+    }
+
+    #[test]
+    fn round_trip() {
+        let options = [
+            ("handle_token", Arg::Str("rres1")),
+            ("modal", Arg::Bool(false)),
+            ("types", Arg::U32(7)),
+        ];
+        let args = [
+            Arg::Str("odd"),
+            Arg::ObjectPath("/org/freedesktop/portal/desktop"),
+            Arg::U32(42),
+            Arg::Bool(true),
+            Arg::Options(&options),
+            // Empty dicts are still 8-aligned
+            Arg::Str("x"),
+            Arg::Options(&[]),
+        ];
+        let message = method_call(
+            3,
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.ScreenCast",
+            "CreateSession",
+            &args,
+        );
+        assert_eq!(message.len() % 8, 0);
+
+        let (kind, header, body) = parse_message(&message).unwrap();
+        assert_eq!(kind, METHOD_CALL);
+        assert_eq!(
+            header.path.as_deref(),
+            Some("/org/freedesktop/portal/desktop")
+        );
+        assert_eq!(header.member.as_deref(), Some("CreateSession"));
+        assert_eq!(header.signature.as_deref(), Some("souba{sv}sa{sv}"));
+        let values = header.decode(&body).unwrap();
+        assert_eq!(
+            values,
+            [
+                str("odd"),
+                str("/org/freedesktop/portal/desktop"),
+                Value::Int(42),
+                Value::Bool(true),
+                Value::Dict(vec![
+                    (str("handle_token"), Value::Variant(Box::new(str("rres1")))),
+                    (str("modal"), Value::Variant(Box::new(Value::Bool(false)))),
+                    (str("types"), Value::Variant(Box::new(Value::Int(7)))),
+                ]),
+                str("x"),
+                Value::Dict(vec![]),
+            ]
+        );
+        assert_eq!(
+            values[4].get("handle_token").and_then(Value::as_str),
+            Some("rres1")
+        );
+        assert_eq!(values[4].get("types").and_then(Value::as_i64), Some(7));
+        assert_eq!(values[4].get("missing"), None);
+
+        // No arguments, no signature
+        let message = method_call(
+            1,
+            "org.freedesktop.DBus",
+            "/",
+            "org.freedesktop.DBus",
+            "Hello",
+            &[],
+        );
+        let (_, header, body) = parse_message(&message).unwrap();
+        assert_eq!(header.signature, None);
+        assert!(body.is_empty());
+        assert_eq!(header.decode(&body).unwrap(), []);
+    }
+
+    #[test]
+    fn big_endian() {
+        let header = Header {
+            big_endian: true,
+            signature: Some("unxd(ys)".to_string()),
+            ..Default::default()
+        };
+        let mut body = vec![0, 0, 0, 42, 0xff, 0xfe, 0, 0];
+        body.extend_from_slice(&(-5i64).to_be_bytes());
+        body.extend_from_slice(&1.5f64.to_be_bytes());
+        body.extend_from_slice(&[7, 0, 0, 0, 0, 0, 0, 2, b'h', b'i', 0]);
+        assert_eq!(
+            header.decode(&body).unwrap(),
+            [
+                Value::Int(42),
+                Value::Int(-2),
+                Value::Int(-5),
+                Value::Double(1.5),
+                Value::Struct(vec![Value::Int(7), str("hi")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        assert_eq!(split_type("a{sv}s").unwrap(), ("a{sv}", "s"));
+        assert_eq!(split_type("(ia(ii))u").unwrap(), ("(ia(ii))", "u"));
+        assert_eq!(split_type("aaiy").unwrap(), ("aai", "y"));
+        assert_eq!(split_type("v").unwrap(), ("v", ""));
+        assert!(split_type("").is_err());
+        assert!(split_type("a").is_err());
+        assert!(split_type("(ii").is_err());
+        assert!(split_type("a{sv").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        let header = |signature: &str| Header {
+            signature: Some(signature.to_string()),
+            ..Default::default()
+        };
+        // Truncated
+        assert!(header("u").decode(&[1, 0]).is_err());
+        assert!(header("s").decode(&[5, 0, 0, 0, b'a']).is_err());
+        assert!(header("z").decode(&[0; 8]).is_err());
+        // Variants nested deeper than MAX_DEPTH
+        let nested = b"\x01v\x00".repeat(MAX_DEPTH + 2);
+        assert!(header("v").decode(&nested).is_err());
+        assert!(parse_message(&[b'l', METHOD_RETURN, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Read DRM driver state from debugfs (`/sys/kernel/debug/dri`)
+//!
+//! debugfs is usually only readable by root, and its files aren't a stable interface: every
+//! read is best effort, `None` when the driver doesn't expose the information.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{dp, sysfs};
+
+/// Root of the DRM debugfs
+pub const DRI: &str = "/sys/kernel/debug/dri";
+
+/// Get the debugfs directory of a connector (`/dev/dri/card0`, `DP-1` ->
+/// `/sys/kernel/debug/dri/0/DP-1`)
+fn connector_dir(card: &Path, connector: &str) -> Option<PathBuf> {
+    let minor = card.file_name()?.to_str()?.strip_prefix("card")?;
+    Some(Path::new(DRI).join(minor).join(connector))
+}
+
+/// Whether the connector's current mode uses Display Stream Compression
+///
+/// Read from `dsc_clock_en` (amdgpu) or `i915_dsc_fec_support` (i915).
+pub fn dsc(card: &Path, connector: &str) -> Option<bool> {
+    let dir = connector_dir(card, connector)?;
+    if let Some(enabled) = sysfs::read_attr(dir.join("dsc_clock_en")) {
+        return Some(enabled == "1");
+    }
+    i915_dsc_enabled(&fs::read_to_string(dir.join("i915_dsc_fec_support")).ok()?)
+}
+
+/// Parse `i915_dsc_fec_support`
+fn i915_dsc_enabled(text: &str) -> Option<bool> {
+    text.lines()
+        .find_map(|line| line.strip_prefix("DSC_Enabled: "))
+        .map(|enabled| enabled.trim() == "yes")
+}
+
+/// Get the trained DisplayPort link of a connector, from `link_settings` (amdgpu)
+pub fn dp_link(card: &Path, connector: &str) -> Option<dp::Link> {
+    let dir = connector_dir(card, connector)?;
+    amdgpu_link(&fs::read_to_string(dir.join("link_settings")).ok()?)
+}
+
+/// Parse amdgpu's `link_settings`, `Current:  4  0x14  0  Verified: ...`
+///
+/// The rate is in units of 270 Mbps, or of 10 Mbps for UHBR rates (from 1000).
+fn amdgpu_link(text: &str) -> Option<dp::Link> {
+    let mut current = text
+        .split_whitespace()
+        .skip_while(|t| *t != "Current:")
+        .skip(1);
+    let lanes = current.next()?.parse().ok()?;
+    let rate = u32::from_str_radix(current.next()?.strip_prefix("0x")?, 16).ok()?;
+    let rate = if rate >= 1000 { rate * 10 } else { rate * 270 };
+    (lanes > 0 && rate > 0).then_some(dp::Link { lanes, rate })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn i915_dsc() {
+        let text = "DSC_Enabled: yes\nDSC_Sink_Support: yes\nForce_DSC_Enable: no\n";
+        assert_eq!(i915_dsc_enabled(text), Some(true));
+        assert_eq!(i915_dsc_enabled("DSC_Enabled: no\n"), Some(false));
+        assert_eq!(i915_dsc_enabled("FEC_Sink_Support: yes\n"), None);
+    }
+
+    #[test]
+    fn amdgpu_link() {
+        let text = "Current:  4  0x14  0  Verified:  4  0x14  0  Reported:  4  0x1e  16  \
+                    Preferred:  0  0x0  0  \n";
+        assert_eq!(
+            super::amdgpu_link(text),
+            Some(dp::Link {
+                lanes: 4,
+                rate: 5400
+            })
+        );
+        let uhbr = "Current:  4  0x3e8  0  Verified:  4  0x3e8  0\n";
+        assert_eq!(super::amdgpu_link(uhbr).map(|l| l.rate), Some(10000));
+        // Not trained
+        assert_eq!(super::amdgpu_link("Current:  0  0x0  0\n"), None);
+    }
+
+    #[test]
+    fn directory() {
+        assert_eq!(
+            connector_dir(Path::new("/dev/dri/card1"), "DP-2"),
+            Some(PathBuf::from("/sys/kernel/debug/dri/1/DP-2"))
+        );
+        assert_eq!(
+            connector_dir(Path::new("/dev/dri/renderD128"), "DP-2"),
+            None
+        );
+    }
+}
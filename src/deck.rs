@@ -0,0 +1,69 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Steam Deck awareness
+
+use std::env;
+use std::fmt;
+
+use crate::{sysfs, Display};
+
+/// Steam Deck model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// Original LCD model (Jupiter)
+    Lcd,
+    /// OLED model (Galileo)
+    Oled,
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Lcd => write!(f, "Steam Deck LCD"),
+            Self::Oled => write!(f, "Steam Deck OLED"),
+        }
+    }
+}
+
+/// Detect the Steam Deck model from DMI, `None` if not running on a Steam Deck
+pub fn model() -> Option<Model> {
+    if sysfs::read_attr("/sys/class/dmi/id/board_vendor").as_deref() != Some("Valve") {
+        return None;
+    }
+    match sysfs::read_attr("/sys/class/dmi/id/product_name").as_deref() {
+        Some("Jupiter") => Some(Model::Lcd),
+        Some("Galileo") => Some(Model::Oled),
+        _ => None,
+    }
+}
+
+/// Whether rres runs inside a gamescope session (eg. Steam Deck game mode)
+pub fn in_gamescope_session() -> bool {
+    env::var_os("GAMESCOPE_WAYLAND_DISPLAY").is_some()
+        || env::var("XDG_CURRENT_DESKTOP").as_deref() == Ok("gamescope")
+}
+
+/// Whether an external display is connected (ie. the Deck is docked)
+pub fn is_docked(displays: &[Display]) -> bool {
+    displays.iter().any(|d| !d.is_internal())
+}
+
+/// Move external displays before the internal panel, keeping their order
+pub fn prefer_external(displays: &mut [Display]) {
+    displays.sort_by_key(|d| d.is_internal());
+}
@@ -0,0 +1,267 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Diagnostics of the common detection failures (`rres doctor`)
+//!
+//! Each check reports what it found and, when something is wrong, a hint on how to fix it:
+//! the session, the seat, the access to each card and whether it answers KMS ioctls, and
+//! NVIDIA's hidden encoders.
+
+use std::env;
+use std::fmt;
+
+use crate::environment;
+
+/// Result of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Detection may be degraded
+    Warning,
+    /// Detection fails
+    Error,
+}
+
+/// A diagnostic
+pub struct Check {
+    pub status: Status,
+    /// What was found (eg. `/dev/dri/card0: amdgpu answers KMS ioctls`)
+    pub message: String,
+    /// How to fix it, if it isn't ok
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Self {
+            status: Status::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    #[cfg_attr(not(any(target_os = "linux", target_os = "freebsd")), allow(dead_code))]
+    fn error(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            status: Status::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self.status {
+            Status::Ok => "ok",
+            Status::Warning => "warn",
+            Status::Error => "error",
+        };
+        write!(f, "[{status:^5}] {}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n        hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every check, on the given card or every card
+pub fn run(card: Option<String>) -> Vec<Check> {
+    let mut checks = vec![];
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+    let _ = card;
+    if let Some(environment) = environment::detect() {
+        checks.push(Check::warning(
+            format!("Running in {environment}"),
+            environment::hint(environment),
+        ));
+    }
+    checks.push(session());
+    checks.push(seat());
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    checks.extend(cards(card));
+    checks
+}
+
+/// Whether a Wayland or X session is reachable, for the compositor fallbacks
+fn session() -> Check {
+    if let Some(display) = env::var_os("WAYLAND_DISPLAY") {
+        Check::ok(format!("Wayland session ({})", display.to_string_lossy()))
+    } else if let Some(display) = env::var_os("DISPLAY") {
+        Check::ok(format!("X11 session ({})", display.to_string_lossy()))
+    } else {
+        Check::warning(
+            "No Wayland or X session (WAYLAND_DISPLAY and DISPLAY are unset)",
+            "without DRM access, rres asks the compositor: run it from the desktop session",
+        )
+    }
+}
+
+/// Whether the session has a seat, which gives its user access to the cards
+fn seat() -> Check {
+    match env::var("XDG_SEAT") {
+        Ok(seat) => Check::ok(format!("Session on {seat}")),
+        Err(_) => Check::warning(
+            "The session has no seat (XDG_SEAT is unset, eg. SSH or a service)",
+            "logind only grants the cards to local sessions: use the video group, or run rres \
+             from the desktop",
+        ),
+    }
+}
+
+/// Check the access to each card, and that it answers KMS ioctls
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn cards(card: Option<String>) -> Vec<Check> {
+    use crate::backend::drm::OpenCard;
+
+    let cards = match crate::get_cards(card) {
+        Ok(cards) if !cards.is_empty() => cards,
+        _ => {
+            return vec![Check::error(
+                "No DRM card in /dev/dri",
+                "is the GPU driver loaded? Check lsmod and dmesg, or use RRES_FALLBACK_RES",
+            )]
+        }
+    };
+
+    let mut checks = vec![];
+    for card in cards {
+        let name = card.display();
+        if let Some(check) = access(&card) {
+            checks.push(check);
+            continue;
+        }
+        let card = match OpenCard::open(&card) {
+            Ok(card) => card,
+            Err(e) => {
+                checks.push(Check::error(
+                    format!("{name}: {e:#}"),
+                    "the card doesn't answer KMS ioctls: check dmesg for driver errors",
+                ));
+                continue;
+            }
+        };
+        let driver = card.driver();
+        checks.push(Check::ok(format!("{name}: {driver} answers KMS ioctls")));
+
+        if driver == "nvidia-drm" {
+            let modeset = crate::sysfs::read_attr("/sys/module/nvidia_drm/parameters/modeset");
+            if modeset.as_deref() != Some("Y") {
+                checks.push(Check::error(
+                    format!("{name}: nvidia-drm kernel modesetting is disabled"),
+                    "add nvidia-drm.modeset=1 to the kernel command line",
+                ));
+            }
+            match card.connectors() {
+                Ok(connectors) => {
+                    for connector in connectors {
+                        if connector.connected && connector.current_encoder.is_none() {
+                            checks.push(Check::warning(
+                                format!(
+                                    "{name}: {} has no visible encoder, its native mode is \
+                                     reported instead of the current one",
+                                    connector.name
+                                ),
+                                "the NVIDIA driver hides it, set the resolution with \
+                                 RRES_FORCE_RES if the display isn't at its native mode",
+                            ));
+                        }
+                    }
+                }
+                Err(e) => checks.push(Check::error(
+                    format!("{name}: failed to read the connectors: {e:#}"),
+                    "check dmesg for driver errors",
+                )),
+            }
+        }
+    }
+    checks
+}
+
+/// Check that the card can be opened, `None` if it can
+///
+/// Access is given by logind to the local session's user, or through the card's group.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn access(card: &std::path::Path) -> Option<Check> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let path = CString::new(card.as_os_str().as_bytes()).ok()?;
+    // SAFETY: the path is a valid C string
+    if unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+        return None;
+    }
+    let metadata = std::fs::metadata(card).ok()?;
+    let (gid, mode) = (metadata.gid(), metadata.mode());
+    let (group, members) = group(gid).unwrap_or_else(|| (gid.to_string(), vec![]));
+    let user = env::var("USER").unwrap_or_default();
+    let hint = if mode & 0o060 != 0o060 {
+        format!(
+            "the card isn't writable by its group ({group}, mode {:o})",
+            mode & 0o777
+        )
+    } else if process_groups().contains(&gid) {
+        "check the ACLs of the card (getfacl)".to_string()
+    } else if members.contains(&user) {
+        format!("you were added to the {group} group since logging in: log in again")
+    } else {
+        format!("add yourself to the {group} group (usermod -aG {group} $USER) and log in again")
+    };
+    Some(Check::error(
+        format!("{}: permission denied", card.display()),
+        hint,
+    ))
+}
+
+/// Name and members of a group, from `/etc/group`
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn group(gid: u32) -> Option<(String, Vec<String>)> {
+    let groups = std::fs::read_to_string("/etc/group").ok()?;
+    groups.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields[..] {
+            [name, _, id, members] if id.parse() == Ok(gid) => Some((
+                name.to_string(),
+                members.split(',').map(str::to_string).collect(),
+            )),
+            _ => None,
+        }
+    })
+}
+
+/// Groups of the process
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn process_groups() -> Vec<u32> {
+    // SAFETY: a NULL list only counts the groups
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0; count.max(0) as usize];
+    // SAFETY: the list has room for `count` groups
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    // SAFETY: getegid can't fail
+    groups.push(unsafe { libc::getegid() });
+    groups
+}
@@ -0,0 +1,100 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! DisplayPort link bandwidth
+//!
+//! A mode only fits a link if its data rate, at the bit depth the driver picked, is below the
+//! link's payload bandwidth. Modes close to the limit are the usual suspects when a monitor
+//! blanks at a mode change, unless Display Stream Compression kicks in.
+
+use crate::Mode;
+
+/// A trained DisplayPort link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    /// Number of lanes (1, 2 or 4)
+    pub lanes: u8,
+    /// Rate of each lane in Mbps (eg. 5400 for HBR2)
+    pub rate: u32,
+}
+
+impl Link {
+    /// Name of the link rate (eg. `HBR2`), if standard
+    pub fn rate_name(&self) -> Option<&'static str> {
+        Some(match self.rate {
+            1620 => "RBR",
+            2700 => "HBR",
+            5400 => "HBR2",
+            8100 => "HBR3",
+            10000 => "UHBR10",
+            13500 => "UHBR13.5",
+            20000 => "UHBR20",
+            _ => return None,
+        })
+    }
+
+    /// Data rate available to the video stream in Mbps, after the channel coding (8b/10b,
+    /// 128b/132b for UHBR rates)
+    pub fn bandwidth(&self) -> f64 {
+        let efficiency = if self.rate >= 10000 {
+            128.0 / 132.0
+        } else {
+            0.8
+        };
+        f64::from(self.lanes) * f64::from(self.rate) * efficiency
+    }
+
+    /// Part of the bandwidth an uncompressed RGB mode needs at `bpc` bits per component
+    ///
+    /// Above 1, the mode doesn't fit without DSC. `None` if the mode has no pixel clock.
+    pub fn usage(&self, mode: &Mode, bpc: u8) -> Option<f64> {
+        let bandwidth = self.bandwidth();
+        if mode.clock == 0 || bandwidth == 0.0 {
+            return None;
+        }
+        Some(f64::from(mode.clock) / 1000.0 * f64::from(bpc) * 3.0 / bandwidth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage() {
+        let hbr2 = Link {
+            lanes: 4,
+            rate: 5400,
+        };
+        assert_eq!(hbr2.rate_name(), Some("HBR2"));
+        assert_eq!(hbr2.bandwidth(), 17280.0);
+        // 2560x1440@144 (CVT-RB, 586.6 MHz) fits at 8 bpc, not at 10
+        let mode = Mode {
+            clock: 586_590,
+            ..Mode::from_size((2560, 1440))
+        };
+        assert!(hbr2.usage(&mode, 8).is_some_and(|u| u < 0.9));
+        assert!(hbr2.usage(&mode, 10).is_some_and(|u| u > 1.0));
+        assert_eq!(hbr2.usage(&Mode::from_size((2560, 1440)), 8), None);
+
+        let uhbr = Link {
+            lanes: 4,
+            rate: 10000,
+        };
+        assert!(uhbr.usage(&mode, 10).is_some_and(|u| u < 0.6));
+    }
+}
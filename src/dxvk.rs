@@ -0,0 +1,94 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! DXVK configuration generation
+
+use crate::{aspect, Mode};
+
+/// Build a `dxvk.conf` for the given display mode, with the game rendering at `render_res`
+///
+/// The frame rate is capped to the display's refresh rate (when known), and D3D9 games only
+/// see modes of the display's nominal aspect ratio. DXVK doesn't upscale (FSR is gamescope's),
+/// but an upscaled game gets a negative texture LOD bias matching its render scale, so
+/// textures stay as sharp as at the native resolution.
+pub fn config(mode: &Mode, render_res: (u16, u16)) -> String {
+    let (width, height) = mode.size();
+    let refresh = mode.vrefresh;
+
+    let mut config = format!("# dxvk.conf generated by rres for {width}x{height}");
+    if refresh > 0 {
+        config.push_str(&format!("@{refresh}"));
+    }
+    if render_res != (width, height) {
+        config.push_str(&format!(", rendered at {}x{}", render_res.0, render_res.1));
+    }
+    config.push_str("\n\n");
+
+    if refresh > 0 {
+        config.push_str(&format!("dxgi.maxFrameRate = {refresh}\n"));
+        config.push_str(&format!("d3d9.maxFrameRate = {refresh}\n"));
+    }
+
+    config.push_str(&format!(
+        "d3d9.forceAspectRatio = \"{}\"\n",
+        aspect::nominal((width, height))
+    ));
+
+    if let Some(bias) = lod_bias(render_res.1, height) {
+        config.push_str(&format!("d3d11.samplerLodBias = {bias:.2}\n"));
+        config.push_str(&format!("d3d9.samplerLodBias = {bias:.2}\n"));
+    }
+
+    config
+}
+
+/// Texture LOD bias for a game rendered at `render` lines and upscaled to `native`, if any
+///
+/// Supersampled games keep the default bias, a positive one only blurs them.
+fn lod_bias(render: u16, native: u16) -> Option<f64> {
+    if render == 0 || render >= native {
+        return None;
+    }
+    Some((f64::from(render) / f64::from(native)).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nominal_aspect() {
+        // 1366x768 is sold as 16:9, not its exact 683:384
+        let config = config(&Mode::from_size((1366, 768)), (1366, 768));
+        assert!(config.contains("d3d9.forceAspectRatio = \"16:9\"\n"));
+        assert!(!config.contains("maxFrameRate"));
+        assert!(!config.contains("samplerLodBias"));
+    }
+
+    #[test]
+    fn lod_bias() {
+        // FSR quality renders at 1/1.5 of the lines
+        assert_eq!(
+            format!("{:.2}", super::lod_bias(720, 1080).unwrap()),
+            "-0.58"
+        );
+        assert_eq!(super::lod_bias(540, 1080), Some(-1.0));
+        assert_eq!(super::lod_bias(1080, 1080), None);
+        assert_eq!(super::lod_bias(1620, 1080), None);
+        assert_eq!(super::lod_bias(0, 1080), None);
+    }
+}
@@ -0,0 +1,573 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Minimal EDID parsing
+//!
+//! Only the few fields rres reports are decoded: the base block and the CTA-861 extension
+//! data blocks. With the `libdisplay-info` feature, libdisplay-info parses the EDID instead,
+//! including DisplayID extensions.
+
+#[cfg(feature = "libdisplay-info")]
+mod libdisplay_info;
+
+/// Size of an EDID block
+const BLOCK_SIZE: usize = 128;
+/// EDID base block header
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+/// Serial number of the base block
+const SERIAL: std::ops::Range<usize> = 12..16;
+/// Offsets of the base block's descriptors
+const DESCRIPTORS: [usize; 4] = [54, 72, 90, 108];
+/// Size of a descriptor
+const DESCRIPTOR_SIZE: usize = 18;
+/// Tag of the display serial number descriptor
+const DESCRIPTOR_SERIAL: u8 = 0xff;
+/// Tag of the display range limits descriptor
+const DESCRIPTOR_RANGE_LIMITS: u8 = 0xfd;
+/// Chromaticity coordinates of the base block
+const CHROMATICITY: std::ops::Range<usize> = 25..35;
+/// Primaries of sRGB (BT.709) in CIE 1931 xy, red, green and blue
+const SRGB: [(f32, f32); 3] = [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)];
+/// Gamut area, relative to sRGB, from which a display is considered wide gamut
+///
+/// sRGB panels report primaries close to sRGB's (up to ~110%), DCI-P3 covers ~136%.
+const WIDE_GAMUT_AREA: f32 = 1.2;
+/// CTA-861 extension block tag
+const CTA_EXTENSION: u8 = 0x02;
+/// CTA data block tag using an extended tag
+const CTA_EXTENDED_TAG: u8 = 7;
+/// CTA extended tag of the colorimetry data block
+const CTA_COLORIMETRY: u8 = 5;
+/// CTA extended tag of the HDR static metadata data block
+const CTA_HDR_STATIC_METADATA: u8 = 6;
+/// CTA vendor-specific data block tag
+const CTA_VENDOR_SPECIFIC: u8 = 3;
+/// IEEE OUI of the HDMI 1.4 vendor-specific data block, as stored (little endian)
+const HDMI_OUI: [u8; 3] = [0x03, 0x0c, 0x00];
+/// IEEE OUI of the HDMI Forum vendor-specific data block (HDMI 2.x), as stored
+const HDMI_FORUM_OUI: [u8; 3] = [0xd8, 0x5d, 0xc4];
+/// CTA extended tag of the HDMI Forum sink capability data block (HF-VSDB's alternative)
+const CTA_HDMI_FORUM_SCDB: u8 = 0x79;
+
+/// Whether the data is an EDID (base block with a valid header)
+pub fn is_valid(edid: &[u8]) -> bool {
+    edid.len() >= BLOCK_SIZE && edid[..8] == HEADER
+}
+
+/// Get the physical size of the display in millimeters, from the base block
+///
+/// The base block only stores centimeters. Projectors and some TVs report no size.
+pub fn physical_size(edid: &[u8]) -> Option<(u32, u32)> {
+    #[cfg(feature = "libdisplay-info")]
+    if let Some(info) = libdisplay_info::Info::parse(edid) {
+        return info.physical_size();
+    }
+    if !is_valid(edid) || edid[21] == 0 || edid[22] == 0 {
+        return None;
+    }
+    Some((edid[21] as u32 * 10, edid[22] as u32 * 10))
+}
+
+/// Get the vertical refresh rate range in Hz, from the display range limits descriptor
+///
+/// On VRR (FreeSync, G-Sync Compatible) displays, this is the variable refresh range.
+pub fn refresh_range(edid: &[u8]) -> Option<(u16, u16)> {
+    if !is_valid(edid) {
+        return None;
+    }
+    DESCRIPTORS.iter().find_map(|&offset| {
+        let descriptor = &edid[offset..offset + DESCRIPTOR_SIZE];
+        if descriptor[..3] != [0, 0, 0] || descriptor[3] != DESCRIPTOR_RANGE_LIMITS {
+            return None;
+        }
+        // EDID 1.4 rate offsets: 0b10 adds 255 Hz to the max, 0b11 to both
+        let (min_offset, max_offset) = match descriptor[4] & 0x03 {
+            0x02 => (0, 255),
+            0x03 => (255, 255),
+            _ => (0, 0),
+        };
+        let min = u16::from(descriptor[5]) + min_offset;
+        let max = u16::from(descriptor[6]) + max_offset;
+        (min > 0 && min <= max).then_some((min, max))
+    })
+}
+
+/// Get the data blocks of every CTA-861 extension, as (tag, payload)
+///
+/// Blocks using an extended tag are returned with tag `7`, their payload starting with the
+/// extended tag.
+pub fn cta_data_blocks(edid: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut blocks = vec![];
+    if !is_valid(edid) {
+        return blocks;
+    }
+
+    for extension in edid[BLOCK_SIZE..].chunks_exact(BLOCK_SIZE) {
+        if extension[0] != CTA_EXTENSION {
+            continue;
+        }
+        // Data blocks are stored between byte 4 and the detailed timings
+        let end = (extension[2] as usize).min(BLOCK_SIZE);
+        let mut offset = 4;
+        while offset < end {
+            let tag = extension[offset] >> 5;
+            let length = (extension[offset] & 0x1f) as usize;
+            let Some(payload) = extension.get(offset + 1..offset + 1 + length) else {
+                break;
+            };
+            blocks.push((tag, payload));
+            offset += 1 + length;
+        }
+    }
+
+    blocks
+}
+
+/// HDR capabilities of a display (CTA-861 HDR static metadata data block)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    /// SMPTE ST 2084 (PQ, HDR10) transfer function
+    pub pq: bool,
+    /// Hybrid log-gamma transfer function
+    pub hlg: bool,
+    /// Traditional gamma, HDR luminance range
+    pub traditional_hdr: bool,
+    /// Desired content max luminance in cd/m², if given
+    pub max_luminance: Option<f32>,
+    /// Desired content max frame-average luminance in cd/m², if given
+    pub max_frame_avg_luminance: Option<f32>,
+    /// Desired content min luminance in cd/m², if given
+    pub min_luminance: Option<f32>,
+}
+
+impl HdrMetadata {
+    /// Whether an HDR transfer function is supported
+    pub fn is_hdr(&self) -> bool {
+        self.pq || self.hlg || self.traditional_hdr
+    }
+}
+
+/// Get the HDR static metadata data block
+pub fn hdr_metadata(edid: &[u8]) -> Option<HdrMetadata> {
+    #[cfg(feature = "libdisplay-info")]
+    if let Some(info) = libdisplay_info::Info::parse(edid) {
+        return info.hdr_metadata();
+    }
+    let blocks = cta_data_blocks(edid);
+    let payload = blocks.iter().find_map(|(tag, payload)| {
+        (*tag == CTA_EXTENDED_TAG && payload.first() == Some(&CTA_HDR_STATIC_METADATA))
+            .then_some(payload)
+    })?;
+    let eotfs = payload.get(1).copied().unwrap_or_default();
+    // Coded values, 0 when not given (CTA-861-G 7.5.13)
+    let code = |index: usize| payload.get(index).copied().filter(|c| *c != 0);
+    let max_luminance = code(3).map(|c| 50.0 * 2f32.powf(f32::from(c) / 32.0));
+    Some(HdrMetadata {
+        pq: eotfs & 0x04 != 0,
+        hlg: eotfs & 0x08 != 0,
+        traditional_hdr: eotfs & 0x02 != 0,
+        max_luminance,
+        max_frame_avg_luminance: code(4).map(|c| 50.0 * 2f32.powf(f32::from(c) / 32.0)),
+        min_luminance: max_luminance
+            .zip(code(5))
+            .map(|(max, c)| max * (f32::from(c) / 255.0).powi(2) / 100.0),
+    })
+}
+
+/// Whether the display advertises an HDR transfer function (see [`hdr_metadata`])
+pub fn has_hdr(edid: &[u8]) -> bool {
+    hdr_metadata(edid).is_some_and(|m| m.is_hdr())
+}
+
+/// Extended colorimetries of a display (CTA-861 colorimetry data block)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Colorimetry {
+    /// ITU-R BT.2020 RGB
+    pub bt2020_rgb: bool,
+    /// ITU-R BT.2020 YCbCr (constant or non-constant luminance)
+    pub bt2020_ycc: bool,
+    /// DCI-P3 (SMPTE RP 431-2)
+    pub dci_p3: bool,
+    /// opRGB (Adobe RGB)
+    pub op_rgb: bool,
+}
+
+impl Colorimetry {
+    /// Whether a color space wider than sRGB is supported
+    pub fn is_wide_gamut(&self) -> bool {
+        self.bt2020_rgb || self.bt2020_ycc || self.dci_p3 || self.op_rgb
+    }
+}
+
+/// Get the colorimetry data block, `None` for sRGB-only displays
+pub fn colorimetry(edid: &[u8]) -> Option<Colorimetry> {
+    let blocks = cta_data_blocks(edid);
+    let payload = blocks.iter().find_map(|(tag, payload)| {
+        (*tag == CTA_EXTENDED_TAG && payload.first() == Some(&CTA_COLORIMETRY)).then_some(payload)
+    })?;
+    let flags = payload.get(1).copied().unwrap_or_default();
+    let metadata = payload.get(2).copied().unwrap_or_default();
+    Some(Colorimetry {
+        bt2020_rgb: flags & 0x80 != 0,
+        bt2020_ycc: flags & 0x60 != 0,
+        dci_p3: metadata & 0x80 != 0,
+        op_rgb: flags & 0x10 != 0,
+    })
+}
+
+/// Native color primaries and white point of a display, in CIE 1931 xy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chromaticity {
+    pub red: (f32, f32),
+    pub green: (f32, f32),
+    pub blue: (f32, f32),
+    pub white: (f32, f32),
+}
+
+/// Signed area of a polygon, positive when counterclockwise
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for (i, (x1, y1)) in points.iter().enumerate() {
+        let (x2, y2) = points[(i + 1) % points.len()];
+        area += x1 * y2 - x2 * y1;
+    }
+    area / 2.0
+}
+
+/// Clip a polygon by a convex counterclockwise polygon (Sutherland–Hodgman)
+fn clip(subject: &[(f32, f32)], clip: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    // Whether p is left of the edge a -> b
+    let inside = |p: (f32, f32), a: (f32, f32), b: (f32, f32)| {
+        (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0) >= 0.0
+    };
+    let intersection = |p: (f32, f32), q: (f32, f32), a: (f32, f32), b: (f32, f32)| {
+        let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+        let (ex, ey) = (b.0 - a.0, b.1 - a.1);
+        let t = (ex * (p.1 - a.1) - ey * (p.0 - a.0)) / (ey * dx - ex * dy);
+        (p.0 + t * dx, p.1 + t * dy)
+    };
+
+    let mut output = subject.to_vec();
+    for (i, &a) in clip.iter().enumerate() {
+        let b = clip[(i + 1) % clip.len()];
+        let input = std::mem::take(&mut output);
+        for (j, &p) in input.iter().enumerate() {
+            let q = input[(j + 1) % input.len()];
+            match (inside(p, a, b), inside(q, a, b)) {
+                (true, true) => output.push(q),
+                (true, false) => output.push(intersection(p, q, a, b)),
+                (false, true) => output.extend([intersection(p, q, a, b), q]),
+                (false, false) => {}
+            }
+        }
+    }
+    output
+}
+
+impl Chromaticity {
+    /// Triangle of the primaries, counterclockwise
+    fn primaries(&self) -> [(f32, f32); 3] {
+        let primaries = [self.red, self.green, self.blue];
+        if signed_area(&primaries) < 0.0 {
+            [self.blue, self.green, self.red]
+        } else {
+            primaries
+        }
+    }
+
+    /// Area of the gamut relative to sRGB's (eg. ~1.36 for DCI-P3)
+    pub fn gamut_area(&self) -> f32 {
+        signed_area(&self.primaries()) / signed_area(&SRGB)
+    }
+
+    /// Part of the sRGB gamut the display covers, from 0 to 1
+    pub fn srgb_coverage(&self) -> f32 {
+        signed_area(&clip(&SRGB, &self.primaries())) / signed_area(&SRGB)
+    }
+
+    /// Whether the gamut is significantly wider than sRGB (see [`WIDE_GAMUT_AREA`])
+    ///
+    /// Games rendering sRGB content without color management look oversaturated on these.
+    pub fn is_wide_gamut(&self) -> bool {
+        self.gamut_area() >= WIDE_GAMUT_AREA
+    }
+}
+
+/// Get the chromaticity coordinates of the base block
+pub fn chromaticity(edid: &[u8]) -> Option<Chromaticity> {
+    if !is_valid(edid) {
+        return None;
+    }
+    let data = &edid[CHROMATICITY];
+    // 10-bit fractions, the 2 low bits of each are packed in the first two bytes
+    let coordinate = |index: usize| {
+        let low = (data[index / 4] >> (6 - 2 * (index % 4))) & 0x03;
+        f32::from(u16::from(data[2 + index]) << 2 | u16::from(low)) / 1024.0
+    };
+    let point = |index: usize| (coordinate(index), coordinate(index + 1));
+    let chromaticity = Chromaticity {
+        red: point(0),
+        green: point(2),
+        blue: point(4),
+        white: point(6),
+    };
+    // Unset by some virtual displays and adapters
+    (signed_area(&chromaticity.primaries()) > 0.0).then_some(chromaticity)
+}
+
+/// Whether the display supports Auto Low Latency Mode (HDMI 2.1)
+///
+/// ALLM lets the source switch a TV to its game mode. Advertised by the HDMI Forum
+/// vendor-specific data block, or the sink capability data block replacing it.
+pub fn has_allm(edid: &[u8]) -> bool {
+    cta_data_blocks(edid).iter().any(|(tag, payload)| {
+        // Both start with 3 bytes (OUI, or extended tag and reserved), then the same fields
+        let forum = (*tag == CTA_VENDOR_SPECIFIC && payload.get(..3) == Some(&HDMI_FORUM_OUI))
+            || (*tag == CTA_EXTENDED_TAG && payload.first() == Some(&CTA_HDMI_FORUM_SCDB));
+        forum && payload.get(7).is_some_and(|flags| flags & 0x02 != 0)
+    })
+}
+
+/// Get the maximum bits per color component supported by the display
+///
+/// Read from the base block (EDID 1.4 digital input), and from the deep color modes of the
+/// HDMI vendor-specific data block, which HDMI sinks usually rely on.
+pub fn bits_per_component(edid: &[u8]) -> Option<u8> {
+    if !is_valid(edid) {
+        return None;
+    }
+    // Video input definition, bits 6-4: 6, 8, 10, 12, 14 or 16 bits
+    let base = (edid[18] == 1 && edid[19] >= 4 && edid[20] & 0x80 != 0)
+        .then(|| (edid[20] >> 4) & 0x07)
+        .filter(|depth| (1..=6).contains(depth))
+        .map(|depth| 4 + 2 * depth);
+    let hdmi = cta_data_blocks(edid).iter().find_map(|(tag, payload)| {
+        if *tag != CTA_VENDOR_SPECIFIC || payload.get(..3) != Some(&HDMI_OUI[..]) {
+            return None;
+        }
+        // Deep color flags: 48, 36 and 30 bits per pixel
+        let flags = payload.get(5).copied().unwrap_or_default();
+        [(0x40, 16), (0x20, 12), (0x10, 10)]
+            .into_iter()
+            .find_map(|(flag, depth)| (flags & flag != 0).then_some(depth))
+            .or(Some(8))
+    });
+    base.max(hdmi)
+}
+
+/// Position of a display in a tiled display (eg. 5K monitors driven as two 2560x2880 tiles)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// Number of tiles horizontally
+    pub columns: u16,
+    /// Number of tiles vertically
+    pub rows: u16,
+    /// Column of this tile, starting at 0
+    pub column: u16,
+    /// Row of this tile, starting at 0
+    pub row: u16,
+    /// Resolution of the tile
+    pub size: (u16, u16),
+}
+
+/// Get the tiled display topology (DisplayID), needs the `libdisplay-info` feature
+pub fn tile(edid: &[u8]) -> Option<Tile> {
+    #[cfg(feature = "libdisplay-info")]
+    return libdisplay_info::Info::parse(edid)?.tile();
+    #[cfg(not(feature = "libdisplay-info"))]
+    {
+        let _ = edid;
+        None
+    }
+}
+
+/// Mask the serial numbers of the base block, keeping the model and modes
+///
+/// The serial number field and the display serial number descriptor are cleared, and the
+/// checksum updated so that the EDID stays valid. Extension blocks are kept as is.
+pub fn redact(edid: &mut [u8]) {
+    if !is_valid(edid) {
+        return;
+    }
+    edid[SERIAL].fill(0);
+    for offset in DESCRIPTORS {
+        let descriptor = &mut edid[offset..offset + DESCRIPTOR_SIZE];
+        if descriptor[..3] == [0, 0, 0] && descriptor[3] == DESCRIPTOR_SERIAL {
+            descriptor[5..].copy_from_slice(b"REDACTED\n    ");
+        }
+    }
+    let sum = edid[..BLOCK_SIZE - 1]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    edid[BLOCK_SIZE - 1] = sum.wrapping_neg();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A base block followed by a CTA extension holding `blocks`
+    fn with_cta_blocks(blocks: &[u8]) -> Vec<u8> {
+        let mut edid = vec![0; BLOCK_SIZE * 2];
+        edid[..8].copy_from_slice(&HEADER);
+        edid[126] = 1;
+        let cta = &mut edid[BLOCK_SIZE..];
+        cta[0] = CTA_EXTENSION;
+        cta[1] = 3;
+        cta[2] = 4 + blocks.len() as u8;
+        cta[4..4 + blocks.len()].copy_from_slice(blocks);
+        edid
+    }
+
+    #[test]
+    fn hdr_metadata() {
+        // PQ and traditional SDR, 604 / 418 / 0.42 cd/m²
+        let edid = with_cta_blocks(&[0xe6, 0x06, 0x05, 0x01, 0x73, 0x62, 0x43]);
+        let hdr = super::hdr_metadata(&edid).unwrap();
+        assert!(hdr.pq && !hdr.hlg && !hdr.traditional_hdr);
+        assert_eq!(hdr.max_luminance.map(f32::round), Some(604.0));
+        assert_eq!(hdr.max_frame_avg_luminance.map(f32::round), Some(418.0));
+        assert!(hdr.min_luminance.is_some_and(|l| (l - 0.42).abs() < 0.01));
+        assert!(has_hdr(&edid));
+
+        // SDR only, without luminance
+        let edid = with_cta_blocks(&[0xe3, 0x06, 0x01, 0x00]);
+        let hdr = super::hdr_metadata(&edid).unwrap();
+        assert!(!hdr.is_hdr());
+        assert_eq!(hdr.max_luminance, None);
+        assert!(!has_hdr(&edid));
+
+        assert_eq!(super::hdr_metadata(&with_cta_blocks(&[])), None);
+    }
+
+    #[test]
+    fn colorimetry() {
+        // BT.2020 RGB and YCC, DCI-P3 metadata
+        let edid = with_cta_blocks(&[0xe3, 0x05, 0xc0, 0x80]);
+        let colorimetry = super::colorimetry(&edid).unwrap();
+        assert!(colorimetry.bt2020_rgb && colorimetry.bt2020_ycc && colorimetry.dci_p3);
+        assert!(colorimetry.is_wide_gamut());
+
+        // xvYCC only
+        let edid = with_cta_blocks(&[0xe3, 0x05, 0x03, 0x00]);
+        assert!(!super::colorimetry(&edid).unwrap().is_wide_gamut());
+        assert_eq!(super::colorimetry(&with_cta_blocks(&[])), None);
+    }
+
+    /// Store chromaticity coordinates in a base block
+    fn set_chromaticity(edid: &mut [u8], points: [(f32, f32); 4]) {
+        let data = &mut edid[CHROMATICITY];
+        data.fill(0);
+        for (index, value) in points.iter().flat_map(|(x, y)| [x, y]).enumerate() {
+            let value = (value * 1024.0).round() as u16;
+            data[index / 4] |= ((value & 0x03) as u8) << (6 - 2 * (index % 4));
+            data[2 + index] = (value >> 2) as u8;
+        }
+    }
+
+    #[test]
+    fn chromaticity() {
+        let d65 = (0.3127, 0.329);
+        let mut edid = with_cta_blocks(&[]);
+        assert_eq!(super::chromaticity(&edid), None);
+
+        set_chromaticity(&mut edid, [SRGB[0], SRGB[1], SRGB[2], d65]);
+        let srgb = super::chromaticity(&edid).unwrap();
+        assert!((srgb.white.0 - 0.3127).abs() < 0.001);
+        assert!((srgb.gamut_area() - 1.0).abs() < 0.01);
+        assert!((srgb.srgb_coverage() - 1.0).abs() < 0.01);
+        assert!(!srgb.is_wide_gamut());
+
+        // DCI-P3
+        set_chromaticity(&mut edid, [(0.68, 0.32), (0.265, 0.69), (0.15, 0.06), d65]);
+        let p3 = super::chromaticity(&edid).unwrap();
+        assert!((p3.gamut_area() - 1.36).abs() < 0.01);
+        assert!((p3.srgb_coverage() - 1.0).abs() < 0.01);
+        assert!(p3.is_wide_gamut());
+
+        // A 45% NTSC laptop panel
+        set_chromaticity(&mut edid, [(0.59, 0.35), (0.33, 0.555), (0.155, 0.11), d65]);
+        let panel = super::chromaticity(&edid).unwrap();
+        assert!(panel.srgb_coverage() < 0.75);
+        assert!(!panel.is_wide_gamut());
+    }
+
+    #[test]
+    fn allm() {
+        // HF-VSDB: version 1, 600 MHz TMDS, ALLM
+        let edid = with_cta_blocks(&[0x68, 0xd8, 0x5d, 0xc4, 0x01, 0x78, 0x80, 0x00, 0x02]);
+        assert!(has_allm(&edid));
+        let edid = with_cta_blocks(&[0x68, 0xd8, 0x5d, 0xc4, 0x01, 0x78, 0x80, 0x00, 0x00]);
+        assert!(!has_allm(&edid));
+        // HF-SCDB
+        let edid = with_cta_blocks(&[0xe8, 0x79, 0x00, 0x00, 0x01, 0x78, 0x80, 0x00, 0x02]);
+        assert!(has_allm(&edid));
+        assert!(!has_allm(&with_cta_blocks(&[])));
+    }
+
+    #[test]
+    fn bits_per_component() {
+        let mut edid = with_cta_blocks(&[]);
+        assert_eq!(super::bits_per_component(&edid), None);
+        // EDID 1.4, digital, 10 bits
+        edid[18..21].copy_from_slice(&[1, 4, 0xb5]);
+        assert_eq!(super::bits_per_component(&edid), Some(10));
+
+        // HDMI with 30 and 36 bits deep color
+        let edid = with_cta_blocks(&[0x66, 0x03, 0x0c, 0x00, 0x10, 0x00, 0x30]);
+        assert_eq!(super::bits_per_component(&edid), Some(12));
+    }
+
+    #[test]
+    fn refresh_range() {
+        let mut edid = [0u8; BLOCK_SIZE];
+        edid[..8].copy_from_slice(&HEADER);
+        assert_eq!(super::refresh_range(&edid), None);
+
+        // 48-165 Hz
+        edid[90..99].copy_from_slice(&[0, 0, 0, DESCRIPTOR_RANGE_LIMITS, 0, 48, 165, 30, 255]);
+        assert_eq!(super::refresh_range(&edid), Some((48, 165)));
+        // 48-360 Hz, with the max offset
+        edid[94] = 0x02;
+        edid[96] = 105;
+        assert_eq!(super::refresh_range(&edid), Some((48, 360)));
+    }
+
+    #[test]
+    fn redact() {
+        let mut edid = [0u8; BLOCK_SIZE];
+        edid[..8].copy_from_slice(&HEADER);
+        // Manufacturer and product
+        edid[8..12].copy_from_slice(&[0x10, 0xac, 0x34, 0x12]);
+        edid[12..16].copy_from_slice(&[1, 2, 3, 4]);
+        edid[21..23].copy_from_slice(&[60, 34]);
+        edid[72..75].fill(0);
+        edid[75] = DESCRIPTOR_SERIAL;
+        edid[77..90].copy_from_slice(b"ABC123456789\n");
+        edid[90..95].copy_from_slice(&[0, 0, 0, 0xfc, 0]);
+        edid[95..108].copy_from_slice(b"DELL U2720Q\n ");
+
+        super::redact(&mut edid);
+        assert_eq!(edid[8..12], [0x10, 0xac, 0x34, 0x12]);
+        assert_eq!(edid[12..16], [0, 0, 0, 0]);
+        assert_eq!(&edid[77..90], b"REDACTED\n    ");
+        assert_eq!(&edid[95..108], b"DELL U2720Q\n ");
+        assert_eq!(physical_size(&edid), Some((600, 340)));
+        let sum = edid.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(sum, 0);
+    }
+}
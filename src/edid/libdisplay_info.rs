@@ -0,0 +1,207 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! EDID and DisplayID parsing through libdisplay-info (0.2)
+//!
+//! Only the structures rres reads are declared, up to the last field it uses.
+
+use std::ffi::c_void;
+use std::os::raw::c_int;
+
+use super::{HdrMetadata, Tile};
+
+/// `DI_EDID_EXT_DISPLAYID`
+const EXT_DISPLAYID: c_int = 0x70;
+/// `DI_DISPLAYID_DATA_BLOCK_TILED_DISPLAY_TOPO`
+const DATA_BLOCK_TILED_DISPLAY_TOPO: c_int = 0x12;
+
+/// `struct di_info`
+#[repr(C)]
+struct DiInfo {
+    _private: [u8; 0],
+}
+/// `struct di_edid`
+#[repr(C)]
+struct Edid {
+    _private: [u8; 0],
+}
+/// `struct di_edid_ext`
+#[repr(C)]
+struct EdidExtension {
+    _private: [u8; 0],
+}
+/// `struct di_displayid`
+#[repr(C)]
+struct DisplayId {
+    _private: [u8; 0],
+}
+/// `struct di_displayid_data_block`
+#[repr(C)]
+struct DataBlock {
+    _private: [u8; 0],
+}
+
+/// `struct di_edid_screen_size`
+#[repr(C)]
+struct ScreenSize {
+    width_cm: c_int,
+    height_cm: c_int,
+}
+
+/// `struct di_hdr_static_metadata`
+#[repr(C)]
+#[allow(dead_code)]
+struct HdrStaticMetadata {
+    desired_content_max_luminance: f32,
+    desired_content_max_frame_avg_luminance: f32,
+    desired_content_min_luminance: f32,
+    type1: bool,
+    traditional_sdr: bool,
+    traditional_hdr: bool,
+    pq: bool,
+    hlg: bool,
+}
+
+/// `struct di_displayid_tiled_topo`
+#[repr(C)]
+#[allow(dead_code)]
+struct TiledTopology {
+    caps: *const c_void,
+    total_horiz_tiles: c_int,
+    total_vert_tiles: c_int,
+    horiz_tile_location: c_int,
+    vert_tile_location: c_int,
+    horiz_tile_pixels: c_int,
+    vert_tile_lines: c_int,
+}
+
+#[link(name = "display-info")]
+extern "C" {
+    fn di_info_parse_edid(data: *const c_void, size: usize) -> *mut DiInfo;
+    fn di_info_destroy(info: *mut DiInfo);
+    fn di_info_get_edid(info: *const DiInfo) -> *const Edid;
+    fn di_info_get_hdr_static_metadata(info: *const DiInfo) -> *const HdrStaticMetadata;
+    fn di_edid_get_screen_size(edid: *const Edid) -> *const ScreenSize;
+    fn di_edid_get_extensions(edid: *const Edid) -> *const *const EdidExtension;
+    fn di_edid_ext_get_tag(ext: *const EdidExtension) -> c_int;
+    fn di_edid_ext_get_displayid(ext: *const EdidExtension) -> *const DisplayId;
+    fn di_displayid_get_data_blocks(displayid: *const DisplayId) -> *const *const DataBlock;
+    fn di_displayid_data_block_get_tag(block: *const DataBlock) -> c_int;
+    fn di_displayid_data_block_get_tiled_topo(block: *const DataBlock) -> *const TiledTopology;
+}
+
+/// Iterate a NULL-terminated array
+///
+/// # Safety
+///
+/// `array` must be NULL or a NULL-terminated array, living as long as `'a`.
+unsafe fn null_terminated<'a, T: 'a>(array: *const *const T) -> impl Iterator<Item = &'a T> {
+    let mut index = 0;
+    std::iter::from_fn(move || {
+        if array.is_null() {
+            return None;
+        }
+        let item = *array.add(index);
+        index += 1;
+        item.as_ref()
+    })
+}
+
+/// A parsed EDID
+pub struct Info(*mut DiInfo);
+
+impl Info {
+    /// Parse an EDID, `None` if libdisplay-info rejects it
+    pub fn parse(edid: &[u8]) -> Option<Self> {
+        // SAFETY: the data is only read during the call
+        let info = unsafe { di_info_parse_edid(edid.as_ptr().cast(), edid.len()) };
+        (!info.is_null()).then_some(Self(info))
+    }
+
+    fn edid(&self) -> Option<&Edid> {
+        // SAFETY: the EDID lives as long as the info
+        unsafe { di_info_get_edid(self.0).as_ref() }
+    }
+
+    /// Physical size in millimeters
+    pub fn physical_size(&self) -> Option<(u32, u32)> {
+        // SAFETY: the screen size lives as long as the info
+        let size = unsafe { di_edid_get_screen_size(self.edid()?).as_ref()? };
+        match (u32::try_from(size.width_cm), u32::try_from(size.height_cm)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width * 10, height * 10)),
+            _ => None,
+        }
+    }
+
+    /// HDR static metadata, luminances are 0 when not given
+    pub fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        // SAFETY: the metadata lives as long as the info
+        let metadata = unsafe { di_info_get_hdr_static_metadata(self.0).as_ref()? };
+        let luminance = |l: f32| (l > 0.0).then_some(l);
+        Some(HdrMetadata {
+            pq: metadata.pq,
+            hlg: metadata.hlg,
+            traditional_hdr: metadata.traditional_hdr,
+            max_luminance: luminance(metadata.desired_content_max_luminance),
+            max_frame_avg_luminance: luminance(metadata.desired_content_max_frame_avg_luminance),
+            min_luminance: luminance(metadata.desired_content_min_luminance),
+        })
+    }
+
+    /// Position of the display in a tiled display (DisplayID tiled display topology)
+    pub fn tile(&self) -> Option<Tile> {
+        let edid = self.edid()?;
+        // SAFETY: every structure lives as long as the info, arrays are NULL-terminated
+        unsafe {
+            for extension in null_terminated(di_edid_get_extensions(edid)) {
+                if di_edid_ext_get_tag(extension) != EXT_DISPLAYID {
+                    continue;
+                }
+                let Some(displayid) = di_edid_ext_get_displayid(extension).as_ref() else {
+                    continue;
+                };
+                for block in null_terminated(di_displayid_get_data_blocks(displayid)) {
+                    if di_displayid_data_block_get_tag(block) != DATA_BLOCK_TILED_DISPLAY_TOPO {
+                        continue;
+                    }
+                    let Some(topology) = di_displayid_data_block_get_tiled_topo(block).as_ref()
+                    else {
+                        continue;
+                    };
+                    return Some(Tile {
+                        columns: topology.total_horiz_tiles.try_into().ok()?,
+                        rows: topology.total_vert_tiles.try_into().ok()?,
+                        column: topology.horiz_tile_location.try_into().ok()?,
+                        row: topology.vert_tile_location.try_into().ok()?,
+                        size: (
+                            topology.horiz_tile_pixels.try_into().ok()?,
+                            topology.vert_tile_lines.try_into().ok()?,
+                        ),
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Info {
+    fn drop(&mut self) {
+        // SAFETY: the info was returned by di_info_parse_edid
+        unsafe { di_info_destroy(self.0) }
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Runtime environment detection
+//!
+//! WSL, containers and Flatpak usually have no access to the GPU's displays, only to a
+//! compositor.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// An environment where the displays can't be read directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    /// Windows Subsystem for Linux (WSLg provides a Wayland and X11 server)
+    Wsl,
+    /// Docker, Podman, LXC, systemd-nspawn...
+    Container,
+    /// ChromeOS Linux container, displays are sommelier's Wayland outputs
+    Crostini,
+    /// Flatpak sandbox, without `/dev/dri` unless run with `--device=dri`
+    Flatpak,
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wsl => write!(f, "WSL"),
+            Self::Container => write!(f, "container"),
+            Self::Crostini => write!(f, "Crostini"),
+            Self::Flatpak => write!(f, "Flatpak"),
+        }
+    }
+}
+
+/// Detect the environment rres runs in, `None` on a regular system
+pub fn detect() -> Option<Environment> {
+    if is_wsl() {
+        Some(Environment::Wsl)
+    } else if is_flatpak() {
+        Some(Environment::Flatpak)
+    } else if is_crostini() {
+        Some(Environment::Crostini)
+    } else if is_container() {
+        Some(Environment::Container)
+    } else {
+        None
+    }
+}
+
+/// Whether rres runs in WSL
+fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease")
+            .map(|r| r.to_lowercase().contains("microsoft"))
+            .unwrap_or(false)
+}
+
+/// Whether rres runs in a Flatpak sandbox
+fn is_flatpak() -> bool {
+    env::var_os("FLATPAK_ID").is_some() || Path::new("/.flatpak-info").exists()
+}
+
+/// Whether rres runs in Crostini
+fn is_crostini() -> bool {
+    Path::new("/dev/.cros_milestone").exists()
+}
+
+/// Whether rres runs in a container
+fn is_container() -> bool {
+    // Set by systemd-nspawn, Podman, LXC...
+    env::var_os("container").is_some()
+        || Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+}
+
+/// Hint on how to give rres access to the displays, for the error messages
+pub fn hint(environment: Environment) -> &'static str {
+    match environment {
+        Environment::Wsl => {
+            "WSLg provides a Wayland and X11 server, check WAYLAND_DISPLAY and DISPLAY"
+        }
+        Environment::Container => "pass /dev/dri, or the Wayland or X11 socket, to the container",
+        Environment::Crostini => "sommelier provides a Wayland server, check WAYLAND_DISPLAY",
+        Environment::Flatpak => {
+            "run the Flatpak with --device=dri, --socket=wayland or --socket=x11"
+        }
+    }
+}
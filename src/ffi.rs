@@ -0,0 +1,229 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! C API (`ffi` feature)
+//!
+//! Build the shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`. The header is
+//! `include/rres.h`, regenerate it with
+//! `cbindgen --config cbindgen.toml -o include/rres.h src/ffi.rs`.
+//!
+//! Functions return `0` on success and `-1` on failure, the error message being available
+//! from [`rres_last_error`] on the same thread. Display detection follows the environment
+//! variables, like the `rres` command.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::{modes, ProbeOptions};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Store the error for [`rres_last_error`], returning `-1`
+fn fail(error: anyhow::Error) -> c_int {
+    let message = CString::new(format!("{error:#}").replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+    -1
+}
+
+/// Read an optional C string argument
+///
+/// # Safety
+///
+/// `value` must be null or a valid NUL terminated string.
+unsafe fn optional_str(value: *const c_char) -> anyhow::Result<Option<String>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(value).to_str()?.to_string()))
+}
+
+/// A display mode
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RresMode {
+    pub width: u16,
+    pub height: u16,
+    /// Refresh rate in Hz, `0` if unknown
+    pub refresh: f64,
+}
+
+impl From<&crate::Mode> for RresMode {
+    fn from(mode: &crate::Mode) -> Self {
+        Self {
+            width: mode.hdisplay,
+            height: mode.vdisplay,
+            refresh: modes::refresh(mode),
+        }
+    }
+}
+
+/// A connected display
+#[repr(C)]
+#[derive(Debug)]
+pub struct RresDisplay {
+    /// Card the display is connected to (eg. `/dev/dri/card0`)
+    pub card: *mut c_char,
+    /// Connector name (eg. `DP-1`)
+    pub connector: *mut c_char,
+    /// Current mode
+    pub mode: RresMode,
+    /// Whether the display is built into the device (laptop or handheld panel)
+    pub internal: bool,
+}
+
+/// A list of displays, freed with [`rres_free_displays`]
+#[repr(C)]
+#[derive(Debug)]
+pub struct RresDisplayList {
+    pub displays: *mut RresDisplay,
+    pub len: usize,
+}
+
+/// Get the message of the last error of this thread, null if none
+///
+/// The string stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn rres_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Get the resolution of the first display
+///
+/// `card` selects a card (eg. `card0`), every card is probed if null.
+///
+/// # Safety
+///
+/// `card` must be null or a valid string, `width` and `height` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn rres_get_resolution(
+    card: *const c_char,
+    width: *mut u16,
+    height: *mut u16,
+) -> c_int {
+    let card = match optional_str(card) {
+        Ok(card) => card,
+        Err(e) => return fail(e),
+    };
+    match crate::get_res_card(card) {
+        Ok(res) => {
+            *width = res.0;
+            *height = res.1;
+            0
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// List every connected display
+///
+/// `card` selects a card (eg. `card0`), every card is probed if null. The list must be freed
+/// with [`rres_free_displays`].
+///
+/// # Safety
+///
+/// `card` must be null or a valid string, `list` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn rres_list_displays(
+    card: *const c_char,
+    list: *mut RresDisplayList,
+) -> c_int {
+    let card = match optional_str(card) {
+        Ok(card) => card,
+        Err(e) => return fail(e),
+    };
+    let displays = match crate::probe(&ProbeOptions {
+        card,
+        ..Default::default()
+    }) {
+        Ok(displays) => displays,
+        Err(e) => return fail(e),
+    };
+
+    let displays: Box<[RresDisplay]> = displays
+        .iter()
+        .map(|d| RresDisplay {
+            card: CString::new(d.card.to_string_lossy().as_bytes())
+                .unwrap_or_default()
+                .into_raw(),
+            connector: CString::new(d.connector.as_str())
+                .unwrap_or_default()
+                .into_raw(),
+            mode: (&d.mode).into(),
+            internal: d.is_internal(),
+        })
+        .collect();
+    *list = RresDisplayList {
+        len: displays.len(),
+        displays: Box::into_raw(displays).cast(),
+    };
+    0
+}
+
+/// Free a list of displays returned by [`rres_list_displays`]
+///
+/// # Safety
+///
+/// `list` must be null or a list filled by [`rres_list_displays`], not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rres_free_displays(list: *mut RresDisplayList) {
+    let Some(list) = list.as_mut() else {
+        return;
+    };
+    if list.displays.is_null() {
+        return;
+    }
+    let displays = Box::from_raw(ptr::slice_from_raw_parts_mut(list.displays, list.len));
+    for display in displays.iter() {
+        drop(CString::from_raw(display.card));
+        drop(CString::from_raw(display.connector));
+    }
+    list.displays = ptr::null_mut();
+    list.len = 0;
+}
+
+/// Get the render resolution of a gamescope mode (`native` or a FSR preset: `ultra`,
+/// `quality`, `balanced`, `performance`) for a display resolution, null meaning `native`
+///
+/// # Safety
+///
+/// `fsr_mode` must be null or a valid string, `render_width` and `render_height` must be valid
+/// pointers.
+#[no_mangle]
+pub unsafe extern "C" fn rres_render_resolution(
+    width: u16,
+    height: u16,
+    fsr_mode: *const c_char,
+    render_width: *mut u16,
+    render_height: *mut u16,
+) -> c_int {
+    let fsr_mode = match optional_str(fsr_mode) {
+        Ok(mode) => mode.unwrap_or_default(),
+        Err(e) => return fail(e),
+    };
+    match crate::render_res((width, height), &fsr_mode) {
+        Ok(res) => {
+            *render_width = res.0;
+            *render_height = res.1;
+            0
+        }
+        Err(e) => fail(e),
+    }
+}
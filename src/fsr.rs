@@ -1,3 +1,7 @@
+//! AMD FidelityFX Super Resolution presets
+
+/// FSR quality preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fsr {
     Ultra,
     Quality,
@@ -19,6 +23,7 @@ impl TryFrom<&str> for Fsr {
 }
 
 impl Fsr {
+    /// Get the render resolution for the target (display) resolution
     pub fn generate(&self, target_res: (u16, u16)) -> (u16, u16) {
         if target_res == (1920, 1080) {
             match self {
@@ -56,10 +61,169 @@ impl Fsr {
                 Self::Performance => 2.0f32,
             };
 
-            return (
-                (f32::from(target_res.0) / factor).floor() as u16,
-                (f32::from(target_res.1) / factor).floor() as u16,
-            );
+            // Tiny targets still get a pixel
+            (
+                ((f32::from(target_res.0) / factor).floor() as u16).max(target_res.0.min(1)),
+                ((f32::from(target_res.1) / factor).floor() as u16).max(target_res.1.min(1)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const PRESETS: [Fsr; 4] = [Fsr::Ultra, Fsr::Quality, Fsr::Balanced, Fsr::Performance];
+
+    /// Target resolution, and the render resolution of each preset
+    type Golden = ((u16, u16), [(u16, u16); 4]);
+
+    /// Render resolutions of the hardcoded targets, in the order of [`PRESETS`]
+    const TABLES: [Golden; 4] = [
+        (
+            (1920, 1080),
+            [(1477, 831), (1280, 720), (1129, 635), (960, 540)],
+        ),
+        (
+            (2560, 1440),
+            [(1970, 1108), (1706, 960), (1506, 847), (1280, 720)],
+        ),
+        (
+            (3440, 1440),
+            [(2646, 1108), (2293, 960), (2024, 847), (1720, 720)],
+        ),
+        (
+            (3840, 2160),
+            [(2954, 1662), (2560, 1440), (2259, 1270), (1920, 1080)],
+        ),
+    ];
+
+    /// Render resolutions computed by the formula, in the order of [`PRESETS`]
+    const FORMULA: [Golden; 4] = [
+        (
+            (1280, 720),
+            [(984, 553), (853, 480), (752, 423), (640, 360)],
+        ),
+        (
+            (1280, 800),
+            [(984, 615), (853, 533), (752, 470), (640, 400)],
+        ),
+        (
+            (2560, 1080),
+            [(1969, 830), (1706, 720), (1505, 635), (1280, 540)],
+        ),
+        (
+            (5120, 1440),
+            [(3938, 1107), (3413, 960), (3011, 847), (2560, 720)],
+        ),
+    ];
+
+    #[test]
+    fn tables() {
+        for (target, expected) in TABLES {
+            for (fsr, res) in PRESETS.iter().zip(expected) {
+                assert_eq!(fsr.generate(target), res, "{fsr:?} at {target:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn formula() {
+        for (target, expected) in FORMULA {
+            for (fsr, res) in PRESETS.iter().zip(expected) {
+                assert_eq!(fsr.generate(target), res, "{fsr:?} at {target:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn tables_follow_the_formula() {
+        // The tables round where the formula floors, they may only differ by a pixel
+        let factors = [1.3f32, 1.5, 1.7, 2.0];
+        for (target, expected) in TABLES {
+            for (factor, res) in factors.iter().zip(expected) {
+                let width = f32::from(target.0) / factor;
+                let height = f32::from(target.1) / factor;
+                assert!(
+                    (f32::from(res.0) - width).abs() <= 1.0
+                        && (f32::from(res.1) - height).abs() <= 1.0,
+                    "{res:?} at {target:?} is off the {factor} ratio"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse() {
+        for (name, fsr) in ["ultra", "quality", "balanced", "performance"]
+            .iter()
+            .zip(PRESETS)
+        {
+            assert_eq!(Fsr::try_from(*name), Ok(fsr));
+            assert_eq!(Fsr::try_from(name.to_uppercase().as_str()), Ok(fsr));
+        }
+        assert!(Fsr::try_from("native").is_err());
+        assert!(Fsr::try_from("").is_err());
+    }
+
+    fn factor(fsr: Fsr) -> f32 {
+        match fsr {
+            Fsr::Ultra => 1.3,
+            Fsr::Quality => 1.5,
+            Fsr::Balanced => 1.7,
+            Fsr::Performance => 2.0,
+        }
+    }
+
+    fn preset() -> impl Strategy<Value = Fsr> {
+        prop::sample::select(PRESETS.to_vec())
+    }
+
+    proptest! {
+        #[test]
+        fn never_upscales(fsr in preset(), width: u16, height: u16) {
+            let res = fsr.generate((width, height));
+            prop_assert!(res.0 <= width && res.1 <= height, "{res:?}");
+        }
+
+        #[test]
+        fn no_zero_dimensions(fsr in preset(), width in 1u16.., height in 1u16..) {
+            let res = fsr.generate((width, height));
+            prop_assert!(res.0 > 0 && res.1 > 0, "{res:?}");
+        }
+
+        #[test]
+        fn floors_the_ratio(fsr in preset(), width in 2u16.., height in 2u16..) {
+            prop_assume!(!TABLES.iter().any(|(target, _)| *target == (width, height)));
+            let res = fsr.generate((width, height));
+            prop_assert_eq!(res.0, (f32::from(width) / factor(fsr)).floor() as u16);
+            prop_assert_eq!(res.1, (f32::from(height) / factor(fsr)).floor() as u16);
+        }
+
+        #[test]
+        fn within_a_pixel_of_the_ratio(fsr in preset(), width: u16, height: u16) {
+            let res = fsr.generate((width, height));
+            prop_assert!((f32::from(res.0) - f32::from(width) / factor(fsr)).abs() <= 1.0);
+            prop_assert!((f32::from(res.1) - f32::from(height) / factor(fsr)).abs() <= 1.0);
+        }
+
+        #[test]
+        fn keeps_the_aspect_ratio(fsr in preset(), width in 640u16.., height in 640u16..) {
+            let res = fsr.generate((width, height));
+            let aspect = f64::from(width) / f64::from(height);
+            let error = (f64::from(res.0) / f64::from(res.1) - aspect).abs() / aspect;
+            prop_assert!(error < 0.01, "{res:?} for {width}x{height}, off by {error}");
+        }
+
+        #[test]
+        fn presets_are_ordered(width: u16, height: u16) {
+            let sizes: Vec<_> = PRESETS.iter().map(|fsr| fsr.generate((width, height))).collect();
+            for pair in sizes.windows(2) {
+                prop_assert!(pair[0].0 >= pair[1].0 && pair[0].1 >= pair[1].1, "{sizes:?}");
+            }
         }
     }
 }
@@ -0,0 +1,252 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! GPU classification and probing order
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::sysfs;
+
+/// Kind of GPU, as far as sysfs can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Part of the CPU (Intel graphics, AMD APUs), usually driving a laptop's internal panel
+    Integrated,
+    /// Separate GPU, on a graphics card or a laptop's own PCIe lanes
+    Discrete,
+    /// Paravirtualized or emulated GPU (virtio-gpu, VMware...)
+    Virtual,
+    /// Unknown driver, or an AMD GPU alone in the system
+    Unknown,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integrated => write!(f, "integrated"),
+            Self::Discrete => write!(f, "discrete"),
+            Self::Virtual => write!(f, "virtual"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Whether the card is the primary GPU, ie. the one the firmware booted on
+pub fn is_primary(card: &Path) -> bool {
+    sysfs::is_boot_vga(card)
+}
+
+/// Whether a DRM driver drives a virtual GPU
+///
+/// Their connectors are synthetic: in Crostini, virtio-gpu's modes don't match the window.
+pub fn is_virtual_driver(driver: &str) -> bool {
+    [
+        "virtio_gpu",
+        "vmwgfx",
+        "qxl",
+        "bochs",
+        "bochs-drm",
+        "cirrus",
+        "cirrus-qemu",
+        "vboxvideo",
+    ]
+    .contains(&driver)
+}
+
+/// PCI class of 3D controllers: GPUs without VGA, like hybrid laptops' dGPUs
+const PCI_CLASS_3D: u16 = 0x0302;
+
+/// What sysfs tells about a card
+#[derive(Debug, Default, Clone)]
+struct Facts {
+    driver: Option<String>,
+    pci_class: Option<u16>,
+    /// On the root PCI bus (`0000:00:...`)
+    root_bus: bool,
+    boot_vga: bool,
+    /// Other PCI GPUs in the system
+    others: usize,
+}
+
+impl Facts {
+    fn read(card: &Path) -> Self {
+        let slot = sysfs::pci_slot(card);
+        Self {
+            driver: sysfs::driver(card),
+            pci_class: sysfs::pci_class(card),
+            root_bus: slot
+                .as_ref()
+                .is_some_and(|slot| slot.split(':').nth(1) == Some("00")),
+            boot_vga: sysfs::is_boot_vga(card),
+            others: sysfs::cards()
+                .iter()
+                .filter(|c| {
+                    let other = sysfs::pci_slot(c);
+                    other.is_some() && other != slot
+                })
+                .count(),
+        }
+    }
+}
+
+/// Guess if a card is an integrated, discrete or virtual GPU
+///
+/// NVIDIA is always discrete, and so are 3D controllers. GPUs on the root PCI bus are
+/// integrated. Recent AMD APUs aren't on the root bus, but the firmware boots hybrid systems
+/// on the integrated GPU: next to another GPU, the boot VGA AMD GPU is the integrated one.
+pub fn kind(card: &Path) -> Kind {
+    classify(&Facts::read(card))
+}
+
+fn classify(facts: &Facts) -> Kind {
+    match facts.driver.as_deref() {
+        Some(driver) if is_virtual_driver(driver) => Kind::Virtual,
+        Some("nvidia") | Some("nouveau") => Kind::Discrete,
+        Some(_) if facts.pci_class == Some(PCI_CLASS_3D) => Kind::Discrete,
+        Some("i915") | Some("xe") if facts.root_bus => Kind::Integrated,
+        Some("i915") | Some("xe") => Kind::Discrete,
+        Some("amdgpu") | Some("radeon") => match (facts.root_bus, facts.others, facts.boot_vga) {
+            (true, _, _) => Kind::Integrated,
+            (false, 0, _) => Kind::Unknown,
+            (false, _, true) => Kind::Integrated,
+            (false, _, false) => Kind::Discrete,
+        },
+        _ => Kind::Unknown,
+    }
+}
+
+/// Which GPUs have their displays considered first
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Primary GPU first, then card order (card0, card1, card2...)
+    #[default]
+    Default,
+    /// Discrete GPUs first, eg. for an external display wired to a laptop's dGPU
+    PreferDgpu,
+    /// Integrated GPUs first, eg. to keep a laptop's internal panel first when docked
+    PreferIgpu,
+}
+
+impl TryFrom<&str> for Policy {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_ref() {
+            "default" => Ok(Self::Default),
+            "prefer-dgpu" => Ok(Self::PreferDgpu),
+            "prefer-igpu" => Ok(Self::PreferIgpu),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Policy {
+    /// Sort cards according to the policy, keeping card order among equals
+    pub fn sort(&self, cards: &mut [PathBuf]) {
+        let first = match self {
+            Self::Default => {
+                cards.sort_by_key(|card| !is_primary(card));
+                return;
+            }
+            Self::PreferDgpu => Kind::Discrete,
+            Self::PreferIgpu => Kind::Integrated,
+        };
+        cards.sort_by_cached_key(|card| match kind(card) {
+            k if k == first => 0,
+            Kind::Unknown => 1,
+            _ => 2,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts(driver: &str, pci_class: u16, slot: &str, boot_vga: bool, others: usize) -> Facts {
+        Facts {
+            driver: Some(driver.to_string()),
+            pci_class: Some(pci_class),
+            root_bus: slot.split(':').nth(1) == Some("00"),
+            boot_vga,
+            others,
+        }
+    }
+
+    #[test]
+    fn classify() {
+        let kind = |facts| super::classify(&facts);
+        // Intel + NVIDIA laptop
+        assert_eq!(
+            kind(facts("i915", 0x0300, "0000:00:02.0", true, 1)),
+            Kind::Integrated
+        );
+        assert_eq!(
+            kind(facts("nvidia", 0x0300, "0000:01:00.0", false, 1)),
+            Kind::Discrete
+        );
+        assert_eq!(
+            kind(facts("nouveau", 0x0302, "0000:01:00.0", false, 1)),
+            Kind::Discrete
+        );
+        // Intel Arc
+        assert_eq!(
+            kind(facts("xe", 0x0300, "0000:03:00.0", false, 1)),
+            Kind::Discrete
+        );
+        // AMD APU + AMD dGPU laptop, the dGPU isn't always a 3D controller
+        assert_eq!(
+            kind(facts("amdgpu", 0x0300, "0000:c4:00.0", true, 1)),
+            Kind::Integrated
+        );
+        assert_eq!(
+            kind(facts("amdgpu", 0x0380, "0000:03:00.0", false, 1)),
+            Kind::Discrete
+        );
+        assert_eq!(
+            kind(facts("amdgpu", 0x0302, "0000:03:00.0", false, 1)),
+            Kind::Discrete
+        );
+        // Older APUs are on the root bus
+        assert_eq!(
+            kind(facts("radeon", 0x0300, "0000:00:01.0", true, 0)),
+            Kind::Integrated
+        );
+        // A lone AMD GPU could be either
+        assert_eq!(
+            kind(facts("amdgpu", 0x0300, "0000:03:00.0", true, 0)),
+            Kind::Unknown
+        );
+        assert_eq!(
+            kind(facts("virtio_gpu", 0x0300, "0000:00:02.0", true, 0)),
+            Kind::Virtual
+        );
+        assert_eq!(
+            kind(facts("simpledrm", 0x0300, "0000:00:02.0", true, 0)),
+            Kind::Unknown
+        );
+        assert_eq!(kind(Facts::default()), Kind::Unknown);
+    }
+
+    #[test]
+    fn policy() {
+        assert_eq!(Policy::try_from("Prefer-DGPU"), Ok(Policy::PreferDgpu));
+        assert_eq!(Policy::try_from("prefer-igpu"), Ok(Policy::PreferIgpu));
+        assert_eq!(Policy::try_from("default"), Ok(Policy::Default));
+        assert_eq!(Policy::try_from("dgpu"), Err(()));
+    }
+}
@@ -0,0 +1,516 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Minimal JSON values, parsing and serialization
+//!
+//! Objects keep their keys in insertion order. Serialization is compact, on a single line.
+
+use std::fmt;
+
+/// A JSON value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get the items of an array, none for other values
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Self::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// Get a field of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Get a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64)
+            .map(|n| n as u64)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Self::Null
+    }
+}
+
+/// Encode binary data (eg. an EDID) as a hexadecimal string
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode a string made by [`to_hex`]
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Build an object from `(key, value)` pairs
+pub fn object<const N: usize>(fields: [(&str, Value); N]) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Value {
+            fn from(value: $t) -> Self {
+                Self::Number(value as f64)
+            }
+        })*
+    };
+}
+
+from_integer!(u8, u16, u32, u64, usize, i32, i64);
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Self::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<A: Into<Value>, B: Into<Value>> From<(A, B)> for Value {
+    fn from(value: (A, B)) -> Self {
+        Self::Array(vec![value.0.into(), value.1.into()])
+    }
+}
+
+/// Write a quoted, escaped string
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            // JSON has no NaN nor infinity
+            Self::Number(n) if !n.is_finite() => write!(f, "null"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Parse a JSON document
+pub fn parse(input: &str) -> anyhow::Result<Value> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        position: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.position != parser.input.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+/// Maximum nesting of arrays and objects
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("invalid JSON at byte {}: {message}", self.position)
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> anyhow::Result<()> {
+        if !self.input[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error(&format!("expected {literal}")));
+        }
+        self.position += literal.len();
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> anyhow::Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.whitespace();
+        match self.input.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = vec![];
+                self.whitespace();
+                if self.input.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.input.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = vec![];
+                self.whitespace();
+                if self.input.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    if self.input.get(self.position) != Some(&b'"') {
+                        return Err(self.error("expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    fields.push((key, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.input.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Value::Object(fields));
+                        }
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    /// Skip digits, returning how many there were
+    fn digits(&mut self) -> usize {
+        let start = self.position;
+        while let Some(b'0'..=b'9') = self.input.get(self.position) {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    fn number(&mut self) -> anyhow::Result<Value> {
+        let start = self.position;
+        if self.input.get(self.position) == Some(&b'-') {
+            self.position += 1;
+        }
+        // No leading zeros
+        match self.input.get(self.position) {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.input.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            if self.digits() == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.input.get(self.position) {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.input.get(self.position) {
+                self.position += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        let text = std::str::from_utf8(&self.input[start..self.position])?;
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn hex4(&mut self) -> anyhow::Result<u32> {
+        let digits = self
+            .input
+            .get(self.position..self.position + 4)
+            .ok_or_else(|| self.error("truncated escape"))?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(self.error("invalid escape"));
+        }
+        let value = u32::from_str_radix(std::str::from_utf8(digits)?, 16)?;
+        self.position += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        // Opening quote
+        self.position += 1;
+        let mut value = vec![];
+        loop {
+            let Some(&byte) = self.input.get(self.position) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.input.get(self.position) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Surrogate pair. An unpaired surrogate is replaced, and the
+                            // escape after it decoded on its own
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.input[self.position..].starts_with(b"\\u")
+                            {
+                                let high = self.position;
+                                self.position += 2;
+                                let low = self.hex4()?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                } else {
+                                    self.position = high;
+                                }
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    value.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte if byte < 0x20 => return Err(self.error("control character in string")),
+                byte => value.push(byte),
+            }
+        }
+        String::from_utf8(value).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(json: &str) -> String {
+        parse(json).unwrap().as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""\"\\\/\b\f\n\r\t""#), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(string(r#""éé""#), "éé");
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""\u+123""#).is_err());
+        assert!(parse("\"\n\"").is_err());
+        assert!(parse(r#""abc"#).is_err());
+
+        // Round trip
+        let value = Value::from("tab\t \"quote\" \\ \u{1} é 🎮");
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn surrogates() {
+        assert_eq!(string(r#""\ud83c\udfae""#), "🎮");
+        // Unpaired
+        assert_eq!(string(r#""\ud83c""#), "\u{fffd}");
+        assert_eq!(string(r#""\udfae""#), "\u{fffd}");
+        assert_eq!(string(r#""\ud83cx""#), "\u{fffd}x");
+        // A high surrogate followed by another escape doesn't swallow it
+        assert_eq!(string(r#""\ud83c\u0041""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ud83c\ud83c\udfae""#), "\u{fffd}🎮");
+        assert_eq!(string(r#""\ud83c\ud83c""#), "\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+
+        let value = parse(r#" { "a" : [ 1 , { "b" : null } ] , "c" : {} } "#).unwrap();
+        assert_eq!(value.to_string(), r#"{"a":[1,{"b":null}],"c":{}}"#);
+        assert!(value.get("a").unwrap().items()[1]
+            .get("b")
+            .unwrap()
+            .is_null());
+        assert!(parse("[1,]").is_err());
+        assert!(parse(r#"{"a":1,}"#).is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse("{1:2}").is_err());
+    }
+
+    #[test]
+    fn trailing() {
+        assert!(parse("true \n").is_ok());
+        assert!(parse("true false").is_err());
+        assert!(parse("{}}").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("nul").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        let number = |json| parse(json).unwrap().as_f64().unwrap();
+        assert_eq!(number("0"), 0.0);
+        assert_eq!(number("-0"), 0.0);
+        assert_eq!(number("1920"), 1920.0);
+        assert_eq!(number("-1.5"), -1.5);
+        assert_eq!(number("59.951"), 59.951);
+        assert_eq!(number("1e3"), 1000.0);
+        assert_eq!(number("2.5E-1"), 0.25);
+        assert_eq!(number("1e+2"), 100.0);
+        for invalid in [
+            "01", "-01", "00", "+1", "-", ".5", "1.", "1e", "1e+", "1.e3", "--1",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(parse("144").unwrap().as_u64(), Some(144));
+        assert_eq!(parse("-1").unwrap().as_u64(), None);
+        assert_eq!(parse("1.5").unwrap().as_u64(), None);
+        assert_eq!(Value::from(f64::NAN).to_string(), "null");
+    }
+}
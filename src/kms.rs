@@ -0,0 +1,185 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! KMS modesetting
+//!
+//! Only works when nothing else (a compositor or X server) is the DRM master of the card, eg.
+//! from a VT. The kernel restores the console's mode once the card is closed, so the mode
+//! only lasts as long as the returned [`Modeset`].
+
+use std::path::Path;
+
+use anyhow::Context;
+use drm::buffer::DrmFourcc;
+use drm::control::{
+    atomic, dumbbuffer, framebuffer, property, AtomicCommitFlags, Device as ControlDevice,
+    ResourceHandle,
+};
+use drm::{ClientCapability, Device};
+
+use crate::backend::drm::{get_property, Card};
+use crate::{modes, Mode};
+
+/// `type` property value of primary planes
+const PLANE_TYPE_PRIMARY: u64 = 1;
+
+/// A mode set on a connector, kept until dropped
+pub struct Modeset {
+    card: Card,
+    framebuffer: framebuffer::Handle,
+    buffer: dumbbuffer::DumbBuffer,
+    /// The mode that was set
+    pub mode: Mode,
+}
+
+impl Drop for Modeset {
+    fn drop(&mut self) {
+        let _ = self.card.destroy_framebuffer(self.framebuffer);
+        let _ = self.card.destroy_dumb_buffer(self.buffer);
+    }
+}
+
+/// Find a property of a KMS object by name
+fn property<H: ResourceHandle>(
+    card: &Card,
+    handle: H,
+    name: &str,
+) -> anyhow::Result<property::Handle> {
+    match get_property(card, handle, name)? {
+        Some((property, _)) => Ok(property),
+        None => Err(anyhow::anyhow!("missing KMS property: {name}")),
+    }
+}
+
+/// Set a mode on a connector (eg. `DP-1`) of a card
+///
+/// The mode must be supported by the connector. The screen is black (an empty framebuffer)
+/// while the mode is set.
+pub fn set_mode(card_path: &Path, output: &str, mode: &Mode) -> anyhow::Result<Modeset> {
+    let card =
+        Card::open(card_path).with_context(|| format!("failed to open {}", card_path.display()))?;
+    card.acquire_master_lock()
+        .context("failed to become DRM master (is a compositor or X server running?)")?;
+    card.set_client_capability(ClientCapability::UniversalPlanes, true)
+        .context("universal planes are not supported")?;
+    card.set_client_capability(ClientCapability::Atomic, true)
+        .context("atomic modesetting is not supported")?;
+
+    let resources = card.resource_handles()?;
+    let connector = resources
+        .connectors()
+        .iter()
+        .filter_map(|c| card.get_connector(*c, false).ok())
+        .find(|c| crate::backend::drm::connector_name(c) == output)
+        .ok_or_else(|| anyhow::anyhow!("no such output: {output}"))?;
+    let Some(drm_mode) = connector
+        .modes()
+        .iter()
+        .find(|m| Mode::from(**m) == *mode)
+        .copied()
+    else {
+        return Err(anyhow::anyhow!(
+            "{output} doesn't support {}",
+            modes::describe(mode)
+        ));
+    };
+    let mode = *mode;
+
+    // Keep the current CRTC, or use the first one the connector can drive
+    let mut crtc = connector
+        .current_encoder()
+        .and_then(|e| card.get_encoder(e).ok())
+        .and_then(|e| e.crtc());
+    if crtc.is_none() {
+        crtc = connector
+            .encoders()
+            .iter()
+            .filter_map(|e| card.get_encoder(*e).ok())
+            .flat_map(|e| resources.filter_crtcs(e.possible_crtcs()))
+            .next();
+    }
+    let crtc = crtc.ok_or_else(|| anyhow::anyhow!("no CRTC available for {output}"))?;
+
+    let mut plane = None;
+    for handle in card.plane_handles()? {
+        let info = card.get_plane(handle)?;
+        if !resources
+            .filter_crtcs(info.possible_crtcs())
+            .contains(&crtc)
+        {
+            continue;
+        }
+        if let Some((_, PLANE_TYPE_PRIMARY)) = get_property(&card, handle, "type")? {
+            plane = Some(handle);
+            break;
+        }
+    }
+    let plane = plane.ok_or_else(|| anyhow::anyhow!("no primary plane for {output}"))?;
+
+    let (width, height) = mode.size();
+    let buffer = card
+        .create_dumb_buffer((width.into(), height.into()), DrmFourcc::Xrgb8888, 32)
+        .context("failed to allocate a framebuffer")?;
+    let framebuffer = card.add_framebuffer(&buffer, 24, 32)?;
+    let blob = card.create_property_blob(&drm_mode)?;
+
+    let mut request = atomic::AtomicModeReq::new();
+    let handle = connector.handle();
+    request.add_property(
+        handle,
+        property(&card, handle, "CRTC_ID")?,
+        property::Value::CRTC(Some(crtc)),
+    );
+    request.add_property(crtc, property(&card, crtc, "MODE_ID")?, blob);
+    request.add_property(
+        crtc,
+        property(&card, crtc, "ACTIVE")?,
+        property::Value::Boolean(true),
+    );
+    for (name, value) in [
+        ("FB_ID", property::Value::Framebuffer(Some(framebuffer))),
+        ("CRTC_ID", property::Value::CRTC(Some(crtc))),
+        ("SRC_X", property::Value::UnsignedRange(0)),
+        ("SRC_Y", property::Value::UnsignedRange(0)),
+        // Source coordinates are 16.16 fixed point
+        (
+            "SRC_W",
+            property::Value::UnsignedRange((width as u64) << 16),
+        ),
+        (
+            "SRC_H",
+            property::Value::UnsignedRange((height as u64) << 16),
+        ),
+        ("CRTC_X", property::Value::SignedRange(0)),
+        ("CRTC_Y", property::Value::SignedRange(0)),
+        ("CRTC_W", property::Value::UnsignedRange(width.into())),
+        ("CRTC_H", property::Value::UnsignedRange(height.into())),
+    ] {
+        request.add_property(plane, property(&card, plane, name)?, value);
+    }
+
+    log::info!("Setting {} on {output}", modes::describe(&mode));
+    card.atomic_commit(AtomicCommitFlags::ALLOW_MODESET, request)
+        .with_context(|| format!("failed to set {} on {output}", modes::describe(&mode)))?;
+
+    Ok(Modeset {
+        card,
+        framebuffer,
+        buffer,
+        mode,
+    })
+}
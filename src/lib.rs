@@ -17,49 +17,322 @@
 
 use std::env;
 use std::fs;
-use std::os;
 use std::path;
 
 use anyhow::Context;
-use drm::control::{Device as ControlDevice, Mode};
-use drm::Device;
 
-mod fsr;
+pub mod aspect;
+pub mod backend;
+pub mod bench;
+pub mod cache;
+pub mod command;
+pub mod completions;
+pub mod config;
+pub mod cvt;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+mod dbus;
+#[cfg(target_os = "linux")]
+mod debugfs;
+pub mod deck;
+pub mod doctor;
+pub mod dp;
+pub mod dxvk;
+pub mod edid;
+pub mod environment;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fsr;
+pub mod gpu;
+pub mod json;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub mod kms;
+pub mod logger;
+pub mod lutris;
+pub mod metrics;
+pub mod modes;
+pub mod pi;
+pub mod prime;
+pub mod prober;
+pub mod proton;
+#[cfg(feature = "python")]
+mod python;
+pub mod rpc;
+pub mod steam;
+#[cfg(target_os = "freebsd")]
+mod sysctl;
+mod sysfs;
+pub mod timestamp;
+pub mod vulkan;
+pub mod wine;
 
-// Card handle
-// Really just to get a file descriptor for `drm`
-struct Card(std::fs::File);
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub use backend::drm::{get_card_displays, get_card_modes};
+pub use modes::Mode;
+pub use prober::Prober;
 
-impl os::fd::AsFd for Card {
-    fn as_fd(&self) -> os::fd::BorrowedFd<'_> {
-        self.0.as_fd()
+/// A connected display
+///
+/// DRM displays are listed by card (in [`gpu::Policy`] order), then built-in panels first and
+/// by connector type and number, so that indices don't depend on the connectors' creation
+/// order.
+#[derive(Debug, Clone)]
+pub struct Display {
+    /// Card the display is connected to (eg. `/dev/dri/card0`)
+    pub card: path::PathBuf,
+    /// Connector name (eg. `DP-1`)
+    pub connector: String,
+    /// Current mode, or the native mode if it can't be detected
+    pub mode: Mode,
+    /// Every mode supported by the display, preferred mode first
+    ///
+    /// Interlaced and doublescan modes are left out, unless requested by the probe options.
+    pub modes: Vec<Mode>,
+    /// Physical size in millimeters, if known
+    pub physical_size: Option<(u32, u32)>,
+    /// Whether the display supports variable refresh rate, if known (DRM: read with the EDID)
+    pub vrr_capable: Option<bool>,
+    /// Raw EDID, if available and requested ([`ProbeOptions::edid`])
+    pub edid: Option<Vec<u8>>,
+    /// Properties of the DRM connector
+    pub properties: Properties,
+}
+
+/// Properties of a display's KMS connector, `None` when unknown (eg. other backends)
+///
+/// Driver state not exposed as a property is read from debugfs, when accessible (root).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Properties {
+    /// Whether the driver can send HDR metadata to the display (`HDR_OUTPUT_METADATA`)
+    pub hdr_output_metadata: Option<bool>,
+    /// Maximum bits per component the driver may send (`max bpc`), as set by the compositor
+    ///
+    /// The link may use less, when its bandwidth is too low for the mode at this depth.
+    pub max_bpc: Option<u8>,
+    /// Values `max bpc` accepts, (min, max)
+    pub max_bpc_range: Option<(u8, u8)>,
+    /// Whether the current mode uses Display Stream Compression (amdgpu and i915 debugfs)
+    pub dsc: Option<bool>,
+    /// Trained DisplayPort link (amdgpu debugfs)
+    pub dp_link: Option<dp::Link>,
+    /// How a built-in panel is mounted in the device (`panel orientation`)
+    pub panel_orientation: Option<Orientation>,
+}
+
+/// Orientation of a panel in its device, eg. the portrait panels of handhelds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// The panel's top is the device's top
+    Normal,
+    /// The panel's top is the device's bottom
+    UpsideDown,
+    /// The panel's top is on the left of the device
+    LeftSideUp,
+    /// The panel's top is on the right of the device
+    RightSideUp,
+}
+
+impl Orientation {
+    const ALL: [Self; 4] = [
+        Self::Normal,
+        Self::UpsideDown,
+        Self::LeftSideUp,
+        Self::RightSideUp,
+    ];
+
+    /// Get the orientation from the value of the `panel orientation` property
+    pub fn from_property(value: u64) -> Option<Self> {
+        Self::ALL.get(usize::try_from(value).ok()?).copied()
+    }
+
+    /// Name of the orientation, as the kernel's (eg. `Right Side Up`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::UpsideDown => "Upside Down",
+            Self::LeftSideUp => "Left Side Up",
+            Self::RightSideUp => "Right Side Up",
+        }
+    }
+
+    /// Get the orientation from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|o| o.as_str() == name)
+    }
+
+    /// Whether the panel is mounted sideways, its width being the device's height
+    pub fn is_sideways(&self) -> bool {
+        matches!(self, Self::LeftSideUp | Self::RightSideUp)
     }
 }
 
-impl Card {
-    pub fn open<P: AsRef<path::Path>>(path: P) -> Self {
-        let mut options = std::fs::OpenOptions::new();
-        options.read(true);
-        options.write(true);
-        Card(options.open(path).unwrap())
+impl Display {
+    /// Rotate the modes of a sideways panel to the device's orientation, eg. 1280x800 for the
+    /// Steam Deck's 800x1280 panel
+    pub(crate) fn rotate_to_device(&mut self) {
+        if !self
+            .properties
+            .panel_orientation
+            .is_some_and(|o| o.is_sideways())
+        {
+            return;
+        }
+        self.mode = self.mode.rotated();
+        for mode in self.modes.iter_mut() {
+            *mode = mode.rotated();
+        }
+        self.physical_size = self.physical_size.map(|(w, h)| (h, w));
+    }
+
+    /// Whether the display is built into the device (laptop or handheld panel)
+    pub fn is_internal(&self) -> bool {
+        ["eDP", "LVDS", "DSI", "Built-in"]
+            .iter()
+            .any(|i| self.connector.starts_with(&format!("{i}-")))
+    }
+
+    /// Whether the display supports HDR, unknown without EDID
+    pub fn hdr(&self) -> Option<bool> {
+        self.edid.as_deref().map(edid::has_hdr)
+    }
+
+    /// HDR capabilities advertised by the EDID (transfer functions, luminance)
+    pub fn hdr_metadata(&self) -> Option<edid::HdrMetadata> {
+        self.edid.as_deref().and_then(edid::hdr_metadata)
+    }
+
+    /// Desired content max luminance in cd/m², from the EDID
+    pub fn max_luminance(&self) -> Option<f32> {
+        self.hdr_metadata().and_then(|m| m.max_luminance)
+    }
+
+    /// Extended colorimetries advertised by the EDID, empty for sRGB-only displays
+    pub fn colorimetry(&self) -> Option<edid::Colorimetry> {
+        self.edid
+            .as_deref()
+            .map(|edid| edid::colorimetry(edid).unwrap_or_default())
+    }
+
+    /// Native color primaries of the display, from the EDID
+    pub fn chromaticity(&self) -> Option<edid::Chromaticity> {
+        self.edid.as_deref().and_then(edid::chromaticity)
+    }
+
+    /// Whether the display has a wide color gamut, unknown without EDID
+    ///
+    /// Decided by the area of the native primaries ([`edid::Chromaticity::is_wide_gamut`]), or
+    /// by the advertised colorimetries (BT.2020, DCI-P3) when the EDID has no primaries.
+    pub fn wide_gamut(&self) -> Option<bool> {
+        match self.chromaticity() {
+            Some(chromaticity) => Some(chromaticity.is_wide_gamut()),
+            None => self.colorimetry().map(|c| c.is_wide_gamut()),
+        }
+    }
+
+    /// Part of the sRGB gamut covered by the display, from 0 to 1, from the EDID primaries
+    pub fn srgb_coverage(&self) -> Option<f32> {
+        self.chromaticity().map(|c| c.srgb_coverage())
+    }
+
+    /// Whether the display supports Auto Low Latency Mode (automatic game mode of TVs), unknown
+    /// without EDID
+    pub fn allm(&self) -> Option<bool> {
+        self.edid.as_deref().map(edid::has_allm)
+    }
+
+    /// Maximum bits per color component advertised by the EDID
+    pub fn bits_per_component(&self) -> Option<u8> {
+        self.edid.as_deref().and_then(edid::bits_per_component)
+    }
+
+    /// Variable refresh rate range in Hz, from the EDID range limits
+    ///
+    /// `None` unless the connector reports VRR support ([`Display::vrr_capable`]): displays
+    /// without VRR also advertise the rates they accept.
+    pub fn vrr_range(&self) -> Option<(u16, u16)> {
+        if self.vrr_capable != Some(true) {
+            return None;
+        }
+        self.edid.as_deref().and_then(edid::refresh_range)
+    }
+
+    /// Position in a tiled display, if the display is a tile
+    pub fn tile(&self) -> Option<edid::Tile> {
+        self.edid.as_deref().and_then(edid::tile)
     }
 }
 
-// Implement `drm` types
-impl Device for Card {}
-impl ControlDevice for Card {}
+/// Display detection settings
+#[derive(Debug, Default, Clone)]
+pub struct ProbeOptions {
+    /// Card to probe (eg. card0), every card if `None`
+    pub card: Option<String>,
+    /// Order in which the GPUs are probed
+    pub gpu_policy: gpu::Policy,
+    /// Display index, `RRES_DISPLAY` if `None`
+    pub display: Option<usize>,
+    /// Forced resolution, `RRES_FORCE_RES` if `None`
+    pub force_res: Option<(u16, u16)>,
+    /// Resolution used when no display can be detected, `RRES_FALLBACK_RES` if `None`
+    pub fallback_res: Option<(u16, u16)>,
+    /// Mode to select among the display's supported modes, the current mode if `None`
+    pub mode: Option<modes::ModeSpec>,
+    /// Use the display's preferred (native) mode instead of the current one
+    pub native: bool,
+    /// Keep interlaced and doublescan modes in [`Display::modes`]
+    pub interlaced: bool,
+    /// Fail when the forced resolution isn't supported by the display, instead of warning
+    pub strict: bool,
+    /// Use the highest refresh rate available at the resolution of `mode` (or of the current
+    /// mode)
+    pub best_refresh: bool,
+    /// Read virtual GPUs (virtio-gpu, eg. in Crostini) through DRM, instead of preferring the
+    /// compositor's outputs
+    pub prefer_drm: bool,
+    /// Ask the compositor's display service over D-Bus instead of reading `/dev/dri`
+    pub via_dbus: bool,
+    /// Ask the ScreenCast portal (the user picks the monitors), for sandboxes
+    pub via_portal: bool,
+    /// Reuse the displays found by a recent probe, see [`cache`]
+    pub cache: bool,
+    /// Stop looking for displays once this many are found, when only the first ones are
+    /// needed. Every display is found if `None`.
+    pub limit: Option<usize>,
+    /// Read the displays' EDID ([`Display::edid`]), needed by [`Display::hdr`],
+    /// [`Display::vrr_range`] and [`Display::tile`], and their connector's
+    /// [`Display::vrr_capable`] and [`Display::properties`]. Left out by default, saving
+    /// property reads on every connector.
+    pub edid: bool,
+    /// Use the largest region of this aspect ratio within the selected mode (letterbox or
+    /// pillarbox), eg. 2560x1440 for 16:9 on a 3440x1440 display
+    pub force_aspect: Option<aspect::Ratio>,
+    /// Use the closest standard resolution (1080p, 1440p...) instead of the selected mode's,
+    /// eg. 1920x1200 for a 3072x1280 panel, see [`aspect::snap`]
+    pub snap: bool,
+    /// Scale the selected mode down to fit these bounds, keeping its aspect ratio (eg.
+    /// 1920x1080 for a 4K TV driven by a weak GPU)
+    pub max_res: Option<(u16, u16)>,
+    /// Report the modes of sideways panels (eg. 800x1280 for the Steam Deck) as the panel
+    /// scans them out, instead of rotated to the device's orientation
+    pub raw_orientation: bool,
+}
 
 /// Build FSR arguments for gamescope
+///
+/// A percentage (eg. `75%`) renders at that scale of the resolution, upscaled with
+/// gamescope's default (linear) filter instead of FSR.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
 pub fn gamescope(res: (u16, u16), fsr_mode: &str) -> anyhow::Result<Vec<String>> {
     let gamescope_bin: String = env::var("RRES_GAMESCOPE").unwrap_or("gamescope".to_string());
     let mut gamescope_runner: Vec<String> = vec![gamescope_bin];
 
-    let args = if !fsr_mode.is_empty() && fsr_mode.to_lowercase() != "native" {
-        let Ok(fsr) = fsr::Fsr::try_from(fsr_mode) else {
-            return Err(anyhow::anyhow!("invalid FSR mode: {}", fsr_mode));
-        };
-
-        let fsr_res = fsr.generate(res);
+    let args = if render_scale(fsr_mode).is_some() {
+        let scaled_res = render_res(res, fsr_mode)?;
+        format!(
+            "-W {} -H {} -w {} -h {}",
+            res.0, res.1, scaled_res.0, scaled_res.1
+        )
+    } else if !fsr_mode.is_empty() && fsr_mode.to_lowercase() != "native" {
+        let fsr_res = render_res(res, fsr_mode)?;
         format!(
             "-W {} -H {} -U -w {} -h {}",
             res.0, res.1, fsr_res.0, fsr_res.1
@@ -69,14 +342,54 @@ pub fn gamescope(res: (u16, u16), fsr_mode: &str) -> anyhow::Result<Vec<String>>
     };
 
     gamescope_runner.extend(args.split(' ').map(|s| s.to_owned()));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(command = ?gamescope_runner, "gamescope command built");
 
     Ok(gamescope_runner)
 }
 
-/// Get all the displays from the system or selected card
-pub fn get_displays(card: Option<String>) -> anyhow::Result<Vec<Mode>> {
-    // Store found displays
-    let mut displays: Vec<Mode> = vec![];
+/// Get the render scale of a gamescope mode given as a percentage (eg. `75%`)
+pub(crate) fn render_scale(mode: &str) -> Option<anyhow::Result<f64>> {
+    let percent = mode.strip_suffix('%')?;
+    Some(match percent.trim().parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent / 100.0),
+        _ => Err(anyhow::anyhow!(
+            "invalid render scale: {mode} (from 1% to 100%)"
+        )),
+    })
+}
+
+/// Get the render resolution for a gamescope mode (native, a FSR preset or a percentage)
+pub fn render_res(res: (u16, u16), fsr_mode: &str) -> anyhow::Result<(u16, u16)> {
+    if fsr_mode.is_empty() || fsr_mode.to_lowercase() == "native" {
+        return Ok(res);
+    }
+    if let Some(scale) = render_scale(fsr_mode) {
+        let scale = scale?;
+        // Tiny targets still get a pixel
+        let scaled = |size: u16| ((f64::from(size) * scale).round() as u16).max(size.min(1));
+        return Ok((scaled(res.0), scaled(res.1)));
+    }
+
+    let Ok(fsr) = fsr::Fsr::try_from(fsr_mode) else {
+        return Err(anyhow::anyhow!("invalid FSR mode: {}", fsr_mode));
+    };
+
+    Ok(fsr.generate(res))
+}
+
+/// Get the logical resolution of a display scaled by the compositor, eg. 2560x1440 for
+/// 3840x2160 at 150%
+pub fn logical_res(res: (u16, u16), scale: f64) -> (u16, u16) {
+    if scale <= 0.0 {
+        return res;
+    }
+    let scaled = |size: u16| (f64::from(size) / scale).round() as u16;
+    (scaled(res.0), scaled(res.1))
+}
+
+/// Get the card paths to probe: the selected card, or every card on the system
+pub fn get_cards(card: Option<String>) -> anyhow::Result<Vec<path::PathBuf>> {
     // Store the checked cards
     let mut cards: Vec<path::PathBuf> = vec![];
 
@@ -90,120 +403,317 @@ pub fn get_displays(card: Option<String>) -> anyhow::Result<Vec<Mode>> {
         cards.push(file);
     } else {
         // Open every card on the system
-        for entry in fs::read_dir("/dev/dri/")? {
+        let mut render_nodes: Vec<path::PathBuf> = vec![];
+        for entry in fs::read_dir("/dev/dri/").context("failed to read /dev/dri/")? {
             let file = entry?;
             if let Some(name) = file.file_name().to_str() {
                 if name.starts_with("card") {
                     cards.push(file.path());
+                } else if name.starts_with("renderD") {
+                    render_nodes.push(file.path());
                 }
             }
         }
+
+        // Rootless containers often only get the render nodes, which can't do modesetting.
+        // Keep the matching cards, their connectors can still be read from sysfs.
+        if cfg!(target_os = "linux") && cards.is_empty() && !render_nodes.is_empty() {
+            log::warn!("only render nodes are available in /dev/dri/ (container?),");
+            log::warn!("display detection needs the primary nodes (cardX), reading sysfs instead");
+            render_nodes.sort();
+            cards.extend(
+                render_nodes
+                    .iter()
+                    .filter_map(|r| sysfs::render_node_card(r)),
+            );
+        }
     }
 
     // Sort cards (card0, card1, card2...)
     cards.sort();
 
-    // Read card list
-    for file in cards {
-        let gpu = Card::open(file);
-        let info = gpu.get_driver()?;
-        log::debug!("Found GPU: {}", info.name().to_string_lossy());
-        // Find displays
-        match get_card_modes(&gpu) {
-            Ok(modes) => displays.extend_from_slice(&modes),
-            Err(e) => log::error!("failed to read modes: {e}"),
-        }
+    Ok(cards)
+}
+
+/// Get all the displays from the system or selected card
+pub fn get_displays(card: Option<String>) -> anyhow::Result<Vec<Mode>> {
+    get_displays_opts(&ProbeOptions {
+        card,
+        ..Default::default()
+    })
+}
+
+/// Get all the displays, following the given options
+pub fn get_displays_opts(options: &ProbeOptions) -> anyhow::Result<Vec<Mode>> {
+    Ok(probe(options)?.into_iter().map(|d| d.mode).collect())
+}
+
+/// Detect every connected display, following the given options
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, err, fields(card = ?options.card, cache = options.cache))
+)]
+pub fn probe(options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+    let start = std::time::Instant::now();
+    let result = probe_displays(options);
+    metrics::record(start.elapsed(), &result);
+    #[cfg(feature = "tracing")]
+    if let Ok(displays) = &result {
+        tracing::debug!(
+            displays = displays.len(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "probe finished"
+        );
     }
+    result
+}
+
+fn probe_displays(options: &ProbeOptions) -> anyhow::Result<Vec<Display>> {
+    // Replayed displays are neither read from nor written to the cache
+    let forced = backend::from_env()?;
+    let cache = options.cache && forced.is_none();
 
+    let cached = if cache { cache::load(options) } else { None };
+    let mut displays = match cached {
+        Some(displays) => {
+            log::debug!("Using the displays of the cache");
+            #[cfg(feature = "tracing")]
+            tracing::debug!(displays = displays.len(), "displays read from the cache");
+            displays
+        }
+        None => {
+            let backend = if let Some(backend) = forced {
+                backend
+            } else if options.via_portal {
+                backend::portal()?
+            } else if options.via_dbus {
+                backend::dbus()?
+            } else {
+                backend::platform()
+            };
+            log::debug!("Probing displays with the {} backend", backend.name());
+            #[cfg(feature = "tracing")]
+            tracing::debug!(backend = backend.name(), "backend selected");
+            match backend.probe(options) {
+                Ok(displays) if !displays.is_empty() => {
+                    if cache {
+                        cache::store(options, &displays);
+                    }
+                    displays
+                }
+                result if backend::should_fall_back(options, &result) => {
+                    backend::fallback(options, result)?
+                }
+                result => result?,
+            }
+        }
+    };
+
+    finish(options, &mut displays);
     Ok(displays)
 }
 
+/// Apply the options to the displays found by a backend
+fn finish(options: &ProbeOptions, displays: &mut [Display]) {
+    if !options.interlaced {
+        for display in displays.iter_mut() {
+            display.modes.retain(modes::is_progressive);
+        }
+    }
+
+    // Handhelds' portrait panels are used in landscape
+    if !options.raw_orientation {
+        for display in displays.iter_mut() {
+            display.rotate_to_device();
+        }
+    }
+
+    // Docked Steam Decks should use the external display
+    if deck::model().is_some() && deck::is_docked(displays) {
+        log::info!("Steam Deck is docked, preferring the external display");
+        deck::prefer_external(displays);
+    }
+}
+
+/// Get the selected display (the first one by default)
+pub fn get_display_opts(options: &ProbeOptions) -> anyhow::Result<Display> {
+    let selection: usize = match options.display {
+        Some(display) => display,
+        None => env::var("RRES_DISPLAY")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("Failed to parse RRES_DISPLAY")?,
+    };
+
+    // Only the displays up to the selected one are needed, unless a docked Steam Deck
+    // reorders them
+    let mut displays = if options.limit.is_none() && deck::model().is_none() {
+        probe(&ProbeOptions {
+            limit: Some(selection + 1),
+            ..options.clone()
+        })?
+    } else {
+        probe(options)?
+    };
+
+    if selection >= displays.len() {
+        return Err(anyhow::anyhow!("invalid display: {}", selection));
+    }
+
+    Ok(displays.swap_remove(selection))
+}
+
 /// Get the resolution from first display
 pub fn get_res() -> anyhow::Result<(u16, u16)> {
     get_res_card(None)
 }
 
+/// Get the resolution of the primary GPU's first display, as fast as possible
+///
+/// Only the primary card's connectors are read: no cache, EDID, other GPUs nor fallbacks.
+/// `None` when that isn't enough (or `RRES_FORCE_RES`, `RRES_DISPLAY` or `RRES_BACKEND` is
+/// set), [`get_res`] knows better.
+pub fn get_res_fast() -> Option<(u16, u16)> {
+    if ["RRES_FORCE_RES", "RRES_DISPLAY", "RRES_BACKEND"]
+        .iter()
+        .any(|var| env::var_os(var).is_some())
+    {
+        return None;
+    }
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    return backend::drm::primary_mode().map(|m| m.size());
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+    return None;
+}
+
 /// Get the resolution from the first display of the selected card
 pub fn get_res_card(card: Option<String>) -> anyhow::Result<(u16, u16)> {
-    let res;
+    get_res_opts(&ProbeOptions {
+        card,
+        ..Default::default()
+    })
+}
 
-    if let Ok(forced) = env::var("RRES_FORCE_RES") {
-        if let Some((x, y)) = forced.split_once('x') {
-            res = (x.parse()?, y.parse()?);
-        } else {
-            return Err(anyhow::anyhow!("failed to parse RRES_FORCE_RES"));
-        }
-    } else {
-        let displays = get_displays(card)?;
+/// Get the resolution from the first display, following the given options
+pub fn get_res_opts(options: &ProbeOptions) -> anyhow::Result<(u16, u16)> {
+    Ok(get_mode_opts(options)?.size())
+}
 
-        let selection: usize = env::var("RRES_DISPLAY")
-            .unwrap_or_else(|_| "0".to_string())
-            .parse()
-            .context("Failed to parse RRES_DISPLAY")?;
+/// Get the current mode of the first display, following the given options
+///
+/// A forced resolution is checked against the display's modes when possible, and gets the
+/// refresh rate and timings of the display's mode of that size (the current one first). Without
+/// one, or with `RRES_FORCE_RES` (set to skip the detection) unless `options.strict` is set, it
+/// has neither. A requested mode (`options.mode`) must be supported by the display.
+pub fn get_mode_opts(options: &ProbeOptions) -> anyhow::Result<Mode> {
+    let mode;
 
-        if selection > displays.len() - 1 {
-            return Err(anyhow::anyhow!("invalid display: {}", selection));
-        }
+    let (forced, from_env) = match options.force_res {
+        Some(forced) => (Some(forced), false),
+        None => match env::var("RRES_FORCE_RES") {
+            Ok(forced) => (
+                Some(config::parse_res(&forced).context("failed to parse RRES_FORCE_RES")?),
+                true,
+            ),
+            Err(_) => (None, false),
+        },
+    };
 
-        res = displays[selection].size();
+    if let Some(forced) = forced {
+        let matching = match !from_env || options.strict {
+            true => check_forced_res(options, forced)?,
+            false => None,
+        };
+        mode = matching.unwrap_or(Mode::from_size(forced));
+    } else if options.best_refresh {
+        let display = get_display_opts(options)?;
+        let size = options.mode.map(|m| m.size).unwrap_or(display.mode.size());
+        let Some(best) = modes::best_refresh(&display.modes, size) else {
+            return Err(anyhow::anyhow!(
+                "{} doesn't support {}x{}",
+                display.connector,
+                size.0,
+                size.1
+            ));
+        };
+        log::info!("Highest refresh rate: {}", modes::describe(best));
+        mode = *best;
+    } else if let Some(spec) = &options.mode {
+        let display = get_display_opts(options)?;
+        let Some(selected) = modes::closest(&display.modes, spec) else {
+            return Err(anyhow::anyhow!(
+                "{} doesn't support {spec}",
+                display.connector
+            ));
+        };
+        log::info!("Selected mode: {}", modes::describe(selected));
+        mode = *selected;
+    } else if options.native {
+        let display = get_display_opts(options)?;
+        mode = *modes::preferred(&display.modes).unwrap_or(&display.mode);
+        log::info!("Native mode: {}", modes::describe(&mode));
+    } else {
+        mode = get_display_opts(options)?.mode;
     }
 
-    Ok(res)
-}
-
-/// Get all the connected display's modes from a libdrm card.
-pub fn get_card_modes<G: ControlDevice>(gpu: &G) -> anyhow::Result<Vec<Mode>> {
-    let mut modes: Vec<Mode> = vec![];
-
-    let resources = gpu
-        .resource_handles()
-        .context("failed to get resource handles")?;
-    let connectors = resources.connectors();
-    for handle in connectors {
-        let connector = gpu
-            .get_connector(*handle, false)
-            .context("failed to get connector handle")?;
-        if connector.state() == drm::control::connector::State::Connected {
-            // Connected, get mode
-            modes.push(get_connector_mode(gpu, &connector)?);
-        }
+    let mut size = mode.size();
+    if let Some(ratio) = options.force_aspect {
+        size = aspect::fit(size, ratio);
+        log::info!("Forced {ratio}: {}x{}", size.0, size.1);
+    }
+    if options.snap {
+        size = aspect::snap(size);
+        log::info!("Closest standard resolution: {}x{}", size.0, size.1);
+    }
+    if let Some(max) = options.max_res {
+        size = aspect::clamp(size, max);
+        log::info!("Within {}x{}: {}x{}", max.0, max.1, size.0, size.1);
     }
-    Ok(modes)
+    if size != mode.size() {
+        // The refresh rate is kept, but not the timings of the full mode
+        return Ok(Mode {
+            vrefresh: modes::refresh(&mode).round() as u32,
+            ..Mode::from_size(size)
+        });
+    }
+
+    Ok(mode)
 }
 
-/// Get current display mode from connector
+/// Check that the display supports a forced resolution, returning its mode of that size
 ///
-/// Note: nVidia GPUs don't share the current encoder+crtc, so this function will report the
-/// native display's resolution instead of the current resolution.
-fn get_connector_mode<G: ControlDevice>(
-    gpu: &G,
-    connector: &drm::control::connector::Info,
-) -> anyhow::Result<Mode> {
-    if connector.state() != drm::control::connector::State::Connected {
-        return Err(anyhow::anyhow!("Connector is disconnected"));
-    }
-    if let Some(encoder_handle) = connector.current_encoder() {
-        // Get the encoder then crtc
-        let encoder = gpu.get_encoder(encoder_handle)?;
-        if let Some(crtc_handle) = encoder.crtc() {
-            let crtc = gpu.get_crtc(crtc_handle).context("failed to get crtc")?;
-            // Get current mode, and store it
-            if let Some(current_mode) = crtc.mode() {
-                log::debug!(
-                    "Found display: {:?}, {}x{}",
-                    connector.interface(),
-                    current_mode.size().0,
-                    current_mode.size().1
-                );
-                return Ok(current_mode);
-            }
+/// Unsupported resolutions are only a warning, unless `options.strict` is set. Forced
+/// resolutions are often used when the display can't be read, which isn't an error.
+fn check_forced_res(options: &ProbeOptions, forced: (u16, u16)) -> anyhow::Result<Option<Mode>> {
+    let display = match get_display_opts(options) {
+        Ok(display) => display,
+        Err(e) => {
+            log::info!("Can't check the forced resolution against the display: {e}");
+            return Ok(None);
         }
+    };
+    if display.mode.size() == forced {
+        return Ok(Some(display.mode));
     }
-    // nVidia GPUs don't expose the encoder (and thus neither the crtc)
-    log::warn!(
-        "Could not detect current mode for display {:?},",
-        connector.interface()
+    if let Some(mode) = modes::best_refresh(&display.modes, forced) {
+        return Ok(Some(*mode));
+    }
+
+    let closest: Vec<String> = modes::closest_sizes(&display.modes, forced, 3)
+        .iter()
+        .map(|s| format!("{}x{}", s.0, s.1))
+        .collect();
+    let message = format!(
+        "{}x{} isn't supported by {}, closest modes: {}",
+        forced.0,
+        forced.1,
+        display.connector,
+        closest.join(", ")
     );
-    log::warn!("reading native resolution");
-    return Ok(connector.modes()[0]);
+    if options.strict {
+        return Err(anyhow::anyhow!(message));
+    }
+    log::warn!("{message}");
+
+    Ok(None)
 }
@@ -0,0 +1,319 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! rres' logger: stderr, and a log file when set
+//!
+//! Steam and most launchers swallow stderr, so the log can be copied to a file
+//! (`--log-file`, `RRES_LOG_FILE`), each record with a timestamp. With the `journald`
+//! feature, records go to the systemd journal instead of stderr when it is connected to it.
+//!
+//! With [`Format::Json`] (`--log-format json`), each record is a JSON object on its own line,
+//! with its `card` and `connector` when it's about one.
+//!
+//! Levels are colored on a terminal, unless `NO_COLOR` is set (see [`Color`]).
+
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use anyhow::Context;
+
+use crate::json::{self, Value};
+
+#[cfg(all(feature = "journald", target_os = "linux"))]
+mod journal;
+
+/// Log file, once opened
+static FILE: Mutex<Option<fs::File>> = Mutex::new(None);
+/// Whether records are written as JSON
+static JSON: AtomicBool = AtomicBool::new(false);
+/// Whether the levels are colored on stderr
+static COLORS: AtomicBool = AtomicBool::new(false);
+
+/// When to color the levels (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// On a terminal, unless `NO_COLOR` is set (<https://no-color.org>)
+    Auto,
+    Always,
+    Never,
+}
+
+impl TryFrom<&str> for Color {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow::anyhow!(
+                "invalid color mode: {value} (auto, always or never)"
+            )),
+        }
+    }
+}
+
+impl Color {
+    /// Whether stderr gets colors
+    fn enabled(self) -> bool {
+        match self {
+            Self::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stderr().is_terminal()
+            }
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Format of the records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `LEVEL [target] message`, timestamped in the log file
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl TryFrom<&str> for Format {
+    type Error = anyhow::Error;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow::anyhow!(
+                "invalid log format: {value} (text or json)"
+            )),
+        }
+    }
+}
+
+/// Collects the key-values of a record (eg. `card`)
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Render a record as text, `LEVEL [target] message`
+fn text(record: &log::Record, colors: bool) -> String {
+    let level = format!("{:<5}", record.level());
+    let level = match record.level() {
+        _ if !colors => level,
+        log::Level::Error => format!("\x1b[31m{level}\x1b[0m"),
+        log::Level::Warn => format!("\x1b[33m{level}\x1b[0m"),
+        log::Level::Info => format!("\x1b[36m{level}\x1b[0m"),
+        log::Level::Debug => format!("\x1b[35m{level}\x1b[0m"),
+        log::Level::Trace => level,
+    };
+    format!("{level} [{}] {}", record.target(), record.args())
+}
+
+/// Render a record as a JSON object
+fn json(record: &log::Record) -> String {
+    let mut fields = Fields(vec![]);
+    let _ = record.key_values().visit(&mut fields);
+    let mut object = json::object([
+        ("time", crate::timestamp::now().into()),
+        ("level", record.level().as_str().into()),
+        ("target", record.target().into()),
+        ("message", record.args().to_string().into()),
+    ]);
+    if let Value::Object(object) = &mut object {
+        object.extend(fields.0.into_iter().map(|(k, v)| (k, v.into())));
+    }
+    object.to_string()
+}
+
+struct Logger {
+    #[cfg(all(feature = "journald", target_os = "linux"))]
+    journal: Option<journal::Journal>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        #[cfg(all(feature = "journald", target_os = "linux"))]
+        let logged = self.journal.as_ref().is_some_and(|j| j.send(record));
+        #[cfg(not(all(feature = "journald", target_os = "linux")))]
+        let logged = false;
+        let json = JSON.load(Ordering::Relaxed).then(|| json(record));
+        if !logged {
+            match &json {
+                Some(json) => eprintln!("{json}"),
+                None => eprintln!("{}", text(record, COLORS.load(Ordering::Relaxed))),
+            }
+        }
+        if let Ok(mut file) = FILE.lock() {
+            if let Some(file) = file.as_mut() {
+                if let Some(json) = &json {
+                    let _ = writeln!(file, "{json}");
+                    return;
+                }
+                let _ = writeln!(file, "{} {}", crate::timestamp::now(), text(record, false));
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = FILE.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Install the logger, copying the log to `RRES_LOG_FILE` if set
+///
+/// The level can be changed afterwards with [`set_level`], eg. once the options are parsed.
+pub fn init(level: log::LevelFilter) -> anyhow::Result<()> {
+    if let Some(path) = std::env::var_os("RRES_LOG_FILE") {
+        set_file(&path.to_string_lossy())?;
+    }
+    set_color(Color::Auto);
+    log::set_boxed_logger(Box::new(Logger {
+        #[cfg(all(feature = "journald", target_os = "linux"))]
+        journal: journal::Journal::connect(),
+    }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Set when the levels are colored on stderr
+pub fn set_color(color: Color) {
+    COLORS.store(color.enabled(), Ordering::Relaxed);
+}
+
+/// Set the format of the records, on stderr and in the log file
+pub fn set_format(format: Format) {
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+/// Set the level of the records logged
+pub fn set_level(level: log::LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Increase a level by one (`-v`)
+pub fn louder(level: log::LevelFilter) -> log::LevelFilter {
+    use log::LevelFilter::*;
+    match level {
+        Off => Error,
+        Error => Warn,
+        Warn => Info,
+        Info => Debug,
+        Debug | Trace => Trace,
+    }
+}
+
+/// Decrease a level by one (`-q`)
+pub fn quieter(level: log::LevelFilter) -> log::LevelFilter {
+    use log::LevelFilter::*;
+    match level {
+        Off | Error => Off,
+        Warn => Error,
+        Info => Warn,
+        Debug => Info,
+        Trace => Debug,
+    }
+}
+
+/// Copy the log to a file (appended), replacing the previous one
+pub fn set_file(path: &str) -> anyhow::Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {path}"))?;
+    *FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter::*;
+
+    #[test]
+    fn json_records() {
+        let record = log::Record::builder()
+            .args(format_args!("Found GPU: amdgpu"))
+            .level(log::Level::Debug)
+            .target("rres::backend::drm")
+            .key_values(&[("card", "/dev/dri/card0")])
+            .build();
+        let object = json::parse(&json(&record)).unwrap();
+        assert_eq!(object.get("level").unwrap().as_str(), Some("DEBUG"));
+        assert_eq!(
+            object.get("message").unwrap().as_str(),
+            Some("Found GPU: amdgpu")
+        );
+        assert_eq!(object.get("card").unwrap().as_str(), Some("/dev/dri/card0"));
+        assert!(object.get("connector").is_none());
+
+        assert_eq!(Format::try_from("json").unwrap(), Format::Json);
+        assert!(Format::try_from("xml").is_err());
+    }
+
+    #[test]
+    fn text_records() {
+        let record = log::Record::builder()
+            .args(format_args!("reading native resolution"))
+            .level(log::Level::Warn)
+            .target("rres")
+            .build();
+        assert_eq!(
+            text(&record, false),
+            "WARN  [rres] reading native resolution"
+        );
+        assert_eq!(
+            text(&record, true),
+            "\x1b[33mWARN \x1b[0m [rres] reading native resolution"
+        );
+
+        assert_eq!(Color::try_from("never").unwrap(), Color::Never);
+        assert!(Color::try_from("sometimes").is_err());
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(louder(Warn), Info);
+        assert_eq!(louder(louder(Warn)), Debug);
+        assert_eq!(louder(Trace), Trace);
+        assert_eq!(louder(Off), Error);
+        assert_eq!(quieter(Warn), Error);
+        assert_eq!(quieter(Error), Off);
+        assert_eq!(quieter(Off), Off);
+        assert_eq!(quieter(louder(Info)), Info);
+    }
+}
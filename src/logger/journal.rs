@@ -0,0 +1,91 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! systemd journal logging, through its native protocol
+//!
+//! Used instead of stderr when it is connected to the journal (`JOURNAL_STREAM`), eg. in
+//! systemd units and gamescope-session, so that records keep their priority. See
+//! <https://systemd.io/JOURNAL_NATIVE_PROTOCOL/>.
+
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+
+/// Socket of the native protocol
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// A connection to the journal
+pub struct Journal(UnixDatagram);
+
+impl Journal {
+    /// Connect to the journal, if stderr is connected to it
+    pub fn connect() -> Option<Self> {
+        if !stderr_is_journal() {
+            return None;
+        }
+        let socket = UnixDatagram::unbound().ok()?;
+        socket.connect(SOCKET).ok()?;
+        Some(Self(socket))
+    }
+
+    /// Send a record, `false` if the journal didn't take it
+    pub fn send(&self, record: &log::Record) -> bool {
+        let mut entry = vec![];
+        field(&mut entry, "PRIORITY", priority(record.level()).as_bytes());
+        field(&mut entry, "SYSLOG_IDENTIFIER", b"rres");
+        field(&mut entry, "TARGET", record.target().as_bytes());
+        field(&mut entry, "MESSAGE", record.args().to_string().as_bytes());
+        self.0.send(&entry).is_ok()
+    }
+}
+
+/// Whether stderr is the stream systemd connected to the journal (`JOURNAL_STREAM=dev:inode`)
+fn stderr_is_journal() -> bool {
+    let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, inode)) = stream.split_once(':') else {
+        return false;
+    };
+    match std::fs::metadata("/proc/self/fd/2") {
+        Ok(stderr) => dev.parse() == Ok(stderr.dev()) && inode.parse() == Ok(stderr.ino()),
+        Err(_) => false,
+    }
+}
+
+/// Syslog priority of a log level
+fn priority(level: log::Level) -> &'static str {
+    match level {
+        log::Level::Error => "3",
+        log::Level::Warn => "4",
+        log::Level::Info => "6",
+        log::Level::Debug | log::Level::Trace => "7",
+    }
+}
+
+/// Append a field to an entry, in the binary form if the value has newlines
+fn field(entry: &mut Vec<u8>, name: &str, value: &[u8]) {
+    let _ = entry.write_all(name.as_bytes());
+    if value.contains(&b'\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value);
+    entry.push(b'\n');
+}
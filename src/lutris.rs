@@ -0,0 +1,55 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Lutris game configuration snippets
+
+/// Build a YAML fragment to merge into a Lutris game configuration
+///
+/// With a gamescope mode (native, a FSR preset or a percentage, see [`crate::gamescope`]), the
+/// game runs in gamescope at its render resolution, upscaled with FSR for the presets.
+/// Otherwise a Wine virtual desktop of the display's resolution is used.
+pub fn config(res: (u16, u16), gamescope_mode: Option<&str>) -> anyhow::Result<String> {
+    let mut config = String::from("# Lutris game configuration generated by rres\n");
+
+    match gamescope_mode {
+        Some(mode) => {
+            let render = crate::render_res(res, mode)?;
+            config.push_str("system:\n");
+            config.push_str("  env:\n");
+            for (key, value) in crate::proton::env(res, mode, true)? {
+                config.push_str(&format!("    {key}: '{value}'\n"));
+            }
+            config.push_str("  gamescope: true\n");
+            config.push_str(&format!("  gamescope_output_res: {}x{}\n", res.0, res.1));
+            config.push_str(&format!(
+                "  gamescope_game_res: {}x{}\n",
+                render.0, render.1
+            ));
+            // Percentages use gamescope's default filter
+            if render != res && crate::render_scale(mode).is_none() {
+                config.push_str("  gamescope_flags: '-U'\n");
+            }
+        }
+        None => {
+            config.push_str("wine:\n");
+            config.push_str("  Desktop: true\n");
+            config.push_str(&format!("  WineDesktop: {}x{}\n", res.0, res.1));
+        }
+    }
+
+    Ok(config)
+}
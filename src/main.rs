@@ -15,13 +15,55 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, Write};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
 
 use anyhow::Context;
-use simple_logger::SimpleLogger;
 
 const USAGE: &str = "\
-Usage: rres [options]
+Usage: rres [command] [options]
+
+Commands:
+
+  run -- <program> [args]   Run a program with RRES_W, RRES_H, RRES_REFRESH (and
+                            RRES_FSR_W, RRES_FSR_H with -g) set, see Command tokens
+  wine -- <program> [args]  Run a program in a Wine virtual desktop sized to the display
+  wine-reg [file]           Print (or write to file) a Wine registry fragment setting
+                            the virtual desktop resolution. Import it with wine regedit
+  dxvk-conf [file]          Print (or write to file) a dxvk.conf capping the frame rate to
+                            the refresh rate, with the display's aspect ratio for D3D9
+                            and a texture LOD bias for the render resolution of -g
+  lutris                    Print a Lutris game configuration fragment: gamescope with -g,
+                            a Wine virtual desktop otherwise
+  wrapper <launcher>        Print a gamescope wrapper command (-g native by default)
+                            for the heroic or bottles launchers
+  steam -- %command%        Steam launch options wrapper, runs the game in gamescope
+                            (-g native by default)
+  info                      Print a summary of each display: current and native modes,
+                            maximum resolution and refresh rate, HDR, VRR, size
+  cvt <mode>                Print a CVT reduced blanking modeline (eg. 2560x1080@75,
+                            60Hz by default), and the matching kernel video= option
+  set --output <output> --mode <mode>
+                            Set a mode on an output (eg. DP-1) until Enter is pressed.
+                            Needs a free card: run it from a VT, without a compositor
+  completions <shell>       Print the completion script of bash, zsh or fish
+  dump [file]               Print (or write to file) the state of every card: connectors,
+                            encoders, CRTCs, modes and EDIDs, eg. for bug reports
+  doctor                    Check the common causes of detection failures (permissions,
+                            seat, driver, session), with hints to fix them. Exits with 1
+                            if one fails
+
+Options:
 
   -c, --card <card>       Specify a GPU (file existing in /dev/dri/, eg. card0)
   -m, --multi             Read all monitors. If this option is ommited, rres will
@@ -31,39 +73,264 @@ Usage: rres [options]
   -h, --help              Show this help message
   -g, --gamescope <mode>  Gamescope mode. Also supports FSR upscaling
                           Supported modes are native, ultra, quality, balanced and performance
+      --scale <percent>   With gamescope, render at a percentage of the resolution (eg.
+                          75%), upscaled with a plain linear filter instead of FSR
+      --mode <mode>       Use the closest supported mode instead of the current one,
+                          eg. 1920x1080 or 1920x1080@120
+      --res <res>         Use a supported resolution instead of the current one, like
+                          --mode without a refresh rate (eg. 1920x1080)
+      --best-refresh      Use the highest refresh rate available at the resolution,
+                          and print it instead of the resolution
+      --force-aspect <ratio>
+                          Use the largest region of an aspect ratio within the
+                          resolution, eg. 16:9 gives 2560x1440 on a 3440x1440 display
+                          (for games breaking on ultrawide), also for gamescope
+      --snap              Use the closest standard resolution (720p to 2160p, 16:9 or
+                          16:10) within the resolution, for games with fixed lists
+                          (eg. 2560x1440 on 3200x1800), also for gamescope
+      --max <res>         Scale the resolution down to fit within bounds, keeping its
+                          aspect ratio (eg. 1920x1080 on a 4K TV), also for gamescope
+      --aspect            Print the nominal aspect ratio of the resolution (eg. 16:9 for
+                          1366x768, 21:9 for 3440x1440) instead of the resolution
+      --logical           Print the resolution in logical pixels, divided by the scale
+                          the compositor (Wayland or GNOME) applies to the display
+      --force-res <res>   Force a resolution (eg. 1920x1080), like RRES_FORCE_RES.
+                          Warns if the display doesn't support it
+      --strict            Fail if the forced resolution isn't supported by the display
+      --fallback-res <res>
+                          Resolution used when no display can be detected, not even
+                          through Wayland or X11 (eg. in containers), like
+                          RRES_FALLBACK_RES
+      --native            Use the display's native (preferred) mode instead of the
+                          current one, eg. when the desktop runs at a lower resolution
+      --via-dbus          Ask the compositor over D-Bus (GNOME) instead of reading
+                          /dev/dri, eg. without access to the GPU
+      --no-cache          Probe the displays again instead of reusing the result of a
+                          run from the last 5 seconds
+      --via-portal        Ask the ScreenCast portal, which lets you pick the monitors.
+                          Logical sizes only, for sandboxes (Flatpak)
+      --prefer-drm        Read virtual GPUs (virtio-gpu, eg. in Crostini) through DRM
+                          instead of asking the compositor
+      --modeline          Print the mode in xorg.conf Modeline syntax
+      --interlaced        Also list and select interlaced and doublescan modes
+      --raw-orientation   Report the resolution of sideways panels (handhelds) as the
+                          panel scans out, eg. 800x1280 instead of 1280x800
+      --output <output>   Output (connector, eg. DP-1) for `rres set`
+      --gpu-policy <policy>
+                          Which GPU's displays come first: default (primary GPU,
+                          then card order), prefer-dgpu or prefer-igpu
+      --list-cards        List the GPUs, marking the primary (boot VGA) one
+      --redact            Mask the monitors' serial numbers in the EDIDs of `rres dump`,
+                          to share it publicly (models and modes are kept)
+      --debug-topology    Print which connector uses which encoder and CRTC, and the
+                          unused ones (also from RRES_BACKEND=mock:<file>)
+      --rpc               Answer JSON-RPC 2.0 requests on stdin, one per line: get_displays,
+                          get_res, build_gamescope and get_schema (see the README)
+      --schema            Print the JSON schema of the --rpc results
+      --env-file <file>   Also write the detected values (RRES_W, RRES_H, ..., and
+                          RRES_<index>_W, RRES_<index>_H... for each display) to a
+                          dotenv file, eg. for systemd's EnvironmentFile=
+      --metrics <address>
+                          With --rpc or --supervise, serve Prometheus metrics on
+                          host:port or a Unix socket path
+      --modes             List every mode supported by each display, marking the
+                          current and preferred ones
+      --render-gpu        Print the GPU that will render, honoring DRI_PRIME and
+                          __NV_PRIME_RENDER_OFFLOAD. Displays are always read from
+                          the GPU they are connected to
+      --spawn             Run wrapped commands as a child process and wait for them,
+                          instead of replacing rres
+      --gamemode          Run wrapped commands with gamemoderun
+      --mangohud          Show MangoHud in the game (through gamescope if used)
+      --hdr               With -g, enable HDR output in gamescope if the display
+                          supports it, tuned to the display's luminance
+      --print-cmd         Print the wrapped command as a shell-quoted string instead
+                          of running it
+      --supervise         With -g, restart gamescope with the new resolution when the
+                          display changes (eg. docking)
+      --log-file <file>   Copy rres' log to a file, with timestamps (eg. when Steam
+                          hides stderr)
+      --color <when>      Color the log levels: auto (on a terminal, unless NO_COLOR
+                          is set), always or never
+      --log-format <format>
+                          Log records as text (default) or json, one object per line
+      --log-child <file>  Copy the wrapped command's output to a file, with timestamps
+                          (implies --spawn)
+      --chdir <dir>       Run the wrapped command in another directory
+      --unset <var>       Remove a variable from the wrapped command's environment
+                          (eg. RRES_FORCE_RES). Can be specified multiple times
+      --deck              Show Steam Deck detection (model, game mode, dock)
+      --vulkan            Map each Vulkan device to its DRM card and displays
+                          (requires vulkaninfo)
+      --fast              Only read the primary GPU's first display (no cache, EDID nor
+                          logging), for launch scripts. Takes no other option
+      --bench <runs>      Probe the displays <runs> times with each backend and card,
+                          and print the min/avg/max timings
 
 Environment variables:
 
   RRES_DISPLAY=<index>      Select display in single mode (starting at 0)
-  RRES_FORCE_RES=RESXxRESY  Force a specific resolution to be detected
+  RRES_FORCE_RES=RESXxRESY  Force a specific resolution to be detected, without probing
+                            the displays (unless --strict)
+  RRES_FALLBACK_RES=RESXxRESY
+                            Resolution used when no display can be detected
   RRES_GAMESCOPE=<path>     Specify a gamescope binary for -g
+  RRES_GPU_POLICY=<policy>  Default value for --gpu-policy
+  RRES_CONFIG=<path>        Configuration file (default: ~/.config/rres/config)
+  RRES_WINE=<path>          Specify a wine binary for `rres wine`
+  RRES_WINE_DESKTOP=<name>  Wine virtual desktop name for `rres wine` and
+                            `rres wine-reg` (default: rres)
+  RRES_LOG_FILE=<file>      Default value for --log-file
+  RRES_BACKEND=mock:<file>  Replay the cards recorded by `rres dump` instead of probing
+                            the GPUs, eg. to test launch scripts without a GPU
+
+Command tokens:
+
+  Arguments of the wrapped commands can use {W}, {H}, {REFRESH} and the render
+  resolution {FSR_W}, {FSR_H} (same as {W}, {H} without -g), replaced by their values.
+  Without a known refresh rate (eg. with RRES_FORCE_RES), RRES_REFRESH is unset and
+  {REFRESH} is kept.
+
+  Example:
+  ./rres run -- game --width {W} --height {H}
+
+Configuration file:
+
+  Steam games (detected through SteamAppId) can have a forced resolution or display:
+
+  [steam.12345]
+  res = 1920x1080
+  display = 1
 
 Wine Virtual Desktop example:
 
-  wine \"explorer /desktop=Game,$(./rres)\" game.exe
+  ./rres wine -- game.exe
+
+  Which is equivalent to:
+  wine \"explorer /desktop=rres,$(./rres)\" game.exe
 
 Gamescope usage:
 
   ./rres -g FSR_MODE -- GAMESCOPE_ARGS
 
   Example:
-  ./rres -g ultra -- -f -- wine game.exe";
+  ./rres -g ultra -- -f -- wine game.exe
+
+Steam launch options example:
+
+  rres steam -g quality -- %command%";
+
+/// Subcommands, selected by the first argument
+enum Command {
+    /// Print the resolution, or run gamescope
+    Default,
+    /// Run a program
+    Run,
+    /// Run a program in a Wine virtual desktop
+    Wine,
+    /// Generate a Wine registry file for the virtual desktop
+    WineReg,
+    /// Generate a dxvk.conf
+    DxvkConf,
+    /// Print a Lutris configuration fragment
+    Lutris,
+    /// Print a wrapper command for a launcher
+    Wrapper,
+    /// Wrap a Steam `%command%` in gamescope
+    Steam,
+    /// Set a mode through KMS
+    Set,
+    /// Compute a CVT-RB modeline
+    Cvt,
+    /// Print a summary of the displays
+    Info,
+    /// Print a shell completion script
+    Completions,
+    /// Record the state of the cards
+    Dump,
+    /// Diagnose detection failures
+    Doctor,
+}
+
+impl Command {
+    /// Whether the trailing arguments are a command to run, instead of operands
+    fn wraps_command(&self) -> bool {
+        matches!(self, Self::Default | Self::Run | Self::Wine | Self::Steam)
+    }
+}
 
 fn main() -> anyhow::Result<()> {
+    // Launch scripts may run `rres --fast` before every game, answer before any setup
+    let fast = env::args_os().skip(1).eq([OsString::from("--fast")]);
+    if fast {
+        if let Some(res) = rres::get_res_fast() {
+            println!("{}x{}", res.0, res.1);
+            return Ok(());
+        }
+    }
+
     // Settings
     let mut verbosity = log::LevelFilter::Warn;
     let mut multi = false;
-    let mut card: Option<String> = None;
+    let mut options = rres::ProbeOptions {
+        cache: true,
+        ..Default::default()
+    };
     let mut gamescope: Option<String> = None;
-    let mut gamescope_args: Vec<String> = vec![];
+    let mut list_cards = false;
+    let mut debug_topology = false;
+    // Only used by `rres dump`
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    let mut redact = false;
+    let mut rpc = false;
+    let mut schema = false;
+    let mut metrics: Option<String> = None;
+    let mut env_file: Option<String> = None;
+    let mut render_gpu = false;
+    let mut vulkan = false;
+    let mut bench: Option<usize> = None;
+    let mut deck = false;
+    let mut list_modes = false;
+    let mut output: Option<String> = None;
+    let mut modeline = false;
+    let mut aspect = false;
+    let mut logical = false;
+    let mut scale: Option<String> = None;
+    let mut command_args: Vec<String> = vec![];
+    let mut run_options = RunOptions::default();
 
     // Init logger
-    SimpleLogger::new().with_level(verbosity).init()?;
+    rres::logger::init(verbosity)?;
+
+    if let Ok(policy) = env::var("RRES_GPU_POLICY") {
+        options.gpu_policy = parse_gpu_policy(&policy)?;
+    }
 
     // Handle CLI
+    let command;
     {
         use lexopt::prelude::*;
-        let mut parser = lexopt::Parser::from_env();
+        let mut args: Vec<OsString> = env::args_os().skip(1).collect();
+        command = match args.first().and_then(|a| a.to_str()) {
+            Some("run") => Command::Run,
+            Some("wine") => Command::Wine,
+            Some("wine-reg") => Command::WineReg,
+            Some("dxvk-conf") => Command::DxvkConf,
+            Some("lutris") => Command::Lutris,
+            Some("wrapper") => Command::Wrapper,
+            Some("steam") => Command::Steam,
+            Some("set") => Command::Set,
+            Some("cvt") => Command::Cvt,
+            Some("info") => Command::Info,
+            Some("completions") => Command::Completions,
+            Some("dump") => Command::Dump,
+            Some("doctor") => Command::Doctor,
+            _ => Command::Default,
+        };
+        if !matches!(command, Command::Default) {
+            args.remove(0);
+        }
+        let mut parser = lexopt::Parser::from_args(args);
 
         while let Some(arg) = parser.next()? {
             match arg {
@@ -71,34 +338,378 @@ fn main() -> anyhow::Result<()> {
                     multi = true;
                 }
                 Short('c') | Long("card") => {
-                    card = Some(parser.value()?.into_string().unwrap());
+                    options.card = Some(parser.value()?.into_string().unwrap());
                 }
                 Short('h') | Long("help") => {
                     println!("{USAGE}");
                     process::exit(0);
                 }
                 Short('v') | Long("verbose") => {
-                    verbosity = increment_loglevel(verbosity);
+                    verbosity = rres::logger::louder(verbosity);
                 }
                 Short('q') | Long("quiet") => {
-                    verbosity = decrement_loglevel(verbosity);
+                    verbosity = rres::logger::quieter(verbosity);
                 }
                 Short('g') | Long("gamescope") => {
                     gamescope = Some(parser.value()?.into_string().unwrap());
                 }
+                Long("scale") => {
+                    scale = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("mode") => {
+                    let value = parser.value()?.to_string_lossy().to_string();
+                    options.mode = Some(
+                        rres::modes::ModeSpec::try_from(value.as_str())
+                            .with_context(|| format!("invalid mode: {value}"))?,
+                    );
+                }
+                Long("res") => {
+                    options.mode = Some(rres::modes::ModeSpec {
+                        size: rres::config::parse_res(&parser.value()?.to_string_lossy())?,
+                        refresh: None,
+                    });
+                }
+                Long("best-refresh") => {
+                    options.best_refresh = true;
+                }
+                Long("aspect") => {
+                    aspect = true;
+                }
+                Long("logical") => {
+                    logical = true;
+                }
+                Long("snap") => {
+                    options.snap = true;
+                }
+                Long("max") => {
+                    let max = rres::config::parse_res(&parser.value()?.to_string_lossy())?;
+                    if max.0 == 0 || max.1 == 0 {
+                        return Err(anyhow::anyhow!(
+                            "invalid bounds: {}x{} (must be at least 1x1)",
+                            max.0,
+                            max.1
+                        ));
+                    }
+                    options.max_res = Some(max);
+                }
+                Long("force-aspect") => {
+                    let value = parser.value()?.to_string_lossy().to_string();
+                    options.force_aspect = Some(
+                        rres::aspect::Ratio::try_from(value.as_str())
+                            .with_context(|| format!("invalid aspect ratio: {value}"))?,
+                    );
+                }
+                Long("force-res") => {
+                    options.force_res =
+                        Some(rres::config::parse_res(&parser.value()?.to_string_lossy())?);
+                }
+                Long("fallback-res") => {
+                    options.fallback_res =
+                        Some(rres::config::parse_res(&parser.value()?.to_string_lossy())?);
+                }
+                Long("via-dbus") => {
+                    options.via_dbus = true;
+                }
+                Long("no-cache") => {
+                    options.cache = false;
+                }
+                Long("via-portal") => {
+                    options.via_portal = true;
+                }
+                Long("prefer-drm") => {
+                    options.prefer_drm = true;
+                }
+                Long("strict") => {
+                    options.strict = true;
+                }
+                Long("native") => {
+                    options.native = true;
+                }
+                Long("modeline") => {
+                    modeline = true;
+                }
+                Long("interlaced") => {
+                    options.interlaced = true;
+                }
+                Long("raw-orientation") => {
+                    options.raw_orientation = true;
+                }
+                Long("output") => {
+                    output = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("gpu-policy") => {
+                    options.gpu_policy = parse_gpu_policy(&parser.value()?.to_string_lossy())?;
+                }
+                Long("list-cards") => {
+                    list_cards = true;
+                }
+                Long("debug-topology") => {
+                    debug_topology = true;
+                }
+                #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+                Long("redact") => {
+                    redact = true;
+                }
+                Long("rpc") => {
+                    rpc = true;
+                }
+                Long("env-file") => {
+                    env_file = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("schema") => {
+                    schema = true;
+                }
+                Long("metrics") => {
+                    metrics = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("modes") => {
+                    list_modes = true;
+                }
+                Long("render-gpu") => {
+                    render_gpu = true;
+                }
+                Long("spawn") => {
+                    run_options.spawn = true;
+                }
+                Long("gamemode") => {
+                    run_options.gamemode = true;
+                }
+                Long("mangohud") => {
+                    run_options.mangohud = true;
+                }
+                Long("hdr") => {
+                    run_options.hdr = true;
+                    options.edid = true;
+                }
+                Long("print-cmd") => {
+                    run_options.print_cmd = true;
+                }
+                Long("supervise") => {
+                    run_options.supervise = true;
+                }
+                Long("color") => {
+                    let color = parser.value()?.to_string_lossy().to_string();
+                    rres::logger::set_color(rres::logger::Color::try_from(color.as_str())?);
+                }
+                Long("log-format") => {
+                    let format = parser.value()?.to_string_lossy().to_string();
+                    rres::logger::set_format(rres::logger::Format::try_from(format.as_str())?);
+                }
+                Long("log-file") => {
+                    rres::logger::set_file(&parser.value()?.to_string_lossy())?;
+                }
+                Long("log-child") => {
+                    run_options.log_child = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("chdir") => {
+                    run_options.chdir = Some(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("unset") => {
+                    run_options
+                        .unset
+                        .push(parser.value()?.to_string_lossy().to_string());
+                }
+                Long("deck") => {
+                    deck = true;
+                }
+                Long("vulkan") => {
+                    vulkan = true;
+                }
+                // Without the fast path's answer, detect as usual
+                Long("fast") if fast => {}
+                Long("fast") => {
+                    return Err(anyhow::anyhow!(
+                        "--fast can't be combined with other options"
+                    ));
+                }
+                Long("bench") => {
+                    bench = Some(parser.value()?.parse()?);
+                }
+                Value(val) if !command.wraps_command() => {
+                    command_args.push(val.to_string_lossy().to_string());
+                }
                 Value(val) => {
-                    gamescope_args.push(val.to_string_lossy().to_string());
-                    gamescope_args
+                    command_args.push(val.to_string_lossy().to_string());
+                    command_args
                         .extend(parser.raw_args()?.map(|s| s.to_string_lossy().to_string()));
                 }
                 _ => return Err(arg.unexpected().into()),
             }
         }
     }
+    rres::logger::set_level(verbosity);
+
+    // The scale is a gamescope mode, replacing FSR
+    if let Some(scale) = scale {
+        match gamescope.as_deref() {
+            None | Some("native") => {
+                gamescope = Some(format!("{}%", scale.trim_end_matches('%')));
+            }
+            Some(fsr_mode) => {
+                return Err(anyhow::anyhow!(
+                    "--scale can't be used with the {fsr_mode} FSR mode"
+                ));
+            }
+        }
+    }
+
+    if schema {
+        println!("{}", rres::rpc::schema());
+        return Ok(());
+    }
+
+    // Long-running modes follow mode changes
+    if rpc || run_options.supervise {
+        options.cache = false;
+    }
+
+    if let Some(address) = metrics {
+        if !rpc && !run_options.supervise {
+            log::warn!("--metrics is only useful with --rpc or --supervise");
+        }
+        rres::metrics::serve(&address)?;
+    }
+
+    if let Some(runs) = bench {
+        for timings in rres::bench::run(&options, runs) {
+            println!("{timings}");
+        }
+
+        return Ok(());
+    }
+
+    if rpc {
+        return rres::rpc::serve(io::stdin().lock(), io::stdout().lock(), &options);
+    }
+
+    if debug_topology {
+        #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+        return Err(rres::backend::Unsupported.into());
+        #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+        {
+            use rres::backend::fixture::Fixture;
+            let fixture = match rres::backend::mock_fixture()? {
+                Some(path) => Fixture::load(&path)?,
+                None => Fixture::capture(&options)?,
+            };
+            print!("{}", fixture.topology());
+            return Ok(());
+        }
+    }
+
+    if list_cards {
+        let mut cards = rres::get_cards(options.card.clone())?;
+        options.gpu_policy.sort(&mut cards);
+        for card in cards {
+            let primary = if rres::gpu::is_primary(&card) {
+                ", primary"
+            } else {
+                ""
+            };
+            println!(
+                "{} {}{primary}",
+                rres::prime::describe_card(&card),
+                rres::gpu::kind(&card)
+            );
+        }
+
+        return Ok(());
+    }
+
+    if render_gpu {
+        let cards = rres::get_cards(options.card)?;
+        let offload = rres::prime::render_offload();
+        match rres::prime::render_card(&cards, offload.as_ref()) {
+            Some(render) => println!("{}", rres::prime::describe_card(&render)),
+            None => return Err(anyhow::anyhow!("could not find the rendering GPU")),
+        }
+
+        return Ok(());
+    }
+
+    if vulkan {
+        let cards = rres::get_cards(options.card.clone())?;
+        for device in rres::vulkan::devices()? {
+            println!(
+                "GPU{}: {} [{}]",
+                device.index,
+                device.name,
+                device.device_select()
+            );
+            let Some(card) = device.find_card(&cards) else {
+                println!("  No DRM card (software or unsupported device)");
+                continue;
+            };
+            let card_name = card.file_name().unwrap().to_string_lossy().to_string();
+            println!("  Card: {card_name}");
+            let displays = rres::get_displays_opts(&rres::ProbeOptions {
+                card: Some(card_name.clone()),
+                ..options.clone()
+            })?;
+            for mode in displays {
+                let res = mode.size();
+                println!("  Display: {}x{}", res.0, res.1);
+            }
+            println!(
+                "  Select with: MESA_VK_DEVICE_SELECT={} rres --card {card_name}",
+                device.device_select()
+            );
+        }
+
+        return Ok(());
+    }
+
+    if deck {
+        let Some(model) = rres::deck::model() else {
+            println!("Not a Steam Deck");
+            return Ok(());
+        };
+        let displays = rres::probe(&options)?;
+        println!("Model: {model}");
+        println!(
+            "Game mode (gamescope session): {}",
+            yes_no(rres::deck::in_gamescope_session())
+        );
+        println!("Docked: {}", yes_no(rres::deck::is_docked(&displays)));
+        for (i, display) in displays.iter().enumerate() {
+            let res = display.mode.size();
+            let kind = if display.is_internal() {
+                "internal"
+            } else {
+                "external"
+            };
+            println!(
+                "Display #{i}: {} ({kind}), {}x{}",
+                display.connector, res.0, res.1
+            );
+        }
+
+        return Ok(());
+    }
+
+    if list_modes {
+        for (i, display) in rres::probe(&options)?.iter().enumerate() {
+            let card = display
+                .card
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            println!("Display #{i}: {} ({card})", display.connector);
+            for mode in &display.modes {
+                let mut flags = rres::modes::flags(mode);
+                if *mode == display.mode {
+                    flags.insert(0, "current");
+                }
+                println!("  {:<20}{}", rres::modes::describe(mode), flags.join(", "));
+            }
+        }
+
+        return Ok(());
+    }
 
     if multi {
         // List every display
-        let displays = rres::get_displays(card)?;
+        let displays = rres::get_displays_opts(&options)?;
 
         for (i, mode) in displays.iter().enumerate() {
             let res = mode.size();
@@ -108,29 +719,347 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let res = rres::get_res_card(card)?;
+    if let Command::Info = command {
+        let options = rres::ProbeOptions {
+            edid: true,
+            ..options.clone()
+        };
+        for (i, display) in rres::probe(&options)?.iter().enumerate() {
+            print_info(i, display);
+        }
+
+        return Ok(());
+    }
 
-    if let Some(fsr_mode) = gamescope {
-        let mut gamescope_runner = rres::gamescope(res, &fsr_mode)?;
+    if let Command::Doctor = command {
+        let checks = rres::doctor::run(options.card.clone());
+        for check in &checks {
+            println!("{check}");
+        }
+        if checks
+            .iter()
+            .any(|c| c.status == rres::doctor::Status::Error)
+        {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Command::Dump = command {
+        #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+        return Err(rres::backend::Unsupported.into());
+        #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+        {
+            let mut fixture = rres::backend::fixture::Fixture::capture(&options)?;
+            if redact {
+                fixture.redact();
+            }
+            let dump = format!("{}\n", fixture.to_json());
+            match command_args.first() {
+                Some(file) => {
+                    fs::write(file, dump).with_context(|| format!("failed to write {file}"))?;
+                    log::info!("Replay it with RRES_BACKEND=mock:{file}");
+                }
+                None => print!("{dump}"),
+            }
+
+            return Ok(());
+        }
+    }
+
+    if let Command::Completions = command {
+        match command_args.first().map(String::as_str) {
+            None => return Err(anyhow::anyhow!("missing shell (bash, zsh or fish)")),
+            // Values completed by the scripts
+            Some("cards") => {
+                for card in rres::get_cards(None)? {
+                    if let Some(name) = card.file_name() {
+                        println!("{}", name.to_string_lossy());
+                    }
+                }
+            }
+            Some("connectors") => {
+                for display in rres::probe(&options)? {
+                    println!("{}", display.connector);
+                }
+            }
+            Some(shell) => {
+                let shell = rres::completions::Shell::try_from(shell)?;
+                print!("{}", rres::completions::generate(shell, USAGE));
+            }
+        }
+
+        return Ok(());
+    }
+
+    if let Command::Cvt = command {
+        let Some(spec) = command_args.first() else {
+            return Err(anyhow::anyhow!("missing mode (eg. 2560x1080@75)"));
+        };
+        let spec = rres::modes::ModeSpec::try_from(spec.as_str())?;
+        let refresh = spec.refresh.unwrap_or(60.0);
+        let mode = rres::cvt::reduced_blanking(spec.size, refresh)?;
+        let (width, height) = mode.size();
+        println!(
+            "# {width}x{height} {:.2} Hz (CVT-RB) hsync: {:.2} kHz; pclk: {:.2} MHz",
+            rres::modes::refresh(&mode),
+            mode.clock as f64 / mode.htotal as f64,
+            mode.clock as f64 / 1000.0
+        );
+        println!(
+            "{}",
+            rres::modes::modeline(&mode, &format!("{width}x{height}R"))
+        );
+        println!(
+            "# Kernel command line: video=<output>:{width}x{height}R@{}",
+            refresh.round()
+        );
+
+        return Ok(());
+    }
+
+    if let Command::Set = command {
+        let Some(output) = output else {
+            return Err(anyhow::anyhow!("missing --output"));
+        };
+        let Some(spec) = &options.mode else {
+            return Err(anyhow::anyhow!("missing --mode"));
+        };
+        // Only the modes as the panel scans out can be set
+        options.raw_orientation = true;
+        let Some(display) = rres::probe(&options)?
+            .into_iter()
+            .find(|d| d.connector == output)
+        else {
+            return Err(anyhow::anyhow!("no such output: {output}"));
+        };
+        let Some(mode) = rres::modes::closest(&display.modes, spec) else {
+            return Err(anyhow::anyhow!("{output} doesn't support {spec}"));
+        };
+        return set_mode(&display, &output, mode);
+    }
 
-        gamescope_runner.extend(
-            gamescope_args
-                .iter()
-                .map(|s| s.as_str().to_owned())
-                .collect::<Vec<String>>(),
+    // Per-game overrides, the command line's options win
+    if let Some(app_id) = rres::config::steam_app_id(&command_args) {
+        let config = rres::config::Config::load()?;
+        if let Some(app) = config.apps.get(&app_id) {
+            log::info!("Applying overrides for Steam AppID {app_id}: {app:?}");
+            options.force_res = options.force_res.or(app.res);
+            options.display = options.display.or(app.display);
+        }
+    }
+
+    let mode = rres::get_mode_opts(&options)?;
+    let res = mode.size();
+    let hdr = if run_options.hdr {
+        let display = rres::get_display_opts(&options)?;
+        let metadata = display.hdr_metadata().filter(|m| m.is_hdr());
+        if metadata.is_none() {
+            log::warn!("{} doesn't support HDR, leaving it off", display.connector);
+        }
+        metadata
+    } else {
+        None
+    };
+
+    if modeline {
+        if mode.clock == 0 {
+            return Err(anyhow::anyhow!("forced resolutions have no timings"));
+        }
+        println!(
+            "{}",
+            rres::modes::modeline(&mode, &format!("{}x{}", res.0, res.1))
+        );
+
+        return Ok(());
+    }
+
+    let values = rres::command::Values {
+        res,
+        refresh: mode.vrefresh,
+        render_res: rres::render_res(res, gamescope.as_deref().unwrap_or_default())?,
+    };
+    if let Some(file) = env_file {
+        let displays = rres::probe(&options)?;
+        fs::write(&file, rres::command::env_file(&values, &displays))
+            .with_context(|| format!("failed to write {file}"))?;
+    }
+
+    let raw_args = command_args;
+    let command_args = rres::command::substitute(&raw_args, &values);
+
+    if let Command::Run = command {
+        let mut command_args = command_args;
+        if run_options.mangohud {
+            command_args = rres::command::mangohud(&command_args, 0);
+        }
+        let mut env = values.env();
+        env.extend(rres::proton::setup(
+            &command_args,
+            res,
+            gamescope.as_deref().unwrap_or_default(),
+            false,
+        )?);
+        return run(&command_args, &env, &run_options);
+    }
+
+    if let Command::Wine = command {
+        if command_args.is_empty() {
+            return Err(anyhow::anyhow!("missing program to run in wine"));
+        }
+        let env = rres::proton::setup(
+            &command_args,
+            res,
+            gamescope.as_deref().unwrap_or_default(),
+            false,
+        )?;
+        let mut wine_runner = rres::wine::virtual_desktop(res)?;
+        wine_runner.extend(command_args);
+        if run_options.mangohud {
+            wine_runner = rres::command::mangohud(&wine_runner, 0);
+        }
+
+        return run(&wine_runner, &env, &run_options);
+    }
+
+    if let Command::WineReg = command {
+        let registry = rres::wine::registry(
+            res,
+            rres::wine::prefix().as_deref(),
+            &rres::wine::desktop_name(),
         );
+        match command_args.first() {
+            Some(file) => {
+                fs::write(file, registry).with_context(|| format!("failed to write {file}"))?;
+                log::info!("Import it with: wine regedit {file}");
+            }
+            None => print!("{registry}"),
+        }
+
+        return Ok(());
+    }
+
+    if let Command::DxvkConf = command {
+        let render_res = rres::render_res(res, gamescope.as_deref().unwrap_or_default())?;
+        let config = rres::dxvk::config(&mode, render_res);
+        match command_args.first() {
+            Some(file) => {
+                fs::write(file, config).with_context(|| format!("failed to write {file}"))?
+            }
+            None => print!("{config}"),
+        }
+
+        return Ok(());
+    }
+
+    if let Command::Lutris = command {
+        print!("{}", rres::lutris::config(res, gamescope.as_deref())?);
+
+        return Ok(());
+    }
+
+    if let Command::Wrapper = command {
+        let Some(launcher) = command_args.first() else {
+            return Err(anyhow::anyhow!("missing launcher (heroic or bottles)"));
+        };
+        let Ok(launcher) = rres::command::Launcher::try_from(launcher.as_str()) else {
+            return Err(anyhow::anyhow!("unknown launcher: {launcher}"));
+        };
+        let fsr_mode = gamescope.as_deref().unwrap_or_default();
+        // The launcher may run a Proton game, that can't be told from here
+        let mut wrapper = vec!["env".to_string()];
+        for (key, value) in rres::proton::env(res, fsr_mode, true)? {
+            log::info!("Setting {key}={value}, for Proton games");
+            wrapper.push(format!("{key}={value}"));
+        }
+        wrapper.extend(rres::gamescope(res, fsr_mode)?);
+        println!("{}", launcher.wrapper(&wrapper));
 
-        log::info!(
-            "Running {} with args {:?}",
-            &gamescope_runner[0],
-            &gamescope_runner[1..]
+        return Ok(());
+    }
+
+    if let Command::Steam = command {
+        if command_args.is_empty() {
+            return Err(anyhow::anyhow!("missing %command% to wrap"));
+        }
+        let fsr_mode = gamescope.unwrap_or("native".to_string());
+        let env = rres::proton::setup(&command_args, res, &fsr_mode, true)?;
+        if rres::deck::in_gamescope_session() {
+            log::info!("Already in a gamescope session, skipping nested gamescope");
+            return run(&command_args, &env, &run_options);
+        }
+
+        let mut gamescope_runner = rres::gamescope(res, &fsr_mode)?;
+
+        if let Some(metadata) = &hdr {
+            rres::command::gamescope_hdr(&mut gamescope_runner, metadata);
+        }
+        let mut command_args = command_args;
+        if run_options.mangohud && !rres::command::gamescope_mangoapp(&mut gamescope_runner) {
+            let position = rres::steam::insert_position(&command_args);
+            command_args = rres::command::mangohud(&command_args, position);
+        }
+
+        return run(
+            &rres::steam::wrap(&command_args, &gamescope_runner),
+            &env,
+            &run_options,
         );
+    }
+
+    if let Some(fsr_mode) = gamescope {
+        let env = rres::proton::setup(&command_args, res, &fsr_mode, true)?;
+        if rres::deck::in_gamescope_session() {
+            // Run the command after the gamescope arguments
+            log::info!("Already in a gamescope session, skipping nested gamescope");
+            let Some(separator) = command_args.iter().position(|a| a == "--") else {
+                return Err(anyhow::anyhow!("missing command after gamescope arguments"));
+            };
+            return run(&command_args[separator + 1..], &env, &run_options);
+        }
 
-        let mut exec = process::Command::new(&gamescope_runner[0]);
-        exec.args(&gamescope_runner[1..]);
-        exec.spawn()
-            .with_context(|| format!("failed to run {}", gamescope_runner[0]))?
-            .wait()?;
+        if run_options.supervise && !run_options.print_cmd {
+            let proton = !env.is_empty();
+            return supervise(&options, &run_options, |mode| {
+                let res = mode.size();
+                let values = rres::command::Values {
+                    res,
+                    refresh: mode.vrefresh,
+                    render_res: rres::render_res(res, &fsr_mode)?,
+                };
+                let args = rres::command::substitute(&raw_args, &values);
+                let env = rres::proton::env(res, &fsr_mode, true)?;
+                let command =
+                    gamescope_command(res, &fsr_mode, args, run_options.mangohud, hdr.as_ref())?;
+                Ok((command, if proton { env } else { vec![] }))
+            });
+        }
+
+        let gamescope_runner = gamescope_command(
+            res,
+            &fsr_mode,
+            command_args,
+            run_options.mangohud,
+            hdr.as_ref(),
+        )?;
+        run(&gamescope_runner, &env, &run_options)?;
+    } else if options.best_refresh {
+        println!("{}", mode.vrefresh);
+    } else if aspect {
+        println!("{}", rres::aspect::nominal(res));
+    } else if logical {
+        let display = rres::get_display_opts(&options)?;
+        let res = match rres::backend::scale(&display.connector) {
+            Some(scale) => rres::logical_res(res, scale),
+            None => {
+                log::warn!(
+                    "No compositor reports the scale of {}, printing physical pixels",
+                    display.connector
+                );
+                res
+            }
+        };
+        println!("{}x{}", res.0, res.1);
     } else {
         println!("{}x{}", res.0, res.1);
     }
@@ -138,26 +1067,521 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Increase `log::LevelFilter` by one level
-fn increment_loglevel(level: log::LevelFilter) -> log::LevelFilter {
-    use log::LevelFilter::*;
-    match level {
-        Off => Error,
-        Error => Warn,
-        Warn => Info,
-        Info => Debug,
-        Debug | Trace => Trace,
+/// Build the gamescope command, followed by the user's gamescope arguments and command
+///
+/// HDR output is enabled with the display's HDR metadata, if given.
+fn gamescope_command(
+    res: (u16, u16),
+    fsr_mode: &str,
+    mut args: Vec<String>,
+    mangohud: bool,
+    hdr: Option<&rres::edid::HdrMetadata>,
+) -> anyhow::Result<Vec<String>> {
+    let mut gamescope_runner = rres::gamescope(res, fsr_mode)?;
+    if let Some(metadata) = hdr {
+        rres::command::gamescope_hdr(&mut gamescope_runner, metadata);
+    }
+    if mangohud && !rres::command::gamescope_mangoapp(&mut gamescope_runner) {
+        match args.iter().position(|a| a == "--") {
+            Some(separator) => args = rres::command::mangohud(&args, separator + 1),
+            None => log::warn!("no command after gamescope arguments, skipping MangoHud"),
+        }
+    }
+    gamescope_runner.extend(args);
+
+    Ok(gamescope_runner)
+}
+
+/// How wrapped commands are run
+#[derive(Default)]
+struct RunOptions {
+    /// Spawn the command and wait for it, instead of replacing rres with it
+    spawn: bool,
+    /// Run the command with gamemode
+    gamemode: bool,
+    /// Show MangoHud in the game
+    mangohud: bool,
+    /// Enable HDR output in gamescope, when the display supports it
+    hdr: bool,
+    /// Print the command as a shell-quoted string instead of running it
+    print_cmd: bool,
+    /// Restart gamescope when the display changes
+    supervise: bool,
+    /// Log file for the command's output
+    log_child: Option<String>,
+    /// Working directory of the command
+    chdir: Option<String>,
+    /// Environment variables removed from the command's environment
+    unset: Vec<String>,
+}
+
+/// Run a command (program followed by its arguments)
+///
+/// `env` is added to the command's environment. rres is replaced by the command, unless
+/// `options.spawn` is set: termination signals are then forwarded to the command, and rres
+/// exits with the command's exit code.
+fn run(command: &[String], env: &[(String, String)], options: &RunOptions) -> anyhow::Result<()> {
+    let command = prepare(command, options)?;
+
+    if options.print_cmd {
+        let mut line: Vec<String> = vec![];
+        if !options.unset.is_empty() {
+            line.push("env".to_string());
+            for var in &options.unset {
+                line.extend(["-u".to_string(), var.clone()]);
+            }
+        }
+        line.extend(env.iter().map(|(k, v)| format!("{k}={v}")));
+        line.extend(command);
+        match &options.chdir {
+            Some(dir) => println!(
+                "cd {} && {}",
+                rres::command::quote(dir),
+                rres::command::join(&line)
+            ),
+            None => println!("{}", rres::command::join(&line)),
+        }
+        return Ok(());
+    }
+
+    log::info!("Running {} with args {:?}", &command[0], &command[1..]);
+
+    let mut exec = process_command(&command, env, options);
+
+    // Other platforms can't replace the process, the command is waited for
+    #[cfg(unix)]
+    if !options.spawn && options.log_child.is_none() {
+        // Only returns on failure
+        let e = exec.exec();
+        return Err(e).with_context(|| format!("failed to run {}", command[0]));
+    }
+
+    let (mut child, tees) = spawn(&mut exec, options)?;
+    forward_signals(child.id());
+    let status = child.wait()?;
+    for tee in tees {
+        let _ = tee.join();
+    }
+    exit_on_failure(&command[0], status);
+
+    Ok(())
+}
+
+/// Build a process from a command, with its environment and working directory
+fn process_command(
+    command: &[String],
+    env: &[(String, String)],
+    options: &RunOptions,
+) -> process::Command {
+    let mut exec = process::Command::new(&command[0]);
+    exec.args(&command[1..]);
+    for var in &options.unset {
+        log::info!("Unsetting {var}");
+        exec.env_remove(var);
+    }
+    for (key, value) in env {
+        log::info!("Setting {key}={value}");
+        exec.env(key, value);
+    }
+    if let Some(dir) = &options.chdir {
+        exec.current_dir(dir);
+    }
+    exec
+}
+
+/// Apply the run options to a command
+fn prepare(command: &[String], options: &RunOptions) -> anyhow::Result<Vec<String>> {
+    if command.is_empty() {
+        return Err(anyhow::anyhow!("missing command to run"));
+    }
+    if options.gamemode {
+        return rres::command::gamemode(command);
+    }
+    Ok(command.to_vec())
+}
+
+/// Spawn a command, copying its output to the child log file if enabled
+///
+/// The returned threads finish once the command's output is closed.
+fn spawn(
+    exec: &mut process::Command,
+    options: &RunOptions,
+) -> anyhow::Result<(process::Child, Vec<thread::JoinHandle<()>>)> {
+    let program = exec.get_program().to_string_lossy().to_string();
+    let Some(log_child) = &options.log_child else {
+        let child = exec
+            .spawn()
+            .with_context(|| format!("failed to run {program}"))?;
+        return Ok((child, vec![]));
+    };
+
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_child)
+        .with_context(|| format!("failed to open {log_child}"))?;
+    let log = Arc::new(Mutex::new(log));
+
+    exec.stdout(process::Stdio::piped());
+    exec.stderr(process::Stdio::piped());
+    let mut child = exec
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+
+    let mut tees = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        tees.push(tee(stdout, io::stdout(), "stdout", log.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tees.push(tee(stderr, io::stderr(), "stderr", log));
+    }
+
+    Ok((child, tees))
+}
+
+/// Copy lines from `input` to `output`, and to `log` with a timestamp
+fn tee<R, W>(
+    input: R,
+    mut output: W,
+    stream: &'static str,
+    log: Arc<Mutex<fs::File>>,
+) -> thread::JoinHandle<()>
+where
+    R: io::Read + Send + 'static,
+    W: io::Write + Send + 'static,
+{
+    thread::spawn(move || {
+        for line in io::BufReader::new(input).split(b'\n').map_while(Result::ok) {
+            let _ = output.write_all(&line);
+            let _ = output.write_all(b"\n");
+            let _ = output.flush();
+            if let Ok(mut log) = log.lock() {
+                let _ = writeln!(
+                    log,
+                    "{} {stream}: {}",
+                    rres::timestamp::now(),
+                    String::from_utf8_lossy(&line)
+                );
+            }
+        }
+    })
+}
+
+/// Exit with the exit code of a failed command, like the shell does
+fn exit_on_failure(program: &str, status: process::ExitStatus) {
+    if !status.success() {
+        log::info!("{program} exited with {status}");
+        let code = match status.code() {
+            Some(code) => code,
+            #[cfg(unix)]
+            None => 128 + status.signal().unwrap_or(0),
+            #[cfg(not(unix))]
+            None => 1,
+        };
+        process::exit(code);
+    }
+}
+
+/// Interval between display checks when supervising
+const SUPERVISE_INTERVAL: time::Duration = time::Duration::from_secs(2);
+
+/// Run a command (and its environment) built for the current display mode, restarting it when
+/// the mode changes
+fn supervise<F>(
+    probe_options: &rres::ProbeOptions,
+    options: &RunOptions,
+    build: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(&rres::Mode) -> anyhow::Result<(Vec<String>, Vec<(String, String)>)>,
+{
+    loop {
+        let mode = rres::get_mode_opts(probe_options)?;
+        let (command, env) = build(&mode)?;
+        let command = prepare(&command, options)?;
+        log::info!("Running {} with args {:?}", &command[0], &command[1..]);
+
+        let mut exec = process_command(&command, &env, options);
+        let (mut child, _tees) = spawn(&mut exec, options)?;
+        forward_signals(child.id());
+
+        loop {
+            thread::sleep(SUPERVISE_INTERVAL);
+            if let Some(status) = child.try_wait()? {
+                exit_on_failure(&command[0], status);
+                return Ok(());
+            }
+            match rres::get_mode_opts(probe_options) {
+                Ok(current) if current.size() != mode.size() => {
+                    let (old, new) = (mode.size(), current.size());
+                    log::info!(
+                        "Display changed ({}x{} -> {}x{}), restarting",
+                        old.0,
+                        old.1,
+                        new.0,
+                        new.1
+                    );
+                    // SAFETY: plain kill(2) on our own child
+                    #[cfg(unix)]
+                    unsafe {
+                        libc::kill(child.id() as i32, libc::SIGTERM);
+                    }
+                    #[cfg(not(unix))]
+                    child.kill()?;
+                    child.wait()?;
+                    break;
+                }
+                Ok(_) => {}
+                // Displays may briefly disappear while (un)docking
+                Err(e) => log::debug!("failed to read the display: {e}"),
+            }
+        }
+    }
+}
+
+/// PID of the spawned command, for signal forwarding
+#[cfg(unix)]
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if pid > 0 {
+        // SAFETY: kill is async-signal-safe
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+/// Forward termination signals sent to rres to the spawned command
+#[cfg(unix)]
+fn forward_signals(pid: u32) {
+    CHILD_PID.store(pid as i32, Ordering::SeqCst);
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        // SAFETY: the handler only calls async-signal-safe functions
+        unsafe {
+            libc::signal(
+                signal,
+                forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// Console events (Ctrl+C) already reach the spawned command on other platforms
+#[cfg(not(unix))]
+fn forward_signals(_pid: u32) {}
+
+/// Print the capabilities summary of a display
+fn print_info(index: usize, display: &rres::Display) {
+    let card = display
+        .card
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let unknown = || "unknown".to_string();
+
+    println!("Display #{index}: {} ({card})", display.connector);
+    println!(
+        "  Current mode:    {}",
+        rres::modes::describe(&display.mode)
+    );
+    if let Some(native) = rres::modes::preferred(&display.modes) {
+        println!("  Native mode:     {}", rres::modes::describe(native));
+    }
+    if let Some(max) = display
+        .modes
+        .iter()
+        .max_by_key(|m| m.size().0 as u32 * m.size().1 as u32)
+    {
+        println!("  Max resolution:  {}x{}", max.size().0, max.size().1);
+    }
+    if let Some(max) = display
+        .modes
+        .iter()
+        .max_by(|a, b| rres::modes::refresh(a).total_cmp(&rres::modes::refresh(b)))
+        .filter(|m| rres::modes::refresh(m) > 0.0)
+    {
+        println!(
+            "  Max refresh:     {:.2} Hz ({}x{})",
+            rres::modes::refresh(max),
+            max.size().0,
+            max.size().1
+        );
+    }
+    println!(
+        "  HDR:             {}",
+        display
+            .hdr_metadata()
+            .map(|m| {
+                let transfers: Vec<&str> =
+                    [(m.pq, "PQ"), (m.hlg, "HLG"), (m.traditional_hdr, "gamma")]
+                        .into_iter()
+                        .filter_map(|(supported, name)| supported.then_some(name))
+                        .collect();
+                match (transfers.is_empty(), m.max_luminance) {
+                    (true, _) => "no".to_string(),
+                    (false, Some(max)) => {
+                        format!("yes ({}), up to {max:.0} cd/m²", transfers.join(", "))
+                    }
+                    (false, None) => format!("yes ({})", transfers.join(", ")),
+                }
+            })
+            .or_else(|| display.hdr().map(|h| yes_no(h).to_string()))
+            .unwrap_or_else(unknown)
+    );
+    if let Some(supported) = display.properties.hdr_output_metadata {
+        println!("  HDR output:      {}", yes_no(supported));
+    }
+    println!(
+        "  Wide gamut:      {}",
+        display
+            .wide_gamut()
+            .map(|wide| {
+                let spaces: Vec<&str> = display
+                    .colorimetry()
+                    .map(|c| {
+                        [
+                            (c.bt2020_rgb || c.bt2020_ycc, "BT.2020"),
+                            (c.dci_p3, "DCI-P3"),
+                            (c.op_rgb, "opRGB"),
+                        ]
+                        .into_iter()
+                        .filter_map(|(supported, name)| supported.then_some(name))
+                        .collect()
+                    })
+                    .unwrap_or_default();
+                match (wide, spaces.is_empty()) {
+                    (_, true) => yes_no(wide).to_string(),
+                    (true, false) => format!("yes ({})", spaces.join(", ")),
+                    (false, false) => format!("no (accepts {})", spaces.join(", ")),
+                }
+            })
+            .unwrap_or_else(unknown)
+    );
+    if let Some(chromaticity) = display.chromaticity() {
+        println!(
+            "  Gamut:           {:.0}% of sRGB's area, covers {:.0}% of sRGB",
+            chromaticity.gamut_area() * 100.0,
+            chromaticity.srgb_coverage() * 100.0
+        );
+    }
+    if let Some(allm) = display.allm() {
+        println!(
+            "  Auto game mode:  {}",
+            if allm { "yes (ALLM)" } else { "no" }
+        );
+    }
+    if let Some(bpc) = display.bits_per_component() {
+        println!("  Bit depth:       {bpc} bpc");
+    }
+    if let Some(bpc) = display.properties.max_bpc {
+        match display.properties.max_bpc_range {
+            Some((min, max)) => println!("  Max bpc:         {bpc} (driver accepts {min}-{max})"),
+            None => println!("  Max bpc:         {bpc}"),
+        }
+    }
+    if let Some(dsc) = display.properties.dsc {
+        println!("  DSC:             {}", yes_no(dsc));
+    }
+    if let Some(orientation) = display.properties.panel_orientation {
+        println!("  Orientation:     {}", orientation.as_str());
+    }
+    if let Some(link) = display.properties.dp_link {
+        let rate = match link.rate_name() {
+            Some(name) => format!("{} Gbps ({name})", f64::from(link.rate) / 1000.0),
+            None => format!("{} Gbps", f64::from(link.rate) / 1000.0),
+        };
+        // The driver picks at most max bpc, and no more than the display takes
+        let bpc = display
+            .properties
+            .max_bpc
+            .into_iter()
+            .chain(display.bits_per_component())
+            .min()
+            .unwrap_or(8);
+        let usage = match link.usage(&display.mode, bpc) {
+            Some(usage) if usage > 1.0 && display.properties.dsc == Some(true) => {
+                format!(
+                    ", the mode needs {:.0}% at {bpc} bpc, compressed",
+                    usage * 100.0
+                )
+            }
+            Some(usage) if usage > 1.0 => {
+                format!(
+                    ", the mode needs {:.0}% at {bpc} bpc, more than the link carries",
+                    usage * 100.0
+                )
+            }
+            Some(usage) => format!(", the mode uses {:.0}% at {bpc} bpc", usage * 100.0),
+            None => String::new(),
+        };
+        println!("  DP link:         {} lanes at {rate}{usage}", link.lanes);
+    }
+    println!(
+        "  VRR:             {}",
+        match (display.vrr_capable, display.vrr_range()) {
+            (Some(true), Some((min, max))) => format!("yes, {min}-{max} Hz"),
+            (Some(v), _) => yes_no(v).to_string(),
+            (None, _) => unknown(),
+        }
+    );
+    println!(
+        "  Physical size:   {}",
+        display
+            .physical_size
+            .map(|(w, h)| {
+                let diagonal = ((w * w + h * h) as f64).sqrt() / 25.4;
+                format!("{w}x{h} mm ({diagonal:.1}\")")
+            })
+            .unwrap_or_else(unknown)
+    );
+    if let Some(tile) = display.tile() {
+        println!(
+            "  Tile:            column {}, row {} of {}x{} ({}x{} each)",
+            tile.column + 1,
+            tile.row + 1,
+            tile.columns,
+            tile.rows,
+            tile.size.0,
+            tile.size.1
+        );
     }
 }
 
-/// Decrease `log::LevelFilter` by one level
-fn decrement_loglevel(level: log::LevelFilter) -> log::LevelFilter {
-    use log::LevelFilter::*;
-    match level {
-        Off | Error => Off,
-        Warn => Error,
-        Info => Warn,
-        Debug => Info,
-        Trace => Debug,
+/// Format a boolean for humans
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
     }
 }
+
+/// Set a mode until Enter is pressed (`rres set`)
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn set_mode(display: &rres::Display, output: &str, mode: &rres::Mode) -> anyhow::Result<()> {
+    let modeset = rres::kms::set_mode(&display.card, output, mode)?;
+    rres::cache::invalidate();
+    println!(
+        "{output}: {}, press Enter to restore",
+        rres::modes::describe(&modeset.mode)
+    );
+    io::stdin().read_line(&mut String::new())?;
+    drop(modeset);
+    rres::cache::invalidate();
+
+    Ok(())
+}
+
+/// Set a mode until Enter is pressed (`rres set`), only supported with DRM
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn set_mode(_display: &rres::Display, _output: &str, _mode: &rres::Mode) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("rres set needs DRM (Linux or FreeBSD)"))
+}
+
+/// Parse a `--gpu-policy` value
+fn parse_gpu_policy(value: &str) -> anyhow::Result<rres::gpu::Policy> {
+    rres::gpu::Policy::try_from(value).map_err(|_| anyhow::anyhow!("invalid GPU policy: {value}"))
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Probe metrics, in Prometheus text format
+//!
+//! Every [`crate::probe`] is counted. Long-running modes (`--rpc`, `--supervise`) can serve
+//! the metrics over HTTP with [`serve`], on a TCP address or a Unix socket.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::{modes, Display};
+
+/// A display of the last successful probe
+struct Inventory {
+    card: String,
+    connector: String,
+    width: u16,
+    height: u16,
+    refresh: f64,
+}
+
+struct State {
+    probes: u64,
+    errors: u64,
+    last_duration: Option<Duration>,
+    displays: Vec<Inventory>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    probes: 0,
+    errors: 0,
+    last_duration: None,
+    displays: vec![],
+});
+
+/// Record a probe
+pub(crate) fn record(duration: Duration, result: &anyhow::Result<Vec<Display>>) {
+    let Ok(mut state) = STATE.lock() else {
+        return;
+    };
+    state.probes += 1;
+    state.last_duration = Some(duration);
+    match result {
+        Ok(displays) => {
+            state.displays = displays
+                .iter()
+                .map(|d| Inventory {
+                    card: d.card.to_string_lossy().to_string(),
+                    connector: d.connector.clone(),
+                    width: d.mode.hdisplay,
+                    height: d.mode.vdisplay,
+                    refresh: modes::refresh(&d.mode),
+                })
+                .collect()
+        }
+        Err(_) => state.errors += 1,
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the metrics in Prometheus text format
+pub fn render() -> String {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut text = String::new();
+
+    let _ = writeln!(text, "# HELP rres_probes_total Display probes.");
+    let _ = writeln!(text, "# TYPE rres_probes_total counter");
+    let _ = writeln!(text, "rres_probes_total {}", state.probes);
+    let _ = writeln!(
+        text,
+        "# HELP rres_probe_errors_total Failed display probes."
+    );
+    let _ = writeln!(text, "# TYPE rres_probe_errors_total counter");
+    let _ = writeln!(text, "rres_probe_errors_total {}", state.errors);
+    if let Some(duration) = state.last_duration {
+        let _ = writeln!(
+            text,
+            "# HELP rres_last_probe_duration_seconds Duration of the last probe."
+        );
+        let _ = writeln!(text, "# TYPE rres_last_probe_duration_seconds gauge");
+        let _ = writeln!(
+            text,
+            "rres_last_probe_duration_seconds {}",
+            duration.as_secs_f64()
+        );
+    }
+    let _ = writeln!(
+        text,
+        "# HELP rres_displays Displays found by the last successful probe."
+    );
+    let _ = writeln!(text, "# TYPE rres_displays gauge");
+    let _ = writeln!(text, "rres_displays {}", state.displays.len());
+    let _ = writeln!(
+        text,
+        "# HELP rres_display_info Current mode of each display, from the last successful probe."
+    );
+    let _ = writeln!(text, "# TYPE rres_display_info gauge");
+    for display in &state.displays {
+        let _ = writeln!(
+            text,
+            "rres_display_info{{card=\"{}\",connector=\"{}\",width=\"{}\",height=\"{}\",refresh=\"{:.2}\"}} 1",
+            escape(&display.card),
+            escape(&display.connector),
+            display.width,
+            display.height,
+            display.refresh
+        );
+    }
+
+    text
+}
+
+/// Answer an HTTP request with the metrics, whatever the path
+fn respond<S: std::io::Read + Write>(stream: S) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    // Skip the request headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+    let body = render();
+    write!(
+        reader.get_mut(),
+        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Serve the metrics in the background, on `host:port` or a Unix socket path
+pub fn serve(address: &str) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    if address.contains('/') {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::UnixListener;
+        // Stale socket of a previous run
+        if std::fs::metadata(address).is_ok_and(|m| m.file_type().is_socket()) {
+            let _ = std::fs::remove_file(address);
+        }
+        let listener = UnixListener::bind(address)
+            .with_context(|| format!("failed to listen on {address}"))?;
+        log::info!("Serving metrics on {address}");
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream) {
+                    log::debug!("metrics request failed: {e}");
+                }
+            }
+        });
+        return Ok(());
+    }
+
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("failed to listen on {address}"))?;
+    log::info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream) {
+                log::debug!("metrics request failed: {e}");
+            }
+        }
+    });
+    Ok(())
+}
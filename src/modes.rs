@@ -0,0 +1,377 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Display modes
+
+use std::fmt;
+
+use crate::json::Value;
+
+/// Positive horizontal sync mode flag
+pub const FLAG_PHSYNC: u32 = 1 << 0;
+/// Negative horizontal sync mode flag
+pub const FLAG_NHSYNC: u32 = 1 << 1;
+/// Positive vertical sync mode flag
+pub const FLAG_PVSYNC: u32 = 1 << 2;
+/// Negative vertical sync mode flag
+pub const FLAG_NVSYNC: u32 = 1 << 3;
+/// Interlaced mode flag
+pub const FLAG_INTERLACE: u32 = 1 << 4;
+/// Doublescan mode flag
+pub const FLAG_DBLSCAN: u32 = 1 << 5;
+/// Preferred (native) mode type
+pub const TYPE_PREFERRED: u32 = 1 << 3;
+
+/// A display mode: resolution, refresh rate and timings
+///
+/// Same fields and flag values as the DRM mode info. Modes that only have a resolution (eg.
+/// forced ones) have every other field set to 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mode {
+    /// Pixel clock, in kHz
+    pub clock: u32,
+    /// Width
+    pub hdisplay: u16,
+    pub hsync_start: u16,
+    pub hsync_end: u16,
+    pub htotal: u16,
+    pub hskew: u16,
+    /// Height
+    pub vdisplay: u16,
+    pub vsync_start: u16,
+    pub vsync_end: u16,
+    pub vtotal: u16,
+    pub vscan: u16,
+    /// Refresh rate in Hz, rounded (0 if unknown)
+    pub vrefresh: u32,
+    /// `FLAG_*` bits
+    pub flags: u32,
+    /// `TYPE_*` bits
+    pub mode_type: u32,
+}
+
+impl Mode {
+    /// Build a mode with only a resolution, without refresh rate nor timings
+    pub fn from_size(size: (u16, u16)) -> Self {
+        Self {
+            hdisplay: size.0,
+            vdisplay: size.1,
+            ..Default::default()
+        }
+    }
+
+    /// Get the resolution of the mode
+    pub fn size(&self) -> (u16, u16) {
+        (self.hdisplay, self.vdisplay)
+    }
+
+    /// Get the mode as seen on a panel mounted sideways, horizontal and vertical swapped
+    ///
+    /// The refresh rate is kept. The timings don't describe a real signal anymore: only the
+    /// unrotated mode can be set.
+    pub fn rotated(&self) -> Self {
+        Self {
+            hdisplay: self.vdisplay,
+            hsync_start: self.vsync_start,
+            hsync_end: self.vsync_end,
+            htotal: self.vtotal,
+            vdisplay: self.hdisplay,
+            vsync_start: self.hsync_start,
+            vsync_end: self.hsync_end,
+            vtotal: self.htotal,
+            ..*self
+        }
+    }
+}
+
+/// A requested mode: a resolution, and optionally a refresh rate (eg. `1920x1080@120`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeSpec {
+    /// Resolution
+    pub size: (u16, u16),
+    /// Refresh rate in Hz, any if `None`
+    pub refresh: Option<f64>,
+}
+
+impl TryFrom<&str> for ModeSpec {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (size, refresh) = match value.split_once('@') {
+            Some((size, refresh)) => {
+                let hz: f64 = refresh
+                    .strip_suffix("Hz")
+                    .unwrap_or(refresh)
+                    .parse()
+                    .ok()
+                    .filter(|hz: &f64| hz.is_finite() && *hz > 0.0)
+                    .ok_or_else(|| anyhow::anyhow!("invalid refresh rate: {refresh}"))?;
+                (size, Some(hz))
+            }
+            None => (value, None),
+        };
+
+        let size = crate::config::parse_res(size)
+            .map_err(|_| anyhow::anyhow!("invalid resolution: {size}"))?;
+        if size.0 == 0 || size.1 == 0 {
+            return Err(anyhow::anyhow!("invalid resolution: {value}"));
+        }
+
+        Ok(Self { size, refresh })
+    }
+}
+
+impl fmt::Display for ModeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.size.0, self.size.1)?;
+        if let Some(refresh) = self.refresh {
+            write!(f, "@{refresh}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the supported mode closest to a requested one
+///
+/// Only modes of the requested resolution match, the closest refresh rate wins. Without a
+/// requested refresh rate, the first matching mode (in the display's order) is used.
+pub fn closest<'a>(modes: &'a [Mode], spec: &ModeSpec) -> Option<&'a Mode> {
+    let mut matching = modes.iter().filter(|m| m.size() == spec.size);
+    match spec.refresh {
+        Some(wanted) => matching.min_by(|a, b| {
+            let a = (refresh(a) - wanted).abs();
+            let b = (refresh(b) - wanted).abs();
+            a.total_cmp(&b)
+        }),
+        None => matching.next(),
+    }
+}
+
+/// Find the mode with the highest refresh rate at a resolution
+pub fn best_refresh(modes: &[Mode], size: (u16, u16)) -> Option<&Mode> {
+    modes
+        .iter()
+        .filter(|m| m.size() == size)
+        .max_by(|a, b| refresh(a).total_cmp(&refresh(b)))
+}
+
+/// Get the exact refresh rate of a mode, in Hz
+///
+/// Computed from the timings, as `vrefresh` is rounded (eg. 59.94Hz is reported as 60Hz).
+/// Modes without timings (eg. forced resolutions) fall back to `vrefresh`.
+pub fn refresh(mode: &Mode) -> f64 {
+    if mode.clock == 0 || mode.htotal == 0 || mode.vtotal == 0 {
+        return mode.vrefresh as f64;
+    }
+
+    let mut refresh = mode.clock as f64 * 1000.0 / (mode.htotal as f64 * mode.vtotal as f64);
+    if mode.flags & FLAG_INTERLACE != 0 {
+        refresh *= 2.0;
+    }
+    if mode.flags & FLAG_DBLSCAN != 0 {
+        refresh /= 2.0;
+    }
+    if mode.vscan > 1 {
+        refresh /= mode.vscan as f64;
+    }
+
+    refresh
+}
+
+/// Whether the display reports the mode as its preferred (native) mode
+pub fn is_preferred(mode: &Mode) -> bool {
+    mode.mode_type & TYPE_PREFERRED != 0
+}
+
+/// Whether a mode is progressive (neither interlaced nor doublescan)
+///
+/// Interlaced and doublescan modes are useless for games, they are skipped by default.
+pub fn is_progressive(mode: &Mode) -> bool {
+    mode.flags & (FLAG_INTERLACE | FLAG_DBLSCAN) == 0
+}
+
+/// Get the supported resolutions closest to `size`, closest first
+pub fn closest_sizes(modes: &[Mode], size: (u16, u16), count: usize) -> Vec<(u16, u16)> {
+    let mut sizes: Vec<(u16, u16)> = modes.iter().map(|m| m.size()).collect();
+    sizes.sort_by_key(|s| {
+        let distance = (s.0 as i32 - size.0 as i32).abs() + (s.1 as i32 - size.1 as i32).abs();
+        (distance, std::cmp::Reverse(*s))
+    });
+    sizes.dedup();
+    sizes.truncate(count);
+    sizes
+}
+
+/// Get the preferred (native) mode out of a display's modes
+///
+/// Falls back to the first mode, displays list their native mode first.
+pub fn preferred(modes: &[Mode]) -> Option<&Mode> {
+    modes.iter().find(|m| is_preferred(m)).or(modes.first())
+}
+
+/// Short description of a mode (eg. `2560x1440@164.96`)
+pub fn describe(mode: &Mode) -> String {
+    let (width, height) = mode.size();
+    let refresh = refresh(mode);
+    if refresh > 0.0 {
+        format!("{width}x{height}@{refresh:.2}")
+    } else {
+        format!("{width}x{height}")
+    }
+}
+
+/// Format a mode as an xorg.conf `Modeline` named `name`
+pub fn modeline(mode: &Mode, name: &str) -> String {
+    let mut modeline = format!(
+        "Modeline \"{name}\" {:.2} {} {} {} {} {} {} {} {}",
+        mode.clock as f64 / 1000.0,
+        mode.hdisplay,
+        mode.hsync_start,
+        mode.hsync_end,
+        mode.htotal,
+        mode.vdisplay,
+        mode.vsync_start,
+        mode.vsync_end,
+        mode.vtotal
+    );
+    for (flag, name) in [
+        (FLAG_PHSYNC, "+hsync"),
+        (FLAG_NHSYNC, "-hsync"),
+        (FLAG_PVSYNC, "+vsync"),
+        (FLAG_NVSYNC, "-vsync"),
+        (FLAG_INTERLACE, "Interlace"),
+        (FLAG_DBLSCAN, "DoubleScan"),
+    ] {
+        if mode.flags & flag != 0 {
+            modeline.push(' ');
+            modeline.push_str(name);
+        }
+    }
+    modeline
+}
+
+/// Get the notable flags of a mode, for humans
+pub fn flags(mode: &Mode) -> Vec<&'static str> {
+    let mut flags = vec![];
+    if is_preferred(mode) {
+        flags.push("preferred");
+    }
+    if mode.flags & FLAG_INTERLACE != 0 {
+        flags.push("interlace");
+    }
+    if mode.flags & FLAG_DBLSCAN != 0 {
+        flags.push("doublescan");
+    }
+    flags
+}
+
+/// Serialize a mode as its fields, in declaration order
+pub(crate) fn to_json(mode: &Mode) -> Value {
+    vec![
+        Value::from(mode.clock),
+        mode.hdisplay.into(),
+        mode.hsync_start.into(),
+        mode.hsync_end.into(),
+        mode.htotal.into(),
+        mode.hskew.into(),
+        mode.vdisplay.into(),
+        mode.vsync_start.into(),
+        mode.vsync_end.into(),
+        mode.vtotal.into(),
+        mode.vscan.into(),
+        mode.vrefresh.into(),
+        mode.flags.into(),
+        mode.mode_type.into(),
+    ]
+    .into()
+}
+
+/// Parse a mode serialized by [`to_json`]
+pub(crate) fn from_json(value: &Value) -> Option<Mode> {
+    let Value::Array(fields) = value else {
+        return None;
+    };
+    let fields: Vec<u64> = fields.iter().map(Value::as_u64).collect::<Option<_>>()?;
+    let [clock, hdisplay, hsync_start, hsync_end, htotal, hskew, vdisplay, vsync_start, vsync_end, vtotal, vscan, vrefresh, flags, mode_type] =
+        fields[..]
+    else {
+        return None;
+    };
+    Some(Mode {
+        clock: clock.try_into().ok()?,
+        hdisplay: hdisplay.try_into().ok()?,
+        hsync_start: hsync_start.try_into().ok()?,
+        hsync_end: hsync_end.try_into().ok()?,
+        htotal: htotal.try_into().ok()?,
+        hskew: hskew.try_into().ok()?,
+        vdisplay: vdisplay.try_into().ok()?,
+        vsync_start: vsync_start.try_into().ok()?,
+        vsync_end: vsync_end.try_into().ok()?,
+        vtotal: vtotal.try_into().ok()?,
+        vscan: vscan.try_into().ok()?,
+        vrefresh: vrefresh.try_into().ok()?,
+        flags: flags.try_into().ok()?,
+        mode_type: mode_type.try_into().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_spec() {
+        let spec = |value| ModeSpec::try_from(value).unwrap();
+        assert_eq!(
+            spec("1920x1080"),
+            ModeSpec {
+                size: (1920, 1080),
+                refresh: None
+            }
+        );
+        assert_eq!(spec("1920x1080@120").refresh, Some(120.0));
+        assert_eq!(spec("1920x1080@59.94").refresh, Some(59.94));
+        assert_eq!(spec("1920x1080@144Hz").refresh, Some(144.0));
+        assert_eq!(spec("2560x1440@59.951Hz").to_string(), "2560x1440@59.951");
+        assert_eq!(spec("800x1280").to_string(), "800x1280");
+
+        for invalid in [
+            "",
+            "1920",
+            "1920x",
+            "x1080",
+            "1920X1080",
+            "1920x1080x",
+            "0x1080",
+            "1920x0",
+            "65536x1080",
+            "1920x1080@",
+            "1920x1080@Hz",
+            "1920x1080@60HzHz",
+            "1920x1080@60hz",
+            "1920x1080@0",
+            "1920x1080@-60",
+            "1920x1080@inf",
+            "1920x1080@NaN",
+            "1920x1080@60@60",
+            "@60",
+            " 1920x1080",
+        ] {
+            assert!(ModeSpec::try_from(invalid).is_err(), "{invalid}");
+        }
+    }
+}
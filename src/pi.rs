@@ -0,0 +1,81 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Raspberry Pi quirks
+//!
+//! With the legacy firmware or the vc4 "fake KMS" driver, the firmware owns the display:
+//! connectors can be reported disconnected and the current mode is often missing. The
+//! firmware's framebuffer follows the mode it set, so its size is used instead.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use crate::{sysfs, Display, Mode};
+
+/// Framebuffer set up by the firmware
+const FRAMEBUFFER: &str = "/sys/class/graphics/fb0";
+
+/// Detect the Raspberry Pi model from the device tree, `None` if not running on a Pi
+pub fn model() -> Option<String> {
+    let model = fs::read_to_string("/proc/device-tree/model").ok()?;
+    let model = model.trim_end_matches('\0');
+    model.starts_with("Raspberry Pi").then(|| model.to_string())
+}
+
+/// Parse a size separated by `separator` (eg. `1920,1080`), ignoring trailing fields
+fn parse_size(value: &str, separator: char) -> Option<(u16, u16)> {
+    let mut fields = value.trim().split(separator);
+    let size = (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// Get the size of the firmware's display, from the framebuffer or `vcgencmd`
+pub fn framebuffer_size() -> Option<(u16, u16)> {
+    if let Some(size) =
+        sysfs::read_attr(format!("{FRAMEBUFFER}/virtual_size")).and_then(|s| parse_size(&s, ','))
+    {
+        return Some(size);
+    }
+
+    // eg. "1920 1080 24"
+    let output = process::Command::new("vcgencmd")
+        .arg("get_lcd_info")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_size(&String::from_utf8_lossy(&output.stdout), ' ')
+}
+
+/// Get the display set up by the firmware, when DRM can't report it
+pub fn firmware_display() -> Option<Display> {
+    let size = framebuffer_size()?;
+    log::debug!("Found display (firmware): fb0, {}x{}", size.0, size.1);
+    let mode = Mode::from_size(size);
+    Some(Display {
+        card: PathBuf::from("/dev/fb0"),
+        connector: "fb0".to_string(),
+        mode,
+        modes: vec![mode],
+        physical_size: None,
+        vrr_capable: None,
+        edid: None,
+        properties: Default::default(),
+    })
+}
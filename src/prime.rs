@@ -0,0 +1,237 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! PRIME (hybrid graphics) awareness
+//!
+//! On PRIME laptops the displays are usually wired to the iGPU, while games are rendered on
+//! the dGPU. The render offload variables only select the rendering GPU, displays are always
+//! probed on the GPU that owns them.
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::sysfs;
+
+/// Render offload requested through the environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderOffload {
+    /// `DRI_PRIME=<n>`: 0 is the default GPU, n is the n-th non-default GPU
+    Index(usize),
+    /// `DRI_PRIME=pci-0000_01_00_0`
+    Pci(String),
+    /// `DRI_PRIME=<vendor_id>:<device_id>`
+    PciId(u16, u16),
+    /// `__NV_PRIME_RENDER_OFFLOAD=1`
+    Nvidia,
+}
+
+impl fmt::Display for RenderOffload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Index(i) => write!(f, "DRI_PRIME={i}"),
+            Self::Pci(slot) => write!(f, "DRI_PRIME=pci-{}", slot.replace([':', '.'], "_")),
+            Self::PciId(vendor, device) => write!(f, "DRI_PRIME={vendor:04x}:{device:04x}"),
+            Self::Nvidia => write!(f, "__NV_PRIME_RENDER_OFFLOAD=1"),
+        }
+    }
+}
+
+impl RenderOffload {
+    /// Parse a `DRI_PRIME` value
+    pub fn parse_dri_prime(value: &str) -> Option<Self> {
+        if let Some(tag) = value.strip_prefix("pci-") {
+            // pci-0000_01_00_0 -> 0000:01:00.0
+            let parts: Vec<&str> = tag.split('_').collect();
+            let [domain, bus, device, function] = parts[..] else {
+                return None;
+            };
+            if !parts
+                .iter()
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                return None;
+            }
+            return Some(Self::Pci(format!("{domain}:{bus}:{device}.{function}")));
+        }
+        if let Some((vendor, device)) = value.split_once(':') {
+            return Some(Self::PciId(
+                u16::from_str_radix(vendor, 16).ok()?,
+                u16::from_str_radix(device, 16).ok()?,
+            ));
+        }
+        value.parse().ok().map(Self::Index)
+    }
+}
+
+/// Get the render offload requested in the environment, if any
+pub fn render_offload() -> Option<RenderOffload> {
+    if env::var("__NV_PRIME_RENDER_OFFLOAD").as_deref() == Ok("1") {
+        return Some(RenderOffload::Nvidia);
+    }
+    match env::var("DRI_PRIME") {
+        Ok(value) => {
+            let offload = RenderOffload::parse_dri_prime(&value);
+            if offload.is_none() {
+                log::warn!("ignoring invalid DRI_PRIME value: {value}");
+            }
+            offload
+        }
+        Err(_) => None,
+    }
+}
+
+/// What the render offload variables match cards on
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct CardInfo {
+    boot_vga: bool,
+    pci_slot: Option<String>,
+    pci_id: Option<(u16, u16)>,
+    driver: Option<String>,
+}
+
+impl CardInfo {
+    fn read(card: &Path) -> Self {
+        Self {
+            boot_vga: sysfs::is_boot_vga(card),
+            pci_slot: sysfs::pci_slot(card),
+            pci_id: sysfs::pci_id(card),
+            driver: sysfs::driver(card),
+        }
+    }
+}
+
+/// Get the card that will render, out of the given cards
+///
+/// Without render offload this is the default (boot VGA) GPU.
+pub fn render_card(cards: &[PathBuf], offload: Option<&RenderOffload>) -> Option<PathBuf> {
+    let infos: Vec<CardInfo> = cards.iter().map(|c| CardInfo::read(c)).collect();
+    select(&infos, offload).map(|i| cards[i].clone())
+}
+
+/// Index of the rendering card, out of the given cards
+fn select(cards: &[CardInfo], offload: Option<&RenderOffload>) -> Option<usize> {
+    let default = cards
+        .iter()
+        .position(|c| c.boot_vga)
+        .or((!cards.is_empty()).then_some(0));
+
+    match offload {
+        None | Some(RenderOffload::Index(0)) => default,
+        Some(RenderOffload::Index(n)) => {
+            (0..cards.len()).filter(|i| Some(*i) != default).nth(n - 1)
+        }
+        Some(RenderOffload::Pci(slot)) => {
+            cards.iter().position(|c| c.pci_slot.as_ref() == Some(slot))
+        }
+        Some(RenderOffload::PciId(vendor, device)) => cards
+            .iter()
+            .position(|c| c.pci_id == Some((*vendor, *device))),
+        Some(RenderOffload::Nvidia) => cards
+            .iter()
+            .position(|c| c.driver.as_deref() == Some("nvidia")),
+    }
+}
+
+/// Short human readable description of a card (eg. `card1 (amdgpu, 0000:03:00.0)`)
+pub fn describe_card(card: &Path) -> String {
+    let name = card
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let driver = sysfs::driver(card).unwrap_or_else(|| "unknown".to_string());
+    match sysfs::pci_slot(card) {
+        Some(slot) => format!("{name} ({driver}, {slot})"),
+        None => format!("{name} ({driver})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dri_prime() {
+        let parse = RenderOffload::parse_dri_prime;
+        assert_eq!(parse("0"), Some(RenderOffload::Index(0)));
+        assert_eq!(parse("1"), Some(RenderOffload::Index(1)));
+        assert_eq!(
+            parse("pci-0000_01_00_0"),
+            Some(RenderOffload::Pci("0000:01:00.0".to_string()))
+        );
+        assert_eq!(
+            parse("10de:25a0"),
+            Some(RenderOffload::PciId(0x10de, 0x25a0))
+        );
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse("nvidia"), None);
+        assert_eq!(parse("pci-"), None);
+        assert_eq!(parse("pci-0000_01_00"), None);
+        assert_eq!(parse("pci-0000_01__0"), None);
+        assert_eq!(parse("pci-0000_0g_00_0"), None);
+        assert_eq!(parse("10de:"), None);
+        assert_eq!(parse("10de:25a0:1"), None);
+
+        for value in ["2", "pci-0000_c1_00_0", "1002:73bf"] {
+            let offload = parse(value).unwrap();
+            assert_eq!(offload.to_string(), format!("DRI_PRIME={value}"));
+        }
+    }
+
+    #[test]
+    fn select() {
+        let card = |boot_vga, slot: &str, id, driver: &str| CardInfo {
+            boot_vga,
+            pci_slot: Some(slot.to_string()),
+            pci_id: Some(id),
+            driver: Some(driver.to_string()),
+        };
+        // The dGPU comes first, the iGPU is the boot VGA
+        let cards = [
+            card(false, "0000:01:00.0", (0x10de, 0x25a0), "nvidia"),
+            card(true, "0000:00:02.0", (0x8086, 0x9a49), "i915"),
+            card(false, "0000:02:00.0", (0x1002, 0x73bf), "amdgpu"),
+        ];
+        let select = |offload: Option<RenderOffload>| super::select(&cards, offload.as_ref());
+
+        assert_eq!(select(None), Some(1));
+        assert_eq!(select(Some(RenderOffload::Index(0))), Some(1));
+        assert_eq!(select(Some(RenderOffload::Index(1))), Some(0));
+        assert_eq!(select(Some(RenderOffload::Index(2))), Some(2));
+        assert_eq!(select(Some(RenderOffload::Index(3))), None);
+        assert_eq!(
+            select(Some(RenderOffload::Pci("0000:02:00.0".to_string()))),
+            Some(2)
+        );
+        assert_eq!(
+            select(Some(RenderOffload::Pci("0000:03:00.0".to_string()))),
+            None
+        );
+        assert_eq!(select(Some(RenderOffload::PciId(0x8086, 0x9a49))), Some(1));
+        assert_eq!(select(Some(RenderOffload::PciId(0x8086, 0x46a6))), None);
+        assert_eq!(select(Some(RenderOffload::Nvidia)), Some(0));
+
+        // Without a boot VGA, the first card is the default
+        let unknown = [CardInfo::default(), CardInfo::default()];
+        assert_eq!(super::select(&unknown, None), Some(0));
+        assert_eq!(
+            super::select(&unknown, Some(&RenderOffload::Index(1))),
+            Some(1)
+        );
+        assert_eq!(super::select(&[], None), None);
+    }
+}
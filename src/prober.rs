@@ -0,0 +1,319 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Persistent handle for repeated queries
+//!
+//! [`crate::probe`] opens every card and fetches its connectors on each call. Daemons and
+//! launchers asking often can keep a [`Prober`] instead: the DRM cards stay open between
+//! calls, only the connectors are read again.
+//!
+//! ```no_run
+//! let mut prober = rres::Prober::new(rres::ProbeOptions::default())?;
+//! for _ in 0..3 {
+//!     println!("{:?}", prober.probe()?.first().map(|d| d.mode.size()));
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+use crate::{Display, ProbeOptions};
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+use std::path::{Path, PathBuf};
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+use crate::backend::drm::OpenCard;
+
+/// Open a card
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+type Open = Box<dyn Fn(&Path) -> anyhow::Result<OpenCard> + Send>;
+
+/// Display detection keeping the cards open between calls
+///
+/// Without DRM (other platforms, containers, virtual GPUs deferring to the compositor), every
+/// call is a regular [`crate::probe`]. A card that disappears (eg. an unplugged eGPU) is
+/// closed, and opened again once it's back, unless it's runtime suspended: it would be woken
+/// up.
+pub struct Prober {
+    options: ProbeOptions,
+    /// The cards, `None` while closed
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    cards: Vec<(PathBuf, Option<OpenCard>)>,
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    open: Open,
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    is_suspended: fn(&Path) -> bool,
+}
+
+impl Prober {
+    /// Open the cards selected by the options
+    pub fn new(options: ProbeOptions) -> anyhow::Result<Self> {
+        // The cards are always read, the cache would be stale
+        let options = ProbeOptions {
+            cache: false,
+            ..options
+        };
+        #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+        {
+            let paths = match Self::card_paths(&options) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    log::debug!("{e:#}, probing on every call");
+                    vec![]
+                }
+            };
+            Ok(Self::with_cards(
+                options,
+                &paths,
+                Box::new(OpenCard::open),
+                is_suspended,
+            ))
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+        Ok(Self { options })
+    }
+
+    /// Open the cards of `paths` with `open`, probing on every call if one can't be opened
+    /// or they're all virtual
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    fn with_cards(
+        options: ProbeOptions,
+        paths: &[PathBuf],
+        open: Open,
+        is_suspended: fn(&Path) -> bool,
+    ) -> Self {
+        let cards = match paths
+            .iter()
+            .map(|path| Ok((path.clone(), Some(open(path)?))))
+            .collect::<anyhow::Result<Vec<_>>>()
+        {
+            Ok(cards)
+                if !options.prefer_drm
+                    && cards
+                        .iter()
+                        .all(|(_, card)| card.as_ref().is_some_and(OpenCard::is_virtual)) =>
+            {
+                log::debug!("only virtual GPUs found, probing on every call");
+                vec![]
+            }
+            Ok(cards) => cards,
+            Err(e) => {
+                log::debug!("{e:#}, probing on every call");
+                vec![]
+            }
+        };
+        Self {
+            options,
+            cards,
+            open,
+            is_suspended,
+        }
+    }
+
+    /// Get the cards to open, failing if the DRM backend can't be used alone
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    fn card_paths(options: &ProbeOptions) -> anyhow::Result<Vec<PathBuf>> {
+        if options.via_dbus || options.via_portal || std::env::var_os("RRES_BACKEND").is_some() {
+            return Err(anyhow::anyhow!("not using DRM"));
+        }
+        let mut paths = crate::get_cards(options.card.clone())?;
+        options.gpu_policy.sort(&mut paths);
+        Ok(paths)
+    }
+
+    /// The options used for every probe
+    pub fn options(&self) -> &ProbeOptions {
+        &self.options
+    }
+
+    /// Detect every connected display, like [`crate::probe`]
+    pub fn probe(&mut self) -> anyhow::Result<Vec<Display>> {
+        #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+        if !self.cards.is_empty() {
+            let start = std::time::Instant::now();
+            let result = self.probe_cards();
+            crate::metrics::record(start.elapsed(), &result);
+            return result;
+        }
+        crate::probe(&self.options)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    fn probe_cards(&mut self) -> anyhow::Result<Vec<Display>> {
+        let mut displays = vec![];
+        for (path, card) in &mut self.cards {
+            let remaining = self.options.limit.map(|l| l.saturating_sub(displays.len()));
+            if remaining == Some(0) {
+                break;
+            }
+            if card.is_none() {
+                if (self.is_suspended)(path) {
+                    log::debug!("Not reopening {}: runtime suspended", path.display());
+                    continue;
+                }
+                match (self.open)(path) {
+                    Ok(opened) => {
+                        log::info!("Reopened {}", path.display());
+                        *card = Some(opened);
+                    }
+                    Err(e) => {
+                        log::debug!("{e:#}, skipping {}", path.display());
+                        continue;
+                    }
+                }
+            }
+            let Some(open) = card else { continue };
+            match open.displays(remaining, self.options.edid) {
+                Ok(card_displays) => displays.extend(card_displays),
+                Err(e) => {
+                    log::warn!("{e:#}, closing {} until it's back", path.display());
+                    *card = None;
+                }
+            }
+        }
+        if displays.is_empty() {
+            displays = crate::backend::fallback(&self.options, Ok(displays))?;
+        }
+        crate::finish(&self.options, &mut displays);
+        Ok(displays)
+    }
+}
+
+/// Whether a card is runtime suspended, reopening it would wake it up
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn is_suspended(card: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    return crate::sysfs::is_runtime_suspended(card);
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = card;
+        false
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "freebsd")))]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::backend::drm::tests::{mode, FakeCard};
+
+    /// A laptop (`card0`, eDP-1) with an eGPU (`card1`, DP-1) unplugged while `unplugged`
+    /// is set, counting the cards opened
+    struct Laptop {
+        opened: Arc<AtomicUsize>,
+        unplugged: Arc<AtomicBool>,
+    }
+
+    impl Laptop {
+        fn new() -> Self {
+            Self {
+                opened: Arc::default(),
+                unplugged: Arc::default(),
+            }
+        }
+
+        fn prober(&self, is_suspended: fn(&Path) -> bool) -> Prober {
+            let (opened, unplugged) = (self.opened.clone(), self.unplugged.clone());
+            let open: Open = Box::new(move |path: &Path| {
+                let egpu = path.ends_with("card1");
+                if egpu && unplugged.load(Ordering::Relaxed) {
+                    return Err(anyhow::anyhow!("failed to open {}", path.display()));
+                }
+                opened.fetch_add(1, Ordering::Relaxed);
+                let mut card = FakeCard::default();
+                let current = mode((1920, 1080), 60);
+                card.connect(
+                    1,
+                    if egpu { "DP-1" } else { "eDP-1" },
+                    &[current],
+                    Some(current),
+                );
+                if egpu {
+                    card.gone = unplugged.clone();
+                }
+                OpenCard::new(path, Box::new(card), "amdgpu".to_string())
+            });
+            let paths = [
+                PathBuf::from("/nonexistent/card0"),
+                PathBuf::from("/nonexistent/card1"),
+            ];
+            Prober::with_cards(ProbeOptions::default(), &paths, open, is_suspended)
+        }
+
+        fn set_unplugged(&self, unplugged: bool) {
+            self.unplugged.store(unplugged, Ordering::Relaxed);
+        }
+
+        fn opened(&self) -> usize {
+            self.opened.load(Ordering::Relaxed)
+        }
+    }
+
+    fn connectors(prober: &mut Prober) -> Vec<String> {
+        prober
+            .probe()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.connector)
+            .collect()
+    }
+
+    #[test]
+    fn cards_stay_open() {
+        let laptop = Laptop::new();
+        let mut prober = laptop.prober(|_| false);
+        for _ in 0..3 {
+            assert_eq!(connectors(&mut prober), ["eDP-1", "DP-1"]);
+        }
+        assert_eq!(laptop.opened(), 2);
+    }
+
+    #[test]
+    fn unplugged_card_is_reopened() {
+        let laptop = Laptop::new();
+        let mut prober = laptop.prober(|_| false);
+        assert_eq!(connectors(&mut prober), ["eDP-1", "DP-1"]);
+
+        // Closed, and not found again while unplugged
+        laptop.set_unplugged(true);
+        assert_eq!(connectors(&mut prober), ["eDP-1"]);
+        assert!(prober.cards[1].1.is_none());
+        assert_eq!(connectors(&mut prober), ["eDP-1"]);
+        assert_eq!(laptop.opened(), 2);
+
+        // Plugged back
+        laptop.set_unplugged(false);
+        assert_eq!(connectors(&mut prober), ["eDP-1", "DP-1"]);
+        assert_eq!(laptop.opened(), 3);
+        assert_eq!(connectors(&mut prober), ["eDP-1", "DP-1"]);
+        assert_eq!(laptop.opened(), 3);
+    }
+
+    #[test]
+    fn suspended_card_is_not_reopened() {
+        let laptop = Laptop::new();
+        let mut prober = laptop.prober(|path| path.ends_with("card1"));
+        assert_eq!(connectors(&mut prober), ["eDP-1", "DP-1"]);
+
+        laptop.set_unplugged(true);
+        assert_eq!(connectors(&mut prober), ["eDP-1"]);
+        laptop.set_unplugged(false);
+        assert_eq!(connectors(&mut prober), ["eDP-1"]);
+        assert_eq!(laptop.opened(), 2);
+    }
+}
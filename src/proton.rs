@@ -0,0 +1,129 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Proton integration
+
+use std::env;
+use std::path;
+
+use crate::fsr::Fsr;
+
+/// Whether the command runs a Proton game
+///
+/// Steam sets `STEAM_COMPAT_DATA_PATH` for every Proton launch; the command is also checked
+/// for a `proton` script, for launches outside of Steam.
+pub fn detect(command: &[String]) -> bool {
+    if let Some(compat_data) = env::var_os("STEAM_COMPAT_DATA_PATH") {
+        log::info!("Proton prefix: {}", path::Path::new(&compat_data).display());
+        if let Some(client) = env::var_os("STEAM_COMPAT_CLIENT_INSTALL_PATH") {
+            log::debug!("Steam install: {}", path::Path::new(&client).display());
+        }
+        return true;
+    }
+
+    command
+        .iter()
+        .any(|arg| path::Path::new(arg).file_name().map(|n| n == "proton") == Some(true))
+}
+
+/// Get the environment of a Proton game, for a gamescope mode (native, a FSR preset or a
+/// percentage, see [`crate::gamescope`])
+///
+/// In gamescope, the game sees the render resolution and gamescope upscales: Proton's
+/// fullscreen hack upscaling is disabled, so that the game isn't scaled twice. Otherwise the
+/// fullscreen hack upscales the game from the render resolution (`WINE_FULLSCREEN_FSR_MODE`
+/// and `WINE_FULLSCREEN_FSR_CUSTOM_MODE` are Proton-GE's).
+pub fn env(
+    res: (u16, u16),
+    fsr_mode: &str,
+    gamescope: bool,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let render = crate::render_res(res, fsr_mode)?;
+    let var = |key: &str, value: String| (key.to_string(), value);
+    if gamescope || render == res {
+        return Ok(vec![var("WINE_FULLSCREEN_FSR", "0".to_string())]);
+    }
+
+    let mut env = vec![var("WINE_FULLSCREEN_FSR", "1".to_string())];
+    if Fsr::try_from(fsr_mode).is_ok() {
+        env.push(var("WINE_FULLSCREEN_FSR_MODE", fsr_mode.to_lowercase()));
+    }
+    env.push(var(
+        "WINE_FULLSCREEN_FSR_CUSTOM_MODE",
+        format!("{}x{}", render.0, render.1),
+    ));
+    Ok(env)
+}
+
+/// Get the environment of `command` if it runs a Proton game (see [`env`]), logging it
+pub fn setup(
+    command: &[String],
+    res: (u16, u16),
+    fsr_mode: &str,
+    gamescope: bool,
+) -> anyhow::Result<Vec<(String, String)>> {
+    if !detect(command) {
+        return Ok(vec![]);
+    }
+    let env = env(res, fsr_mode, gamescope)?;
+    for (key, value) in &env {
+        log::info!("Proton game, setting {key}={value}");
+    }
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    fn vars(env: Vec<(String, String)>) -> Vec<String> {
+        env.into_iter().map(|(k, v)| format!("{k}={v}")).collect()
+    }
+
+    #[test]
+    fn fullscreen_hack() {
+        let env = |mode, gamescope| vars(super::env((2560, 1440), mode, gamescope).unwrap());
+        assert_eq!(
+            env("quality", false),
+            [
+                "WINE_FULLSCREEN_FSR=1",
+                "WINE_FULLSCREEN_FSR_MODE=quality",
+                "WINE_FULLSCREEN_FSR_CUSTOM_MODE=1706x960",
+            ]
+        );
+        assert_eq!(
+            env("Performance", false),
+            [
+                "WINE_FULLSCREEN_FSR=1",
+                "WINE_FULLSCREEN_FSR_MODE=performance",
+                "WINE_FULLSCREEN_FSR_CUSTOM_MODE=1280x720",
+            ]
+        );
+        // Render scales have no preset
+        assert_eq!(
+            env("50%", false),
+            [
+                "WINE_FULLSCREEN_FSR=1",
+                "WINE_FULLSCREEN_FSR_CUSTOM_MODE=1280x720",
+            ]
+        );
+        // Nothing to upscale
+        assert_eq!(env("native", false), ["WINE_FULLSCREEN_FSR=0"]);
+        assert_eq!(env("", false), ["WINE_FULLSCREEN_FSR=0"]);
+        // gamescope upscales
+        assert_eq!(env("quality", true), ["WINE_FULLSCREEN_FSR=0"]);
+        assert!(super::env((2560, 1440), "best", false).is_err());
+    }
+}
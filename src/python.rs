@@ -0,0 +1,140 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Python module (`python` feature)
+//!
+//! Build it with `cargo rustc --release --lib --features python --crate-type cdylib`, then
+//! copy `target/release/librres.so` as `rres.so` next to the script importing it.
+//! Failures raise `RuntimeError`.
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+use crate::{modes, ProbeOptions};
+
+/// Convert an rres error to a Python exception
+fn error(error: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{error:#}"))
+}
+
+/// A connected display
+#[pyclass(name = "Display", get_all, frozen)]
+struct PyDisplay {
+    /// Card the display is connected to (eg. `/dev/dri/card0`)
+    card: String,
+    /// Connector name (eg. `DP-1`)
+    connector: String,
+    /// Current resolution
+    width: u16,
+    height: u16,
+    /// Current refresh rate in Hz, 0 if unknown
+    refresh: f64,
+    /// Whether the display is built into the device (laptop or handheld panel)
+    internal: bool,
+    /// Physical size in millimeters, if known
+    physical_size: Option<(u32, u32)>,
+    /// Whether the display supports variable refresh rate, if known
+    vrr_capable: Option<bool>,
+    /// Min and max variable refresh rate in Hz, if known
+    vrr_range: Option<(u16, u16)>,
+    /// Whether the display supports HDR, if known
+    hdr: Option<bool>,
+    /// Desired content max luminance in cd/m², if known
+    max_luminance: Option<f32>,
+    /// Whether the display has a wide color gamut, if known
+    wide_gamut: Option<bool>,
+    /// Part of the sRGB gamut covered by the display, from 0 to 1, if known
+    srgb_coverage: Option<f32>,
+    /// Maximum bits per color component, if known
+    bits_per_component: Option<u8>,
+    /// Whether the display supports Auto Low Latency Mode, if known
+    allm: Option<bool>,
+}
+
+#[pymethods]
+impl PyDisplay {
+    fn __repr__(&self) -> String {
+        format!(
+            "Display(connector={:?}, width={}, height={}, refresh={:.2})",
+            self.connector, self.width, self.height, self.refresh
+        )
+    }
+}
+
+/// List every connected display, of every card or of `card` (eg. `card0`)
+#[pyfunction]
+#[pyo3(signature = (card=None))]
+fn displays(card: Option<String>) -> PyResult<Vec<PyDisplay>> {
+    let displays = crate::probe(&ProbeOptions {
+        card,
+        edid: true,
+        ..Default::default()
+    })
+    .map_err(error)?;
+    Ok(displays
+        .into_iter()
+        .map(|d| PyDisplay {
+            card: d.card.to_string_lossy().to_string(),
+            width: d.mode.hdisplay,
+            height: d.mode.vdisplay,
+            refresh: modes::refresh(&d.mode),
+            internal: d.is_internal(),
+            physical_size: d.physical_size,
+            vrr_capable: d.vrr_capable,
+            vrr_range: d.vrr_range(),
+            hdr: d.hdr(),
+            max_luminance: d.max_luminance(),
+            wide_gamut: d.wide_gamut(),
+            srgb_coverage: d.srgb_coverage(),
+            bits_per_component: d.bits_per_component(),
+            allm: d.allm(),
+            connector: d.connector,
+        })
+        .collect())
+}
+
+/// Get the resolution of the first display, as `(width, height)`
+#[pyfunction]
+#[pyo3(signature = (card=None))]
+fn get_res(card: Option<String>) -> PyResult<(u16, u16)> {
+    crate::get_res_card(card).map_err(error)
+}
+
+/// Get the render resolution of a gamescope mode (`native` or a FSR preset)
+#[pyfunction]
+fn render_res(width: u16, height: u16, fsr_mode: &str) -> PyResult<(u16, u16)> {
+    crate::render_res((width, height), fsr_mode).map_err(error)
+}
+
+/// Build the gamescope command for a resolution and a gamescope mode
+#[pyfunction]
+#[pyo3(signature = (width, height, fsr_mode="native"))]
+fn gamescope(width: u16, height: u16, fsr_mode: &str) -> PyResult<Vec<String>> {
+    crate::gamescope((width, height), fsr_mode).map_err(error)
+}
+
+/// Display resolutions and FSR math
+#[pymodule]
+#[pyo3(name = "rres")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDisplay>()?;
+    m.add_function(wrap_pyfunction!(displays, m)?)?;
+    m.add_function(wrap_pyfunction!(get_res, m)?)?;
+    m.add_function(wrap_pyfunction!(render_res, m)?)?;
+    m.add_function(wrap_pyfunction!(gamescope, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,438 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! JSON-RPC 2.0 server (`rres --rpc`)
+//!
+//! One request per line, one response per line. Methods:
+//!
+//! - `get_displays` (`card`): every display, like `rres -m`
+//! - `get_res` (`card`, `display`): resolution of the selected display, `{"width", "height"}`
+//! - `build_gamescope` (`fsr_mode`, `width`, `height`): the gamescope command, for the given
+//!   resolution or the detected one
+//!
+//! - `get_schema`: the JSON schema of the results (`rres --schema`)
+//!
+//! Parameters are optional, by name. The command line options are the defaults.
+//!
+//! Displays and resolutions carry a `schema_version`, see [`SCHEMA_VERSION`].
+
+use std::io::{BufRead, Write};
+
+use crate::json::{self, Value};
+use crate::{modes, Display, ProbeOptions};
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// rres failed (no display, invalid FSR mode...)
+const SERVER_ERROR: i32 = -32000;
+
+/// Version of the result objects
+///
+/// Adding fields keeps the version, removing, renaming or changing the type of a field bumps
+/// it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A failed request
+struct Error {
+    code: i32,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, format!("{error:#}"))
+    }
+}
+
+/// Convert a display for the responses
+pub fn display_json(display: &Display) -> Value {
+    json::object([
+        ("schema_version", SCHEMA_VERSION.into()),
+        ("card", display.card.to_string_lossy().as_ref().into()),
+        ("connector", display.connector.as_str().into()),
+        ("width", display.mode.hdisplay.into()),
+        ("height", display.mode.vdisplay.into()),
+        ("refresh", modes::refresh(&display.mode).into()),
+        ("internal", display.is_internal().into()),
+        ("physical_size", display.physical_size.into()),
+        ("vrr_capable", display.vrr_capable.into()),
+        ("vrr_range", display.vrr_range().into()),
+        ("hdr", display.hdr().into()),
+        (
+            "max_luminance",
+            display.max_luminance().map(f64::from).into(),
+        ),
+        (
+            "hdr_output_metadata",
+            display.properties.hdr_output_metadata.into(),
+        ),
+        ("wide_gamut", display.wide_gamut().into()),
+        (
+            "srgb_coverage",
+            display.srgb_coverage().map(f64::from).into(),
+        ),
+        ("bits_per_component", display.bits_per_component().into()),
+        ("allm", display.allm().into()),
+    ])
+}
+
+/// Build a JSON schema property
+fn property(kind: Value, description: &str) -> Value {
+    json::object([("type", kind), ("description", description.into())])
+}
+
+/// Get the JSON schema of the results
+///
+/// `$defs` has the `display` objects of `get_displays`, the `resolution` of `get_res` and
+/// the `command` of `build_gamescope`.
+pub fn schema() -> Value {
+    let integer = || Value::from("integer");
+    let nullable = |kind: &str| Value::from(vec![kind, "null"]);
+    let version = json::object([
+        ("const", SCHEMA_VERSION.into()),
+        ("description", "Version of the object's schema".into()),
+    ]);
+    let display = json::object([
+        ("type", "object".into()),
+        (
+            "properties",
+            json::object([
+                ("schema_version", version.clone()),
+                (
+                    "card",
+                    property(
+                        "string".into(),
+                        "DRM card, or the detection backend's source",
+                    ),
+                ),
+                (
+                    "connector",
+                    property("string".into(), "Connector, eg. DP-1"),
+                ),
+                ("width", property(integer(), "Horizontal resolution")),
+                ("height", property(integer(), "Vertical resolution")),
+                (
+                    "refresh",
+                    property("number".into(), "Refresh rate in Hz, 0 if unknown"),
+                ),
+                (
+                    "internal",
+                    property(
+                        "boolean".into(),
+                        "Whether the display is built in (laptop, handheld)",
+                    ),
+                ),
+                (
+                    "physical_size",
+                    json::object([
+                        ("type", nullable("array")),
+                        ("items", json::object([("type", integer())])),
+                        ("minItems", 2.into()),
+                        ("maxItems", 2.into()),
+                        ("description", "Width and height in millimeters".into()),
+                    ]),
+                ),
+                (
+                    "vrr_capable",
+                    property(nullable("boolean"), "Variable refresh rate support"),
+                ),
+                (
+                    "vrr_range",
+                    json::object([
+                        ("type", nullable("array")),
+                        ("items", json::object([("type", integer())])),
+                        ("minItems", 2.into()),
+                        ("maxItems", 2.into()),
+                        (
+                            "description",
+                            "Min and max variable refresh rate in Hz, from the EDID".into(),
+                        ),
+                    ]),
+                ),
+                (
+                    "hdr",
+                    property(nullable("boolean"), "HDR support, from the EDID"),
+                ),
+                (
+                    "max_luminance",
+                    property(
+                        nullable("number"),
+                        "Desired content max luminance in cd/m², from the EDID",
+                    ),
+                ),
+                (
+                    "hdr_output_metadata",
+                    property(
+                        nullable("boolean"),
+                        "Whether the driver can send HDR metadata (HDR_OUTPUT_METADATA)",
+                    ),
+                ),
+                (
+                    "wide_gamut",
+                    property(
+                        nullable("boolean"),
+                        "Wide color gamut, from the EDID primaries or colorimetries",
+                    ),
+                ),
+                (
+                    "srgb_coverage",
+                    property(
+                        nullable("number"),
+                        "Part of the sRGB gamut covered by the display (0 to 1), from the EDID primaries",
+                    ),
+                ),
+                (
+                    "bits_per_component",
+                    property(
+                        nullable("integer"),
+                        "Maximum bits per color component, from the EDID",
+                    ),
+                ),
+                (
+                    "allm",
+                    property(
+                        nullable("boolean"),
+                        "Auto Low Latency Mode (automatic game mode of TVs), from the EDID",
+                    ),
+                ),
+            ]),
+        ),
+        (
+            "required",
+            vec![
+                "schema_version",
+                "card",
+                "connector",
+                "width",
+                "height",
+                "refresh",
+                "internal",
+                "physical_size",
+                "vrr_capable",
+                "vrr_range",
+                "hdr",
+                "max_luminance",
+                "hdr_output_metadata",
+                "wide_gamut",
+                "srgb_coverage",
+                "bits_per_component",
+                "allm",
+            ]
+            .into(),
+        ),
+    ]);
+    let resolution = json::object([
+        ("type", "object".into()),
+        (
+            "properties",
+            json::object([
+                ("schema_version", version),
+                ("width", property(integer(), "Horizontal resolution")),
+                ("height", property(integer(), "Vertical resolution")),
+            ]),
+        ),
+        ("required", vec!["schema_version", "width", "height"].into()),
+    ]);
+    let command = json::object([
+        ("type", "array".into()),
+        ("items", json::object([("type", "string".into())])),
+        ("description", "Command and arguments".into()),
+    ]);
+
+    json::object([
+        (
+            "$schema",
+            "https://json-schema.org/draft/2020-12/schema".into(),
+        ),
+        ("title", "rres JSON-RPC results".into()),
+        ("schema_version", SCHEMA_VERSION.into()),
+        (
+            "$defs",
+            json::object([
+                ("display", display),
+                ("resolution", resolution),
+                ("command", command),
+            ]),
+        ),
+        (
+            "anyOf",
+            vec![
+                json::object([
+                    ("type", "array".into()),
+                    ("items", json::object([("$ref", "#/$defs/display".into())])),
+                ]),
+                json::object([("$ref", "#/$defs/resolution".into())]),
+                json::object([("$ref", "#/$defs/command".into())]),
+            ]
+            .into(),
+        ),
+    ])
+}
+
+/// Read an optional string parameter
+fn string_param(params: &Value, name: &str) -> Result<Option<String>, Error> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(Error::new(
+            INVALID_PARAMS,
+            format!("{name} must be a string"),
+        )),
+    }
+}
+
+/// Read an optional integer parameter
+fn integer_param<T: TryFrom<u64>>(params: &Value, name: &str) -> Result<Option<T>, Error> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .and_then(|n| T::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| Error::new(INVALID_PARAMS, format!("invalid {name}"))),
+    }
+}
+
+/// Run a method
+fn call(method: &str, params: &Value, defaults: &ProbeOptions) -> Result<Value, Error> {
+    let mut options = defaults.clone();
+    if let Some(card) = string_param(params, "card")? {
+        options.card = Some(card);
+    }
+    if let Some(display) = integer_param(params, "display")? {
+        options.display = Some(display);
+    }
+
+    match method {
+        "get_displays" => Ok(Value::Array(
+            crate::probe(&ProbeOptions {
+                edid: true,
+                ..options
+            })?
+            .iter()
+            .map(display_json)
+            .collect(),
+        )),
+        "get_res" => {
+            let res = crate::get_res_opts(&options)?;
+            Ok(json::object([
+                ("schema_version", SCHEMA_VERSION.into()),
+                ("width", res.0.into()),
+                ("height", res.1.into()),
+            ]))
+        }
+        "build_gamescope" => {
+            let fsr_mode = string_param(params, "fsr_mode")?.unwrap_or_default();
+            let res = match (
+                integer_param(params, "width")?,
+                integer_param(params, "height")?,
+            ) {
+                (Some(width), Some(height)) => (width, height),
+                (None, None) => crate::get_res_opts(&options)?,
+                _ => return Err(Error::new(INVALID_PARAMS, "width and height go together")),
+            };
+            Ok(crate::gamescope(res, &fsr_mode)?.into())
+        }
+        "get_schema" => Ok(schema()),
+        _ => Err(Error::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method: {method}"),
+        )),
+    }
+}
+
+/// Handle a request line, `None` for notifications
+fn handle(line: &str, defaults: &ProbeOptions) -> Option<Value> {
+    let request = match json::parse(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(response(
+                Value::Null,
+                Err(Error::new(PARSE_ERROR, e.to_string())),
+            ))
+        }
+    };
+    let id = request.get("id").cloned();
+
+    let (Some(method), Some("2.0")) = (
+        request.get("method").and_then(Value::as_str),
+        request.get("jsonrpc").and_then(Value::as_str),
+    ) else {
+        let error = Error::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request");
+        return Some(response(id.unwrap_or(Value::Null), Err(error)));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = if matches!(params, Value::Object(_) | Value::Null) {
+        log::debug!("RPC call: {method}");
+        call(method, &params, defaults)
+    } else {
+        Err(Error::new(INVALID_PARAMS, "params must be an object"))
+    };
+
+    // Notifications get no response
+    Some(response(id?, result))
+}
+
+/// Build a response
+fn response(id: Value, result: Result<Value, Error>) -> Value {
+    match result {
+        Ok(result) => json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+        Err(error) => json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            (
+                "error",
+                json::object([
+                    ("code", error.code.into()),
+                    ("message", error.message.into()),
+                ]),
+            ),
+        ]),
+    }
+}
+
+/// Answer requests until the input is closed
+///
+/// `defaults` are the options of the requests, overridden by their parameters.
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    defaults: &ProbeOptions,
+) -> anyhow::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(&line, defaults) {
+            writeln!(output, "{response}")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
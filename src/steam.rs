@@ -0,0 +1,66 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Steam launch options integration
+//!
+//! Steam expands `%command%` into a chain of wrappers, eg.
+//! `reaper SteamLaunch AppId=620 -- steam-launch-wrapper -- <runtime/proton> game`.
+//! Steam's own process tracking wrappers must stay outermost, while gamescope has to wrap
+//! everything else (the container runtime can't start gamescope by itself).
+
+use std::path;
+
+/// Wrappers Steam uses to track the game process, each followed by `--`
+const STEAM_WRAPPERS: [&str; 2] = ["reaper", "steam-launch-wrapper"];
+
+/// Find where a wrapper has to be inserted into an expanded `%command%`
+///
+/// Returns the index of the first argument after Steam's tracking wrappers.
+pub fn insert_position(command: &[String]) -> usize {
+    let mut position = 0;
+
+    while let Some(program) = command.get(position) {
+        let name = path::Path::new(program)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !STEAM_WRAPPERS.contains(&name.as_str()) {
+            break;
+        }
+        // Skip the wrapper's arguments, up to and including its "--"
+        match command[position..].iter().position(|a| a == "--") {
+            Some(separator) => position += separator + 1,
+            None => break,
+        }
+    }
+
+    position
+}
+
+/// Insert a wrapper (eg. a gamescope invocation) into an expanded `%command%`
+///
+/// The wrapper is separated from the wrapped command with `--`.
+pub fn wrap(command: &[String], wrapper: &[String]) -> Vec<String> {
+    let position = insert_position(command);
+    log::debug!("Steam wrappers: {:?}", &command[..position]);
+
+    let mut wrapped = command[..position].to_vec();
+    wrapped.extend_from_slice(wrapper);
+    wrapped.push("--".to_string());
+    wrapped.extend_from_slice(&command[position..]);
+    wrapped
+}
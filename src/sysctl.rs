@@ -0,0 +1,74 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Read DRM card information from sysctl (FreeBSD)
+//!
+//! drm-kmod exposes each card under `hw.dri.<minor>`, with its driver name and PCI bus ID.
+
+use std::ffi::CString;
+use std::path::Path;
+use std::ptr;
+
+/// Read a string sysctl
+fn read(name: &str) -> Option<String> {
+    let name = CString::new(name).ok()?;
+    let mut len = 0;
+    // SAFETY: querying the size only
+    if unsafe { libc::sysctlbyname(name.as_ptr(), ptr::null_mut(), &mut len, ptr::null(), 0) } != 0
+    {
+        return None;
+    }
+    let mut value = vec![0u8; len];
+    // SAFETY: value has room for len bytes
+    if unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            &mut len,
+            ptr::null(),
+            0,
+        )
+    } != 0
+    {
+        return None;
+    }
+    value.truncate(len);
+    let value = String::from_utf8_lossy(&value);
+    Some(value.trim_end_matches(['\0', '\n']).to_string())
+}
+
+/// Minor number of a card (`/dev/dri/card1` -> 1)
+fn minor(card: &Path) -> Option<u32> {
+    card.file_name()?
+        .to_str()?
+        .strip_prefix("card")?
+        .parse()
+        .ok()
+}
+
+/// PCI slot of the card (eg. `0000:01:00.0`), from `hw.dri.<minor>.busid`
+pub fn pci_slot(card: &Path) -> Option<String> {
+    let busid = read(&format!("hw.dri.{}.busid", minor(card)?))?;
+    busid.strip_prefix("pci:").map(|s| s.to_string())
+}
+
+/// Name of the driver of the card (eg. `amdgpu`), from `hw.dri.<minor>.name`
+pub fn driver(card: &Path) -> Option<String> {
+    // eg. "amdgpu 0x1234"
+    let name = read(&format!("hw.dri.{}.name", minor(card)?))?;
+    name.split_whitespace().next().map(|s| s.to_string())
+}
@@ -0,0 +1,299 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Read DRM card information from sysfs, without touching the device itself
+//!
+//! FreeBSD has no sysfs, the PCI slot and driver of its cards are read from sysctl instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+use crate::{edid, modes, Display, Mode};
+
+/// Root of the DRM class in sysfs
+pub const DRM_CLASS: &str = "/sys/class/drm";
+
+/// Get the sysfs directory of a card (`/dev/dri/card0` -> `/sys/class/drm/card0`)
+pub fn card_dir(card: &Path) -> PathBuf {
+    let mut dir = PathBuf::from(DRM_CLASS);
+    if let Some(name) = card.file_name() {
+        dir.push(name);
+    }
+    dir
+}
+
+/// Read a sysfs attribute, trimming the trailing newline
+pub fn read_attr<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim_end().to_string())
+}
+
+/// Read a hexadecimal sysfs attribute (eg. `0x10de`)
+fn read_hex(path: PathBuf) -> Option<u16> {
+    let value = read_attr(path)?;
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+/// PCI slot of the card (eg. `0000:01:00.0`)
+pub fn pci_slot(card: &Path) -> Option<String> {
+    #[cfg(target_os = "freebsd")]
+    {
+        crate::sysctl::pci_slot(card)
+    }
+    #[cfg(not(target_os = "freebsd"))]
+    {
+        let device = fs::canonicalize(card_dir(card).join("device")).ok()?;
+        Some(device.file_name()?.to_string_lossy().to_string())
+    }
+}
+
+/// PCI vendor and device IDs of the card
+pub fn pci_id(card: &Path) -> Option<(u16, u16)> {
+    let device = card_dir(card).join("device");
+    Some((
+        read_hex(device.join("vendor"))?,
+        read_hex(device.join("device"))?,
+    ))
+}
+
+/// PCI class and subclass of the card (eg. `0x0300` for a VGA controller)
+pub fn pci_class(card: &Path) -> Option<u16> {
+    let value = read_attr(card_dir(card).join("device/class"))?;
+    let class = u32::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+    u16::try_from(class >> 8).ok()
+}
+
+/// Get every card of the system (`/dev/dri/card0`...), in no particular order
+pub fn cards() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(DRM_CLASS) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let index = name.strip_prefix("card")?;
+            index
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| Path::new("/dev/dri").join(&name))
+        })
+        .collect()
+}
+
+/// Name of the kernel driver bound to the card (eg. `amdgpu`)
+pub fn driver(card: &Path) -> Option<String> {
+    #[cfg(target_os = "freebsd")]
+    {
+        crate::sysctl::driver(card)
+    }
+    #[cfg(not(target_os = "freebsd"))]
+    {
+        let driver = fs::read_link(card_dir(card).join("device/driver")).ok()?;
+        Some(driver.file_name()?.to_string_lossy().to_string())
+    }
+}
+
+/// Whether the firmware used this card as the boot VGA device
+pub fn is_boot_vga(card: &Path) -> bool {
+    read_attr(card_dir(card).join("device/boot_vga")).as_deref() == Some("1")
+}
+
+/// Whether runtime power management suspended the card's device (eg. an idle dGPU)
+///
+/// Opening the card wakes it up, which can take seconds.
+#[cfg(target_os = "linux")]
+pub fn is_runtime_suspended(card: &Path) -> bool {
+    read_attr(card_dir(card).join("device/power/runtime_status")).as_deref() == Some("suspended")
+}
+
+/// Whether a connector of the card is connected
+///
+/// The status is the last one detected by the kernel, reading it doesn't wake the card up.
+#[cfg(target_os = "linux")]
+pub fn has_connected_connector(card: &Path) -> bool {
+    let Some(card_name) = card.file_name().map(|n| n.to_string_lossy().to_string()) else {
+        return false;
+    };
+    let Ok(entries) = fs::read_dir(DRM_CLASS) else {
+        return false;
+    };
+    let prefix = format!("{card_name}-");
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .any(|e| read_attr(e.path().join("status")).as_deref() == Some("connected"))
+}
+
+/// Find the card sharing its device with a render node (`renderD128` -> `/dev/dri/card0`)
+///
+/// The returned path may not exist, eg. when only the render node is passed to a container.
+pub fn render_node_card(render: &Path) -> Option<PathBuf> {
+    let device = fs::canonicalize(card_dir(render).join("device")).ok()?;
+    for entry in fs::read_dir(DRM_CLASS).ok()? {
+        let name = entry.ok()?.file_name().to_string_lossy().to_string();
+        if !name.starts_with("card") || name.contains('-') {
+            continue;
+        }
+        let card = PathBuf::from("/dev/dri").join(&name);
+        if fs::canonicalize(card_dir(&card).join("device"))
+            .ok()
+            .as_ref()
+            == Some(&device)
+        {
+            return Some(card);
+        }
+    }
+    None
+}
+
+/// Get every connected display of a card
+///
+/// sysfs doesn't know the current mode, so the first (preferred) progressive mode is reported
+/// instead. Modes only have a resolution and the interlace flag, without refresh rate nor
+/// timings.
+#[cfg(target_os = "linux")]
+pub fn connected_displays(card: &Path) -> Vec<Display> {
+    let Some(card_name) = card.file_name().map(|n| n.to_string_lossy().to_string()) else {
+        return vec![];
+    };
+    let Ok(entries) = fs::read_dir(DRM_CLASS) else {
+        return vec![];
+    };
+
+    let prefix = format!("{card_name}-");
+    let mut connectors: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|e| e.path())
+        .collect();
+    connectors.sort();
+
+    let mut displays = vec![];
+    for connector in connectors {
+        if read_attr(connector.join("status")).as_deref() != Some("connected") {
+            continue;
+        }
+        let Some(list) = read_attr(connector.join("modes")) else {
+            continue;
+        };
+        let modes: Vec<Mode> = list.lines().filter_map(parse_mode).collect();
+        let Some(mode) = modes
+            .iter()
+            .find(|m| modes::is_progressive(m))
+            .or(modes.first())
+            .copied()
+        else {
+            continue;
+        };
+        let name = connector.file_name().unwrap_or_default().to_string_lossy();
+        let name = name.trim_start_matches(&prefix).to_string();
+        log::debug!(
+            card:% = card.display(), connector = name.as_str();
+            "Found display (sysfs): {name}, {}x{}",
+            mode.size().0,
+            mode.size().1
+        );
+        let edid = read_edid(&connector);
+        displays.push(Display {
+            card: card.to_path_buf(),
+            connector: name,
+            mode,
+            modes,
+            physical_size: edid.as_deref().and_then(edid::physical_size),
+            vrr_capable: None,
+            edid,
+            properties: Default::default(),
+        });
+    }
+    displays
+}
+
+/// Read a connector's `edid` attribute, `None` if empty or invalid
+#[cfg(target_os = "linux")]
+fn read_edid(connector: &Path) -> Option<Vec<u8>> {
+    fs::read(connector.join("edid"))
+        .ok()
+        .filter(|e| edid::is_valid(e))
+}
+
+/// Get the EDID of a card's connector (eg. `DP-1`)
+#[cfg(target_os = "linux")]
+pub fn edid(card: &Path, connector: &str) -> Option<Vec<u8>> {
+    let card_name = card.file_name()?.to_string_lossy();
+    read_edid(&Path::new(DRM_CLASS).join(format!("{card_name}-{connector}")))
+}
+
+/// Parse a mode from a connector's `modes` attribute
+///
+/// Modes are listed as "1920x1080", with an "i" suffix for interlaced ones.
+#[cfg(target_os = "linux")]
+fn parse_mode(line: &str) -> Option<Mode> {
+    let (size, flags) = match line.strip_suffix('i') {
+        Some(size) => (size, modes::FLAG_INTERLACE),
+        None => (line, 0),
+    };
+    let (x, y) = size.split_once('x')?;
+    Some(Mode {
+        flags,
+        ..Mode::from_size((x.parse().ok()?, y.parse().ok()?))
+    })
+}
+
+/// Get the state of every connector (`card0-DP-1 connected enabled`, one per line)
+///
+/// Changes on hotplug, or when a connector is turned on or off.
+pub fn connector_states() -> String {
+    let Ok(entries) = fs::read_dir(DRM_CLASS) else {
+        return String::new();
+    };
+    let mut connectors: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().contains('-'))
+        .map(|e| e.path())
+        .collect();
+    connectors.sort();
+    connectors
+        .iter()
+        .map(|connector| {
+            format!(
+                "{} {} {}\n",
+                connector.file_name().unwrap_or_default().to_string_lossy(),
+                read_attr(connector.join("status")).unwrap_or_default(),
+                read_attr(connector.join("enabled")).unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        assert_eq!(parse_mode("1920x1080"), Some(Mode::from_size((1920, 1080))));
+        let interlaced = parse_mode("1920x1080i").unwrap();
+        assert_eq!(interlaced.size(), (1920, 1080));
+        assert!(!modes::is_progressive(&interlaced));
+        assert_eq!(parse_mode("1920x1080ii"), None);
+        assert_eq!(parse_mode("1920"), None);
+        assert_eq!(parse_mode(""), None);
+    }
+}
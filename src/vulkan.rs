@@ -0,0 +1,277 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Vulkan physical devices, as games will see them
+//!
+//! Devices are read from `vulkaninfo --summary`, so rres doesn't need to link the Vulkan loader.
+//! The summary has no PCI address, but RADV encodes it in the device UUID, which tells two
+//! identical GPUs apart.
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::Context;
+
+use crate::sysfs;
+
+/// A Vulkan physical device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VulkanDevice {
+    /// Index in the Vulkan device list (GPU0, GPU1...)
+    pub index: usize,
+    pub name: String,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// Device type (eg. `PHYSICAL_DEVICE_TYPE_DISCRETE_GPU`)
+    pub device_type: String,
+    /// Driver name (eg. `radv`)
+    pub driver: String,
+    /// PCI slot (eg. `0000:03:00.0`), when the driver exposes it
+    pub pci_slot: Option<String>,
+}
+
+impl VulkanDevice {
+    /// Value for `MESA_VK_DEVICE_SELECT` selecting this device
+    pub fn device_select(&self) -> String {
+        format!("{:04x}:{:04x}", self.vendor_id, self.device_id)
+    }
+
+    /// Find the DRM card backing this device, out of the given cards
+    pub fn find_card<'a>(&self, cards: &'a [PathBuf]) -> Option<&'a Path> {
+        cards
+            .iter()
+            .find(|c| self.matches(sysfs::pci_slot(c).as_deref(), sysfs::pci_id(c)))
+            .map(|c| c.as_path())
+    }
+
+    /// Whether a card with the given PCI slot and IDs backs this device
+    ///
+    /// The slot is compared when known, the IDs can't tell two identical GPUs apart.
+    fn matches(&self, slot: Option<&str>, id: Option<(u16, u16)>) -> bool {
+        match &self.pci_slot {
+            Some(pci_slot) => slot == Some(pci_slot.as_str()),
+            None => id == Some((self.vendor_id, self.device_id)),
+        }
+    }
+}
+
+/// Decode the PCI slot out of a RADV device UUID
+///
+/// RADV fills the UUID with the PCI domain, bus, device and function, as little-endian u32s.
+fn radv_pci_slot(uuid: &str) -> Option<String> {
+    let hex: String = uuid.chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut fields = [0u32; 4];
+    for (i, field) in fields.iter_mut().enumerate() {
+        let bytes = u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16).ok()?;
+        *field = bytes.swap_bytes();
+    }
+    let [domain, bus, device, function] = fields;
+    Some(format!("{domain:04x}:{bus:02x}:{device:02x}.{function:x}"))
+}
+
+/// Get the Vulkan devices by running `vulkaninfo --summary`
+pub fn devices() -> anyhow::Result<Vec<VulkanDevice>> {
+    let output = process::Command::new("vulkaninfo")
+        .arg("--summary")
+        .output()
+        .context("failed to run vulkaninfo (is vulkan-tools installed?)")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("vulkaninfo failed: {}", output.status));
+    }
+    Ok(parse_summary(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the device list from `vulkaninfo --summary` output
+pub fn parse_summary(summary: &str) -> Vec<VulkanDevice> {
+    let mut devices = vec![];
+    let mut current: Option<VulkanDevice> = None;
+    let mut uuid = None;
+    let finish = |mut device: VulkanDevice, uuid: Option<&str>| {
+        if device.driver == "radv" {
+            device.pci_slot = uuid.and_then(radv_pci_slot);
+        }
+        device
+    };
+
+    for line in summary.lines() {
+        let line = line.trim();
+        // Device header: "GPU0:"
+        if let Some(index) = line
+            .strip_prefix("GPU")
+            .and_then(|l| l.strip_suffix(':'))
+            .and_then(|i| i.parse().ok())
+        {
+            devices.extend(current.take().map(|d| finish(d, uuid.as_deref())));
+            uuid = None;
+            current = Some(VulkanDevice {
+                index,
+                name: String::new(),
+                vendor_id: 0,
+                device_id: 0,
+                device_type: String::new(),
+                driver: String::new(),
+                pci_slot: None,
+            });
+            continue;
+        }
+
+        let (Some(device), Some((key, value))) = (current.as_mut(), line.split_once('=')) else {
+            continue;
+        };
+        let value = value.trim();
+        let hex = || u16::from_str_radix(value.trim_start_matches("0x"), 16).unwrap_or(0);
+        match key.trim() {
+            "deviceName" => device.name = value.to_string(),
+            "vendorID" => device.vendor_id = hex(),
+            "deviceID" => device.device_id = hex(),
+            "deviceType" => device.device_type = value.to_string(),
+            "driverName" => device.driver = value.to_string(),
+            "deviceUUID" => uuid = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    devices.extend(current.map(|d| finish(d, uuid.as_deref())));
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // vulkaninfo 1.3.275, RX 6800 XT + llvmpipe
+    const AMD: &str = "\
+==========
+VULKANINFO
+==========
+
+Vulkan Instance Version: 1.3.275
+
+
+Instance Extensions: count = 24
+-------------------------------
+VK_EXT_acquire_drm_display             : extension revision 1
+VK_KHR_surface                         : extension revision 25
+
+Instance Layers: count = 1
+--------------------------
+VK_LAYER_MESA_device_select Linux device selection layer 1.3.211  version 1
+
+Devices:
+========
+GPU0:
+	apiVersion         = 1.3.274
+	driverVersion      = 24.0.5
+	vendorID           = 0x1002
+	deviceID           = 0x73bf
+	deviceType         = PHYSICAL_DEVICE_TYPE_DISCRETE_GPU
+	deviceName         = AMD Radeon RX 6800 XT (RADV NAVI21)
+	driverID           = DRIVER_ID_MESA_RADV
+	driverName         = radv
+	driverInfo         = Mesa 24.0.5
+	conformanceVersion = 1.2.7.1
+	deviceUUID         = 00000000-0300-0000-0000-000000000000
+	driverUUID         = 414d442d-4d45-5341-2d44-525600000000
+GPU1:
+	apiVersion         = 1.3.274
+	driverVersion      = 0.0.1
+	vendorID           = 0x10005
+	deviceID           = 0x0000
+	deviceType         = PHYSICAL_DEVICE_TYPE_CPU
+	deviceName         = llvmpipe (LLVM 17.0.6, 256 bits)
+	driverID           = DRIVER_ID_MESA_LLVMPIPE
+	driverName         = llvmpipe
+	driverInfo         = Mesa 24.0.5 (LLVM 17.0.6)
+	conformanceVersion = 1.3.1.1
+	deviceUUID         = 6d657361-3234-2e30-2e35-000000000000
+	driverUUID         = 6c6c766d-7069-7065-5555-494400000000
+";
+
+    // vulkaninfo 1.3.250, Intel + NVIDIA laptop
+    const HYBRID: &str = "\
+Devices:
+========
+GPU0:
+	apiVersion         = 1.3.255
+	driverVersion      = 23.1.9
+	vendorID           = 0x8086
+	deviceID           = 0x9a49
+	deviceType         = PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU
+	deviceName         = Intel(R) Xe Graphics (TGL GT2)
+	driverID           = DRIVER_ID_INTEL_OPEN_SOURCE_MESA
+	driverName         = Intel open-source Mesa driver
+	driverInfo         = Mesa 23.1.9
+	conformanceVersion = 1.3.5.0
+	deviceUUID         = 8680499a-0100-0000-0002-000000000000
+	driverUUID         = 2d6b2ba5-3ef7-4bdc-8d32-c3de8b4b81bf
+GPU1:
+	apiVersion         = 1.3.242
+	driverVersion      = 535.113.1.0
+	vendorID           = 0x10de
+	deviceID           = 0x25a0
+	deviceType         = PHYSICAL_DEVICE_TYPE_DISCRETE_GPU
+	deviceName         = NVIDIA GeForce RTX 3050 Ti Laptop GPU
+	driverID           = DRIVER_ID_NVIDIA_PROPRIETARY
+	driverName         = NVIDIA
+	driverInfo         = 535.113.01
+	conformanceVersion = 1.3.5.0
+	deviceUUID         = 5c3a1bde-9e6b-b2cf-4c6e-b6b8a7e2b1c4
+	driverUUID         = 38a1c8c2-79c1-5ea6-9a8d-1f7d7b9c3e05
+";
+
+    #[test]
+    fn summary() {
+        let devices = parse_summary(AMD);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].index, 0);
+        assert_eq!(devices[0].name, "AMD Radeon RX 6800 XT (RADV NAVI21)");
+        assert_eq!(devices[0].device_select(), "1002:73bf");
+        assert_eq!(devices[0].device_type, "PHYSICAL_DEVICE_TYPE_DISCRETE_GPU");
+        assert_eq!(devices[0].driver, "radv");
+        assert_eq!(devices[0].pci_slot.as_deref(), Some("0000:03:00.0"));
+        assert_eq!(devices[1].device_type, "PHYSICAL_DEVICE_TYPE_CPU");
+        // Only RADV UUIDs are PCI slots
+        assert_eq!(devices[1].pci_slot, None);
+
+        let devices = parse_summary(HYBRID);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].device_select(), "8086:9a49");
+        assert_eq!(devices[0].pci_slot, None);
+        assert_eq!(devices[1].index, 1);
+        assert_eq!(devices[1].device_select(), "10de:25a0");
+        assert_eq!(devices[1].pci_slot, None);
+
+        assert_eq!(parse_summary(""), vec![]);
+    }
+
+    #[test]
+    fn matches() {
+        let devices = parse_summary(AMD);
+        let radv = &devices[0];
+        assert!(radv.matches(Some("0000:03:00.0"), Some((0x1002, 0x73bf))));
+        // A second RX 6800 XT, in another slot
+        assert!(!radv.matches(Some("0000:0c:00.0"), Some((0x1002, 0x73bf))));
+
+        let devices = parse_summary(HYBRID);
+        let nvidia = &devices[1];
+        assert!(nvidia.matches(Some("0000:01:00.0"), Some((0x10de, 0x25a0))));
+        assert!(!nvidia.matches(Some("0000:00:02.0"), Some((0x8086, 0x9a49))));
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Wine integration
+
+use std::env;
+use std::path;
+
+/// Default name of the Wine virtual desktop
+pub const DEFAULT_DESKTOP: &str = "rres";
+
+/// Get the Wine prefix in use, if `WINEPREFIX` is set
+pub fn prefix() -> Option<path::PathBuf> {
+    env::var_os("WINEPREFIX").map(path::PathBuf::from)
+}
+
+/// Get the virtual desktop name, from `RRES_WINE_DESKTOP`
+pub fn desktop_name() -> String {
+    env::var("RRES_WINE_DESKTOP").unwrap_or(DEFAULT_DESKTOP.to_string())
+}
+
+/// Build a registry file enabling a virtual desktop of the given resolution, for a prefix
+/// (see [`prefix`]) and desktop name (see [`desktop_name`])
+///
+/// Import it with `wine regedit <file>`.
+pub fn registry(res: (u16, u16), prefix: Option<&path::Path>, desktop: &str) -> String {
+    let prefix = match prefix {
+        Some(prefix) => prefix.display().to_string(),
+        None => "default prefix".to_string(),
+    };
+
+    format!(
+        "REGEDIT4\n\
+        \n\
+        ; Virtual desktop generated by rres ({prefix})\n\
+        [HKEY_CURRENT_USER\\Software\\Wine\\Explorer]\n\
+        \"Desktop\"=\"{desktop}\"\n\
+        \n\
+        [HKEY_CURRENT_USER\\Software\\Wine\\Explorer\\Desktops]\n\
+        \"{desktop}\"=\"{}x{}\"\n",
+        res.0, res.1
+    )
+}
+
+/// Build the command running a program in a Wine virtual desktop
+///
+/// The program and its arguments must be appended to the returned command.
+pub fn virtual_desktop(res: (u16, u16)) -> anyhow::Result<Vec<String>> {
+    let wine_bin = env::var("RRES_WINE").unwrap_or("wine".to_string());
+    let desktop = desktop_name();
+
+    if let Some(prefix) = prefix() {
+        if !prefix.is_dir() {
+            return Err(anyhow::anyhow!(
+                "WINEPREFIX is not a directory: {}",
+                prefix.display()
+            ));
+        }
+        log::info!("Using Wine prefix {}", prefix.display());
+    }
+
+    Ok(vec![
+        wine_bin,
+        "explorer".to_string(),
+        format!("/desktop={},{}x{}", desktop, res.0, res.1),
+    ])
+}
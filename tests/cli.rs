@@ -0,0 +1,330 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! The command line, on the displays of a fixture (`RRES_BACKEND=mock:<fixture.json>`)
+
+#![cfg(any(target_os = "linux", target_os = "freebsd"))]
+
+use std::process::{Command, Output};
+
+/// Run rres on a fixture of `tests/fixtures/`, whatever its result
+fn execute(fixture: &str, args: &[&str], env: &[(&str, &str)]) -> Output {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/").to_string() + fixture;
+    Command::new(env!("CARGO_BIN_EXE_rres"))
+        .args(args)
+        .env("RRES_BACKEND", format!("mock:{fixture}"))
+        .env("RRES_CONFIG", "/nonexistent")
+        .env_remove("RRES_DISPLAY")
+        .env_remove("RRES_FORCE_RES")
+        .env_remove("RRES_GAMESCOPE")
+        .env_remove("SteamAppId")
+        .env_remove("STEAM_COMPAT_DATA_PATH")
+        .env_remove("RRES_LOG_FILE")
+        .env_remove("JOURNAL_STREAM")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+/// Run rres on a fixture of `tests/fixtures/`
+fn run(fixture: &str, args: &[&str], env: &[(&str, &str)]) -> Output {
+    let output = execute(fixture, args, env);
+    assert!(
+        output.status.success(),
+        "rres {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Run rres on a fixture of `tests/fixtures/`, returning its output
+fn rres(fixture: &str, args: &[&str], env: &[(&str, &str)]) -> String {
+    String::from_utf8(run(fixture, args, env).stdout).unwrap()
+}
+
+/// Run rres on a fixture of `tests/fixtures/`, returning its log
+fn log(fixture: &str, args: &[&str]) -> String {
+    String::from_utf8(run(fixture, args, &[]).stderr).unwrap()
+}
+
+/// Run rres on a fixture of `tests/fixtures/`, expecting a failure, returning its log
+fn error(fixture: &str, args: &[&str]) -> String {
+    let output = execute(fixture, args, &[]);
+    assert!(!output.status.success(), "rres {args:?} succeeded");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn resolution() {
+    assert_eq!(rres("amd-laptop.json", &[], &[]), "1920x1080\n");
+    assert_eq!(rres("nvidia-desktop.json", &[], &[]), "2560x1440\n");
+}
+
+#[test]
+fn multi() {
+    assert_eq!(
+        rres("amd-laptop.json", &["-m"], &[]),
+        "Display #0: 1920x1080\nDisplay #1: 3840x2160\n"
+    );
+}
+
+#[test]
+fn selection() {
+    assert_eq!(
+        rres("amd-laptop.json", &[], &[("RRES_DISPLAY", "1")]),
+        "3840x2160\n"
+    );
+    assert_eq!(
+        rres("amd-laptop.json", &["--card", "card1"], &[]),
+        "3840x2160\n"
+    );
+    assert_eq!(rres("amd-laptop.json", &["--native"], &[]), "2560x1600\n");
+}
+
+#[test]
+fn aspect() {
+    assert_eq!(rres("amd-laptop.json", &["--aspect"], &[]), "16:9\n");
+    assert_eq!(
+        rres("amd-laptop.json", &["--aspect", "--native"], &[]),
+        "16:10\n"
+    );
+    assert_eq!(
+        rres(
+            "amd-laptop.json",
+            &["--native", "--force-aspect", "16:9"],
+            &[]
+        ),
+        "2560x1440\n"
+    );
+    assert_eq!(
+        rres("amd-laptop.json", &["--native", "--max", "1920x1080"], &[]),
+        "1728x1080\n"
+    );
+    for bounds in ["0x0", "0x1080", "1920x0"] {
+        assert!(error("amd-laptop.json", &["--max", bounds]).contains("invalid bounds"));
+    }
+}
+
+#[test]
+fn config_overrides() {
+    let env = [
+        (
+            "RRES_CONFIG",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/config"),
+        ),
+        ("SteamAppId", "620"),
+    ];
+    assert_eq!(rres("amd-laptop.json", &[], &env), "1280x720\n");
+    // The command line wins
+    assert_eq!(
+        rres("amd-laptop.json", &["--force-res", "2560x1600"], &env),
+        "2560x1600\n"
+    );
+}
+
+#[test]
+fn forced_res() {
+    let warning = "1234x567 isn't supported by eDP-1";
+    let log = log("amd-laptop.json", &["--force-res", "1234x567"]);
+    assert!(log.contains(warning), "{log}");
+    // Not even probed
+    let env = [("RRES_FORCE_RES", "1234x567")];
+    let output = run("missing.json", &["-v"], &env);
+    assert_eq!(output.stdout, b"1234x567\n");
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(!log.contains("forced resolution"), "{log}");
+    let run_args = ["run", "--print-cmd", "--", "game", "{REFRESH}"];
+    assert_eq!(
+        rres("missing.json", &run_args, &env),
+        "RRES_W=1234 RRES_H=567 game '{REFRESH}'\n"
+    );
+    // Unless asked to
+    assert!(error("amd-laptop.json", &["--strict", "--force-res", "1234x567"]).contains(warning));
+    let output = execute("amd-laptop.json", &["--strict"], &env);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(warning));
+    // With the refresh rate of the display's mode of that size
+    let run_args = [
+        "run",
+        "--force-res",
+        "1920x1080",
+        "--print-cmd",
+        "--",
+        "game",
+        "{REFRESH}",
+    ];
+    assert_eq!(
+        rres("amd-laptop.json", &run_args, &[]),
+        "RRES_W=1920 RRES_H=1080 RRES_REFRESH=60 game 60\n"
+    );
+}
+
+#[test]
+fn orientation() {
+    // 800x1280 panel, mounted right side up
+    assert_eq!(rres("handheld.json", &[], &[]), "1280x800\n");
+    assert_eq!(
+        rres("handheld.json", &["--raw-orientation"], &[]),
+        "800x1280\n"
+    );
+}
+
+#[test]
+fn gamescope() {
+    assert_eq!(
+        rres("amd-laptop.json", &["-g", "quality", "--print-cmd"], &[]),
+        "gamescope -W 1920 -H 1080 -U -w 1280 -h 720\n"
+    );
+}
+
+#[test]
+fn scale() {
+    assert_eq!(
+        rres("amd-laptop.json", &["--scale", "75%", "--print-cmd"], &[]),
+        "gamescope -W 1920 -H 1080 -w 1440 -h 810\n"
+    );
+    assert_eq!(
+        rres("amd-laptop.json", &["-g", "50%", "--print-cmd"], &[]),
+        "gamescope -W 1920 -H 1080 -w 960 -h 540\n"
+    );
+}
+
+#[test]
+fn snap() {
+    assert_eq!(
+        rres("amd-laptop.json", &["--native", "--snap"], &[]),
+        "2560x1600\n"
+    );
+    assert_eq!(rres("handheld.json", &["--snap"], &[]), "1280x800\n");
+}
+
+#[test]
+fn logical() {
+    // Without a compositor, the physical resolution is kept
+    let env = [
+        ("WAYLAND_DISPLAY", "/nonexistent"),
+        ("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent"),
+    ];
+    assert_eq!(rres("amd-laptop.json", &["--logical"], &env), "1920x1080\n");
+}
+
+#[test]
+fn verbosity() {
+    // --metrics without --rpc warns, after the options are parsed
+    let warning = ["--metrics", "127.0.0.1:0"];
+    let default = log("amd-laptop.json", &warning);
+    assert!(default.contains("WARN"), "{default}");
+    assert!(!default.contains("DEBUG"), "{default}");
+
+    let quiet = log("amd-laptop.json", &["-q", warning[0], warning[1]]);
+    assert_eq!(quiet, "");
+
+    let verbose = log("amd-laptop.json", &["-vv"]);
+    assert!(
+        verbose.contains("Probing displays with the fixture backend"),
+        "{verbose}"
+    );
+    assert!(!verbose.contains("TRACE"), "{verbose}");
+}
+
+#[test]
+fn json_log() {
+    let log = log("amd-laptop.json", &["-vv", "--log-format", "json"]);
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(!lines.is_empty());
+    assert!(
+        lines.iter().all(|l| l.starts_with('{') && l.ends_with('}')),
+        "{log}"
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.contains(r#""connector":"eDP-1""#) && l.contains(r#""level":"DEBUG""#)),
+        "{log}"
+    );
+}
+
+#[test]
+fn explicit_backend_has_no_fallback() {
+    // The fixture's answer stands, even without display
+    let output = execute("headless.json", &[], &[("RRES_FALLBACK_RES", "1280x720")]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(!log.contains("fallback resolution"), "{log}");
+}
+
+#[test]
+fn proton() {
+    // Upscaled by Proton's fullscreen hack
+    assert_eq!(
+        rres(
+            "amd-laptop.json",
+            &[
+                "run",
+                "-g",
+                "quality",
+                "--print-cmd",
+                "--",
+                "proton",
+                "run",
+                "game.exe"
+            ],
+            &[("STEAM_COMPAT_DATA_PATH", "/tmp/pfx")],
+        ),
+        "RRES_W=1920 RRES_H=1080 RRES_REFRESH=60 RRES_FSR_W=1280 RRES_FSR_H=720 \
+         WINE_FULLSCREEN_FSR=1 WINE_FULLSCREEN_FSR_MODE=quality \
+         WINE_FULLSCREEN_FSR_CUSTOM_MODE=1280x720 proton run game.exe\n"
+    );
+    // Upscaled by gamescope
+    assert_eq!(
+        rres(
+            "amd-laptop.json",
+            &[
+                "-g",
+                "quality",
+                "--print-cmd",
+                "--",
+                "/opt/proton",
+                "run",
+                "game.exe"
+            ],
+            &[],
+        ),
+        "WINE_FULLSCREEN_FSR=0 gamescope -W 1920 -H 1080 -U -w 1280 -h 720 \
+         /opt/proton run game.exe\n"
+    );
+    // Not a Proton game
+    assert_eq!(
+        rres(
+            "amd-laptop.json",
+            &["run", "--print-cmd", "--", "game"],
+            &[]
+        ),
+        "RRES_W=1920 RRES_H=1080 RRES_REFRESH=60 game\n"
+    );
+    let log = log(
+        "amd-laptop.json",
+        &["-v", "-g", "quality", "--print-cmd", "--", "proton"],
+    );
+    assert!(log.contains("WINE_FULLSCREEN_FSR=0"), "{log}");
+    // Launchers may run Proton
+    assert_eq!(
+        rres("amd-laptop.json", &["wrapper", "heroic"], &[]),
+        "env WINE_FULLSCREEN_FSR=0 gamescope -W 1920 -H 1080 --\n"
+    );
+}
@@ -0,0 +1,296 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Snapshots of the output formats scripts parse
+//!
+//! The formats are checked on hand-made displays, and the command line's real output on the
+//! fixtures of `tests/fixtures/` (`RRES_BACKEND=mock:<fixture.json>`).
+//! Every output is compared with its file in `tests/snapshots/`. After an intended change,
+//! update them with `RRES_UPDATE_SNAPSHOTS=1 cargo test --test snapshots` and review the diff.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rres::command::Values;
+use rres::{cvt, Display, Properties};
+
+/// Compare `actual` with the snapshot `name`, or write it when updating
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(name);
+    if env::var_os("RRES_UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing snapshot {}, create it with RRES_UPDATE_SNAPSHOTS=1",
+            path.display()
+        )
+    });
+    assert!(
+        actual == expected,
+        "{name} changed:\n--- expected\n{expected}\n--- actual\n{actual}"
+    );
+}
+
+/// Run rres on a fixture of `tests/fixtures/` (`RRES_BACKEND=mock:<fixture.json>`), with
+/// `input` on stdin, returning its output
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn rres(fixture: &str, args: &[&str], input: &str) -> String {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/").to_string() + fixture;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rres"))
+        .args(args)
+        .env("RRES_BACKEND", format!("mock:{fixture}"))
+        .env("RRES_CONFIG", "/nonexistent")
+        .env_remove("RRES_DISPLAY")
+        .env_remove("RRES_FORCE_RES")
+        .env_remove("RRES_LOG_FILE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "rres {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A desktop monitor at 144 Hz and a laptop panel
+fn displays() -> Vec<Display> {
+    let monitor = [
+        cvt::reduced_blanking((2560, 1440), 144.0).unwrap(),
+        cvt::reduced_blanking((2560, 1440), 60.0).unwrap(),
+        cvt::reduced_blanking((1920, 1080), 60.0).unwrap(),
+    ];
+    let panel = [cvt::reduced_blanking((1920, 1200), 60.0).unwrap()];
+    vec![
+        Display {
+            card: PathBuf::from("/dev/dri/card1"),
+            connector: "DP-1".to_string(),
+            mode: monitor[0],
+            modes: monitor.to_vec(),
+            physical_size: Some((597, 336)),
+            vrr_capable: Some(true),
+            edid: None,
+            properties: Properties {
+                hdr_output_metadata: Some(true),
+                ..Default::default()
+            },
+        },
+        Display {
+            card: PathBuf::from("/dev/dri/card0"),
+            connector: "eDP-1".to_string(),
+            mode: panel[0],
+            modes: panel.to_vec(),
+            physical_size: None,
+            vrr_capable: None,
+            edid: None,
+            properties: Properties::default(),
+        },
+    ]
+}
+
+fn values() -> Values {
+    Values {
+        res: (2560, 1440),
+        refresh: 144,
+        render_res: rres::render_res((2560, 1440), "quality").unwrap(),
+    }
+}
+
+#[test]
+fn displays_json() {
+    let json: Vec<String> = displays()
+        .iter()
+        .map(|d| rres::rpc::display_json(d).to_string())
+        .collect();
+    assert_snapshot("displays.json", &(json.join("\n") + "\n"));
+}
+
+#[test]
+fn schema() {
+    assert_snapshot("schema.json", &(rres::rpc::schema().to_string() + "\n"));
+}
+
+#[test]
+fn env_file() {
+    assert_snapshot(
+        "env_file.env",
+        &rres::command::env_file(&values(), &displays()),
+    );
+}
+
+#[test]
+fn modeline() {
+    let modelines: Vec<String> = displays()[0]
+        .modes
+        .iter()
+        .map(|m| rres::modes::modeline(m, &format!("{}x{}", m.hdisplay, m.vdisplay)))
+        .collect();
+    assert_snapshot("modelines.txt", &(modelines.join("\n") + "\n"));
+}
+
+#[test]
+fn lutris() {
+    assert_snapshot(
+        "lutris.yml",
+        &rres::lutris::config(values().res, Some("quality")).unwrap(),
+    );
+    // Plain render scale, without FSR
+    assert_snapshot(
+        "lutris_scale.yml",
+        &rres::lutris::config(values().res, Some("75%")).unwrap(),
+    );
+}
+
+#[test]
+fn dxvk() {
+    let mode = &displays()[0].mode;
+    assert_snapshot("dxvk.conf", &rres::dxvk::config(mode, mode.size()));
+    for preset in ["ultra", "quality", "balanced", "performance"] {
+        let render_res = rres::render_res(mode.size(), preset).unwrap();
+        assert_snapshot(
+            &format!("dxvk_{preset}.conf"),
+            &rres::dxvk::config(mode, render_res),
+        );
+    }
+}
+
+#[test]
+fn wine_registry() {
+    let registry = rres::wine::registry(values().res, None, rres::wine::DEFAULT_DESKTOP);
+    assert_snapshot("wine.reg", &registry);
+}
+
+#[test]
+fn gamescope_hdr() {
+    // DisplayHDR 400 monitor
+    let metadata = rres::edid::HdrMetadata {
+        pq: true,
+        hlg: false,
+        traditional_hdr: false,
+        max_luminance: Some(446.0),
+        max_frame_avg_luminance: Some(319.5),
+        min_luminance: Some(0.3),
+    };
+    assert_snapshot(
+        "gamescope_hdr.txt",
+        &(rres::command::gamescope_hdr_args(&metadata).join(" ") + "\n"),
+    );
+}
+
+#[test]
+fn completions() {
+    for shell in ["bash", "zsh", "fish"] {
+        // The options are read from the usage text of the binary
+        let output = Command::new(env!("CARGO_BIN_EXE_rres"))
+            .args(["completions", shell])
+            .env("RRES_CONFIG", "/nonexistent")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "rres completions {shell} failed");
+        let script = String::from_utf8(output.stdout).unwrap();
+        assert_snapshot(&format!("completions.{shell}"), &script);
+
+        // Syntax check, with the shells that are installed
+        let path = env::temp_dir().join(format!("rres-completions-{}.{shell}", std::process::id()));
+        fs::write(&path, &script).unwrap();
+        let check = Command::new(shell).arg("-n").arg(&path).output();
+        fs::remove_file(&path).unwrap();
+        if let Ok(check) = check {
+            assert!(
+                check.status.success(),
+                "invalid {shell} completions: {}",
+                String::from_utf8_lossy(&check.stderr)
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn cli() {
+    for fixture in ["amd-laptop", "nvidia-desktop", "handheld"] {
+        let file = format!("{fixture}.json");
+        assert_snapshot(&format!("cli_{fixture}.txt"), &rres(&file, &["-m"], ""));
+        assert_snapshot(
+            &format!("cli_{fixture}_info.txt"),
+            &rres(&file, &["info"], ""),
+        );
+        assert_snapshot(
+            &format!("cli_{fixture}_modes.txt"),
+            &rres(&file, &["--modes"], ""),
+        );
+    }
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn cli_rpc() {
+    let requests = [
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "get_displays"}"#,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "get_res"}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "build_gamescope", "params": {"fsr_mode": "quality"}}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "get_schema"}"#,
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "unknown"}"#,
+    ];
+    assert_snapshot(
+        "cli_rpc.jsonl",
+        &rres(
+            "nvidia-desktop.json",
+            &["--rpc"],
+            &(requests.join("\n") + "\n"),
+        ),
+    );
+    // Same schema as the library's
+    assert_snapshot(
+        "schema.json",
+        &rres("nvidia-desktop.json", &["--schema"], ""),
+    );
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn cli_env_file() {
+    let path = env::temp_dir().join(format!("rres-env-file-{}.env", std::process::id()));
+    let output = rres(
+        "nvidia-desktop.json",
+        &["--env-file", path.to_str().unwrap()],
+        "",
+    );
+    let env_file = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_snapshot("cli_env_file.txt", &output);
+    assert_snapshot("cli_env_file.env", &env_file);
+}
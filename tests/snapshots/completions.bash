@@ -0,0 +1,103 @@
+_rres() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local i
+    # Wrapped commands
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        [[ ${COMP_WORDS[i]} == -- ]] && return
+    done
+    case "$prev" in
+        -c|--card)
+            COMPREPLY=($(compgen -W "$(rres completions cards 2>/dev/null)" -- "$cur"))
+            return
+            ;;
+        -g|--gamescope)
+            COMPREPLY=($(compgen -W "native ultra quality balanced performance" -- "$cur"))
+            return
+            ;;
+        --scale)
+            :
+            return
+            ;;
+        --mode)
+            :
+            return
+            ;;
+        --res)
+            :
+            return
+            ;;
+        --force-aspect)
+            :
+            return
+            ;;
+        --max)
+            :
+            return
+            ;;
+        --force-res)
+            :
+            return
+            ;;
+        --fallback-res)
+            :
+            return
+            ;;
+        --output)
+            COMPREPLY=($(compgen -W "$(rres completions connectors 2>/dev/null)" -- "$cur"))
+            return
+            ;;
+        --gpu-policy)
+            COMPREPLY=($(compgen -W "default prefer-dgpu prefer-igpu" -- "$cur"))
+            return
+            ;;
+        --env-file)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return
+            ;;
+        --metrics)
+            :
+            return
+            ;;
+        --log-file)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return
+            ;;
+        --color)
+            :
+            return
+            ;;
+        --log-format)
+            :
+            return
+            ;;
+        --log-child)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return
+            ;;
+        --chdir)
+            COMPREPLY=($(compgen -d -- "$cur"))
+            return
+            ;;
+        --unset)
+            :
+            return
+            ;;
+        --bench)
+            :
+            return
+            ;;
+    esac
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "-c --card -m --multi -v --verbose -q --quiet -h --help -g --gamescope --scale --mode --res --best-refresh --force-aspect --snap --max --aspect --logical --force-res --strict --fallback-res --native --via-dbus --no-cache --via-portal --prefer-drm --modeline --interlaced --raw-orientation --output --gpu-policy --list-cards --redact --debug-topology --rpc --schema --env-file --metrics --modes --render-gpu --spawn --gamemode --mangohud --hdr --print-cmd --supervise --log-file --color --log-format --log-child --chdir --unset --deck --vulkan --fast --bench" -- "$cur"))
+    elif [[ $COMP_CWORD == 1 ]]; then
+        COMPREPLY=($(compgen -W "run wine wine-reg dxvk-conf lutris wrapper steam info cvt set completions dump doctor" -- "$cur"))
+    elif [[ $COMP_CWORD == 2 && ${COMP_WORDS[1]} == completions ]]; then
+        COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur"))
+    elif [[ $COMP_CWORD == 2 && ${COMP_WORDS[1]} == wrapper ]]; then
+        COMPREPLY=($(compgen -W "heroic bottles" -- "$cur"))
+    elif [[ ${COMP_WORDS[1]} == @(wine-reg|dxvk-conf) ]]; then
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}
+complete -o default -F _rres rres
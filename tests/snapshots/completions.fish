@@ -0,0 +1,70 @@
+complete -c rres -f
+complete -c rres -n __fish_use_subcommand -a run -d 'Run a program with RRES_W, RRES_H, RRES_REFRESH (and RRES_FSR_W, RRES_FSR_H with -g) set, see Command tokens'
+complete -c rres -n __fish_use_subcommand -a wine -d 'Run a program in a Wine virtual desktop sized to the display'
+complete -c rres -n __fish_use_subcommand -a wine-reg -d 'Print (or write to file) a Wine registry fragment setting the virtual desktop resolution'
+complete -c rres -n __fish_use_subcommand -a dxvk-conf -d 'Print (or write to file) a dxvk.conf capping the frame rate to the refresh rate, with the display\'s aspect ratio for D3D9 and a texture LOD bias for the render resolution of -g'
+complete -c rres -n __fish_use_subcommand -a lutris -d 'Print a Lutris game configuration fragment: gamescope with -g, a Wine virtual desktop otherwise'
+complete -c rres -n __fish_use_subcommand -a wrapper -d 'Print a gamescope wrapper command (-g native by default) for the heroic or bottles launchers'
+complete -c rres -n __fish_use_subcommand -a steam -d 'Steam launch options wrapper, runs the game in gamescope (-g native by default)'
+complete -c rres -n __fish_use_subcommand -a info -d 'Print a summary of each display: current and native modes, maximum resolution and refresh rate, HDR, VRR, size'
+complete -c rres -n __fish_use_subcommand -a cvt -d 'Print a CVT reduced blanking modeline (eg. 2560x1080@75, 60Hz by default), and the matching kernel video= option'
+complete -c rres -n __fish_use_subcommand -a set -d 'Set a mode on an output (eg. DP-1) until Enter is pressed'
+complete -c rres -n __fish_use_subcommand -a completions -d 'Print the completion script of bash, zsh or fish'
+complete -c rres -n __fish_use_subcommand -a dump -d 'Print (or write to file) the state of every card: connectors, encoders, CRTCs, modes and EDIDs, eg. for bug reports'
+complete -c rres -n __fish_use_subcommand -a doctor -d 'Check the common causes of detection failures (permissions, seat, driver, session), with hints to fix them'
+complete -c rres -n '__fish_seen_subcommand_from completions' -a 'bash zsh fish'
+complete -c rres -n '__fish_seen_subcommand_from wrapper' -a 'heroic bottles'
+complete -c rres -n '__fish_seen_subcommand_from wine-reg dxvk-conf' -F
+complete -c rres -s c -l card -x -a '(rres completions cards 2>/dev/null)' -d 'Specify a GPU (file existing in /dev/dri/, eg. card0)'
+complete -c rres -s m -l multi -d 'Read all monitors'
+complete -c rres -s v -l verbose -d 'Verbosity level'
+complete -c rres -s q -l quiet -d 'Lower verbosity level'
+complete -c rres -s h -l help -d 'Show this help message'
+complete -c rres -s g -l gamescope -x -a 'native ultra quality balanced performance' -d 'Gamescope mode'
+complete -c rres -l scale -x -d 'With gamescope, render at a percentage of the resolution (eg. 75%), upscaled with a plain linear filter instead of FSR'
+complete -c rres -l mode -x -d 'Use the closest supported mode instead of the current one, eg. 1920x1080 or 1920x1080@120'
+complete -c rres -l res -x -d 'Use a supported resolution instead of the current one, like --mode without a refresh rate (eg. 1920x1080)'
+complete -c rres -l best-refresh -d 'Use the highest refresh rate available at the resolution, and print it instead of the resolution'
+complete -c rres -l force-aspect -x -d 'Use the largest region of an aspect ratio within the resolution, eg. 16:9 gives 2560x1440 on a 3440x1440 display (for games breaking on ultrawide), also for gamescope'
+complete -c rres -l snap -d 'Use the closest standard resolution (720p to 2160p, 16:9 or 16:10) within the resolution, for games with fixed lists (eg. 2560x1440 on 3200x1800), also for gamescope'
+complete -c rres -l max -x -d 'Scale the resolution down to fit within bounds, keeping its aspect ratio (eg. 1920x1080 on a 4K TV), also for gamescope'
+complete -c rres -l aspect -d 'Print the nominal aspect ratio of the resolution (eg. 16:9 for 1366x768, 21:9 for 3440x1440) instead of the resolution'
+complete -c rres -l logical -d 'Print the resolution in logical pixels, divided by the scale the compositor (Wayland or GNOME) applies to the display'
+complete -c rres -l force-res -x -d 'Force a resolution (eg. 1920x1080), like RRES_FORCE_RES'
+complete -c rres -l strict -d 'Fail if the forced resolution isn\'t supported by the display'
+complete -c rres -l fallback-res -x -d 'Resolution used when no display can be detected, not even through Wayland or X11 (eg. in containers), like RRES_FALLBACK_RES'
+complete -c rres -l native -d 'Use the display\'s native (preferred) mode instead of the current one, eg. when the desktop runs at a lower resolution'
+complete -c rres -l via-dbus -d 'Ask the compositor over D-Bus (GNOME) instead of reading /dev/dri, eg. without access to the GPU'
+complete -c rres -l no-cache -d 'Probe the displays again instead of reusing the result of a run from the last 5 seconds'
+complete -c rres -l via-portal -d 'Ask the ScreenCast portal, which lets you pick the monitors'
+complete -c rres -l prefer-drm -d 'Read virtual GPUs (virtio-gpu, eg. in Crostini) through DRM instead of asking the compositor'
+complete -c rres -l modeline -d 'Print the mode in xorg.conf Modeline syntax'
+complete -c rres -l interlaced -d 'Also list and select interlaced and doublescan modes'
+complete -c rres -l raw-orientation -d 'Report the resolution of sideways panels (handhelds) as the panel scans out, eg. 800x1280 instead of 1280x800'
+complete -c rres -l output -x -a '(rres completions connectors 2>/dev/null)' -d 'Output (connector, eg. DP-1) for `rres set`'
+complete -c rres -l gpu-policy -x -a 'default prefer-dgpu prefer-igpu' -d 'Which GPU\'s displays come first: default (primary GPU, then card order), prefer-dgpu or prefer-igpu'
+complete -c rres -l list-cards -d 'List the GPUs, marking the primary (boot VGA) one'
+complete -c rres -l redact -d 'Mask the monitors\' serial numbers in the EDIDs of `rres dump`, to share it publicly (models and modes are kept)'
+complete -c rres -l debug-topology -d 'Print which connector uses which encoder and CRTC, and the unused ones (also from RRES_BACKEND=mock:<file>)'
+complete -c rres -l rpc -d 'Answer JSON-RPC 2.0 requests on stdin, one per line: get_displays, get_res, build_gamescope and get_schema (see the README)'
+complete -c rres -l schema -d 'Print the JSON schema of the --rpc results'
+complete -c rres -l env-file -r -F -d 'Also write the detected values (RRES_W, RRES_H, ..., and RRES_<index>_W, RRES_<index>_H..'
+complete -c rres -l metrics -x -d 'With --rpc or --supervise, serve Prometheus metrics on host:port or a Unix socket path'
+complete -c rres -l modes -d 'List every mode supported by each display, marking the current and preferred ones'
+complete -c rres -l render-gpu -d 'Print the GPU that will render, honoring DRI_PRIME and __NV_PRIME_RENDER_OFFLOAD'
+complete -c rres -l spawn -d 'Run wrapped commands as a child process and wait for them, instead of replacing rres'
+complete -c rres -l gamemode -d 'Run wrapped commands with gamemoderun'
+complete -c rres -l mangohud -d 'Show MangoHud in the game (through gamescope if used)'
+complete -c rres -l hdr -d 'With -g, enable HDR output in gamescope if the display supports it, tuned to the display\'s luminance'
+complete -c rres -l print-cmd -d 'Print the wrapped command as a shell-quoted string instead of running it'
+complete -c rres -l supervise -d 'With -g, restart gamescope with the new resolution when the display changes (eg. docking)'
+complete -c rres -l log-file -r -F -d 'Copy rres\' log to a file, with timestamps (eg. when Steam hides stderr)'
+complete -c rres -l color -x -d 'Color the log levels: auto (on a terminal, unless NO_COLOR is set), always or never'
+complete -c rres -l log-format -x -d 'Log records as text (default) or json, one object per line'
+complete -c rres -l log-child -r -F -d 'Copy the wrapped command\'s output to a file, with timestamps (implies --spawn)'
+complete -c rres -l chdir -x -a '(__fish_complete_directories)' -d 'Run the wrapped command in another directory'
+complete -c rres -l unset -x -d 'Remove a variable from the wrapped command\'s environment (eg. RRES_FORCE_RES)'
+complete -c rres -l deck -d 'Show Steam Deck detection (model, game mode, dock)'
+complete -c rres -l vulkan -d 'Map each Vulkan device to its DRM card and displays (requires vulkaninfo)'
+complete -c rres -l fast -d 'Only read the primary GPU\'s first display (no cache, EDID nor logging), for launch scripts'
+complete -c rres -l bench -x -d 'Probe the displays <runs> times with each backend and card, and print the min/avg/max timings'
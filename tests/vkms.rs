@@ -0,0 +1,154 @@
+// Copyright (c) 2022 Namkhai B.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! End-to-end detection against the kernel's virtual KMS device (vkms)
+//!
+//! Skipped unless `RRES_TEST_VKMS` is set: `1` expects vkms to be loaded already, `load`
+//! runs `modprobe vkms` first (as root). With its default settings, vkms has a single
+//! `Virtual-1` connector, connected, preferring 1024x768, and no CRTC is enabled until a
+//! client sets a mode.
+//!
+//! ```sh
+//! sudo RRES_TEST_VKMS=load cargo test --test vkms
+//! ```
+
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rres::ProbeOptions;
+
+/// Preferred mode of vkms' connector (`XRES_DEF`, `YRES_DEF`)
+const DEFAULT_SIZE: (u16, u16) = (1024, 768);
+
+/// Find the vkms card, `None` if the tests are disabled
+fn vkms_card() -> Option<String> {
+    match env::var("RRES_TEST_VKMS").as_deref() {
+        Ok("load") => {
+            let status = Command::new("modprobe").arg("vkms").status();
+            assert!(
+                status.is_ok_and(|s| s.success()),
+                "failed to load vkms (modprobe vkms)"
+            );
+        }
+        Ok(_) => {}
+        Err(_) => {
+            eprintln!("RRES_TEST_VKMS isn't set, skipping");
+            return None;
+        }
+    }
+
+    let cards = rres::get_cards(None).expect("failed to list the cards");
+    let card = cards
+        .iter()
+        .find(|card| is_vkms(card))
+        .expect("vkms isn't loaded");
+    Some(card.file_name()?.to_string_lossy().to_string())
+}
+
+/// Whether the card's device is vkms' (`/sys/devices/platform/vkms` or `/sys/devices/faux/vkms`)
+fn is_vkms(card: &Path) -> bool {
+    let Some(name) = card.file_name() else {
+        return false;
+    };
+    let device = PathBuf::from("/sys/class/drm").join(name).join("device");
+    fs::canonicalize(device).is_ok_and(|d| d.file_name().is_some_and(|n| n == "vkms"))
+}
+
+fn options(card: String) -> ProbeOptions {
+    ProbeOptions {
+        card: Some(card),
+        edid: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn topology() {
+    let Some(card) = vkms_card() else {
+        return;
+    };
+    let displays = rres::probe(&options(card.clone())).unwrap();
+
+    assert_eq!(displays.len(), 1, "{displays:#?}");
+    let display = &displays[0];
+    assert_eq!(display.card, Path::new("/dev/dri").join(&card));
+    assert_eq!(display.connector, "Virtual-1");
+    assert!(!display.is_internal());
+    // Virtual connectors have no EDID
+    assert_eq!(display.edid, None);
+}
+
+#[test]
+fn modes() {
+    let Some(card) = vkms_card() else {
+        return;
+    };
+    let display = rres::probe(&options(card)).unwrap().remove(0);
+
+    // Preferred mode first
+    assert_eq!(display.modes[0].size(), DEFAULT_SIZE);
+    assert!(display.modes.iter().any(|m| m.size() == (1920, 1080)));
+    assert!(display
+        .modes
+        .iter()
+        .all(|m| m.size().0 > 0 && m.size().1 > 0));
+    // No CRTC is enabled, the native mode is reported
+    assert_eq!(display.mode.size(), DEFAULT_SIZE);
+}
+
+#[test]
+fn resolution() {
+    let Some(card) = vkms_card() else {
+        return;
+    };
+    assert_eq!(
+        rres::get_res_card(Some(card.clone())).unwrap(),
+        DEFAULT_SIZE
+    );
+    assert_eq!(
+        rres::get_res_opts(&ProbeOptions {
+            mode: Some(rres::modes::ModeSpec {
+                size: (1920, 1080),
+                refresh: None,
+            }),
+            ..options(card)
+        })
+        .unwrap(),
+        (1920, 1080)
+    );
+}
+
+#[test]
+fn prober() {
+    let Some(card) = vkms_card() else {
+        return;
+    };
+    let mut prober = rres::Prober::new(options(card.clone())).unwrap();
+    let first = prober.probe().unwrap();
+    let second = prober.probe().unwrap();
+    let fresh = rres::probe(&options(card)).unwrap();
+
+    for displays in [&second, &fresh] {
+        assert_eq!(displays.len(), first.len());
+        assert_eq!(displays[0].connector, first[0].connector);
+        assert_eq!(displays[0].mode, first[0].mode);
+    }
+}